@@ -0,0 +1,92 @@
+//! `org.wally.Wallpaper1` on the session bus, so desktop extensions, widgets and
+//! scripts can control wally without going through `wallyctl`
+
+use crate::{
+    change_now, previous_wallpaper_internal, set_local_wallpaper, toggle_rotation,
+    CurrentWallpaper, WallyError,
+};
+use std::sync::OnceLock;
+use tracing::{info, warn};
+use zbus::object_server::SignalEmitter;
+
+pub const BUS_NAME: &str = "org.wally.Wallpaper1";
+pub const OBJECT_PATH: &str = "/org/wally/Wallpaper1";
+
+/// Kept for the lifetime of the app so the name stays owned and signals can be sent
+static CONNECTION: OnceLock<zbus::Connection> = OnceLock::new();
+
+struct Wallpaper {
+    app: tauri::AppHandle,
+}
+
+fn to_fdo(e: WallyError) -> zbus::fdo::Error {
+    zbus::fdo::Error::Failed(e.to_string())
+}
+
+#[zbus::interface(name = "org.wally.Wallpaper1")]
+impl Wallpaper {
+    /// Change to a new wallpaper
+    async fn next(&self) -> zbus::fdo::Result<()> {
+        change_now(&self.app, "dbus").await.map_err(to_fdo)
+    }
+
+    /// Go back to the previous wallpaper
+    async fn previous(&self) -> zbus::fdo::Result<()> {
+        previous_wallpaper_internal(&self.app).await.map_err(to_fdo)
+    }
+
+    /// Pause rotation, or resume it when paused
+    async fn pause(&self) {
+        toggle_rotation(&self.app);
+    }
+
+    /// Set a local image as the wallpaper
+    async fn set_from_file(&self, path: String) -> zbus::fdo::Result<()> {
+        set_local_wallpaper(&self.app, &path)
+            .await
+            .map(|_| ())
+            .map_err(to_fdo)
+    }
+
+    /// Sent with the local path of the new wallpaper after every change
+    #[zbus(signal)]
+    async fn changed(emitter: &SignalEmitter<'_>, path: &str) -> zbus::Result<()>;
+}
+
+/// Claim the bus name and serve the interface until the app exits
+pub async fn serve(app: tauri::AppHandle) {
+    let connection = async {
+        zbus::connection::Builder::session()?
+            .name(BUS_NAME)?
+            .serve_at(OBJECT_PATH, Wallpaper { app })?
+            .build()
+            .await
+    }
+    .await;
+
+    match connection {
+        Ok(connection) => {
+            info!(target: "wally::dbus", "Serving {} on the session bus", BUS_NAME);
+            let _ = CONNECTION.set(connection);
+        }
+        Err(e) => warn!(target: "wally::dbus", "Failed to register {}: {}", BUS_NAME, e),
+    }
+}
+
+/// Broadcast the `Changed` signal, if the interface is being served
+pub fn emit_changed(current: &CurrentWallpaper) {
+    let Some(connection) = CONNECTION.get() else {
+        return;
+    };
+    let path = current.local_path.clone().unwrap_or_default();
+    tauri::async_runtime::spawn(async move {
+        let result = async {
+            let emitter = SignalEmitter::new(connection, OBJECT_PATH)?;
+            Wallpaper::changed(&emitter, &path).await
+        }
+        .await;
+        if let Err(e) = result {
+            warn!(target: "wally::dbus", "Failed to emit Changed: {}", e);
+        }
+    });
+}
@@ -0,0 +1,192 @@
+//! Environment report users can attach to bug reports
+
+use crate::processing::parse_hex_color;
+use crate::system::find_in_path;
+use crate::WallpaperSettings;
+use crate::{get_config_dir, get_wallpaper_dir, is_gnome, is_kde, portable_dir, schedule};
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// External programs the wallpaper backends and system checks shell out to
+#[cfg(target_os = "linux")]
+const TOOLS: &[&str] = &[
+    "qdbus6",
+    "qdbus",
+    "plasma-apply-wallpaperimage",
+    "kreadconfig6",
+    "kreadconfig5",
+    "gsettings",
+    "gdbus",
+    "busctl",
+    "xprop",
+    "mpvpaper",
+    "swww",
+    "wal",
+    "realesrgan-ncnn-vulkan",
+];
+#[cfg(target_os = "macos")]
+const TOOLS: &[&str] = &[
+    "osascript",
+    "pmset",
+    "defaults",
+    "wal",
+    "realesrgan-ncnn-vulkan",
+];
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+const TOOLS: &[&str] = &["realesrgan-ncnn-vulkan"];
+
+#[derive(Debug, Serialize)]
+pub struct DiagnosticsReport {
+    pub app_version: &'static str,
+    /// Same value as `get_platform`, e.g. "linux-kde"
+    pub platform: String,
+    pub os: &'static str,
+    pub arch: &'static str,
+    pub desktop: DesktopInfo,
+    /// Whether data is kept next to the executable instead of the user's folders
+    pub portable: bool,
+    pub tools: Vec<ToolCheck>,
+    pub config_dir: DirCheck,
+    pub wallpaper_dir: DirCheck,
+    pub network: NetworkCheck,
+    /// Settings that are invalid or likely to cause trouble
+    pub settings_problems: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DesktopInfo {
+    pub xdg_current_desktop: Option<String>,
+    pub xdg_session_type: Option<String>,
+    pub kde: bool,
+    pub gnome: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ToolCheck {
+    pub name: &'static str,
+    /// Where the program was found on PATH
+    pub path: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DirCheck {
+    pub path: String,
+    pub writable: bool,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NetworkCheck {
+    pub reachable: bool,
+    pub status: Option<u16>,
+    pub latency_ms: Option<u64>,
+    pub error: Option<String>,
+}
+
+/// Write and delete a probe file to check the directory can be written
+fn check_dir(path: &Path) -> DirCheck {
+    let probe = path.join(".wally-write-test");
+    let result = fs::write(&probe, b"ok").and_then(|_| fs::remove_file(&probe));
+    DirCheck {
+        path: path.display().to_string(),
+        writable: result.is_ok(),
+        error: result.err().map(|e| e.to_string()),
+    }
+}
+
+async fn check_network(client: &reqwest::Client) -> NetworkCheck {
+    let start = Instant::now();
+    match client
+        .head("https://api.unsplash.com")
+        .timeout(Duration::from_secs(10))
+        .send()
+        .await
+    {
+        Ok(response) => NetworkCheck {
+            reachable: true,
+            status: Some(response.status().as_u16()),
+            latency_ms: Some(start.elapsed().as_millis() as u64),
+            error: None,
+        },
+        Err(e) => NetworkCheck {
+            reachable: false,
+            status: None,
+            latency_ms: None,
+            error: Some(e.to_string()),
+        },
+    }
+}
+
+fn check_settings(settings: &WallpaperSettings) -> Vec<String> {
+    let mut problems = Vec::new();
+    if settings.api_key.trim().is_empty() {
+        problems.push("No Unsplash API key configured".to_string());
+    }
+    if settings.interval_value == 0 {
+        problems.push("Change interval is 0".to_string());
+    }
+    if !matches!(
+        settings.interval_unit.as_str(),
+        "minutes" | "hours" | "days" | "weeks"
+    ) {
+        problems.push(format!(
+            "Unknown interval unit `{}`",
+            settings.interval_unit
+        ));
+    }
+    if !matches!(
+        settings.quality.as_str(),
+        "raw" | "full" | "regular" | "small"
+    ) {
+        problems.push(format!("Unknown download quality `{}`", settings.quality));
+    }
+    if !settings.proxy_url.is_empty() && reqwest::Proxy::all(&settings.proxy_url).is_err() {
+        problems.push(format!("Invalid proxy URL `{}`", settings.proxy_url));
+    }
+    if !settings.tint_color.is_empty() && parse_hex_color(&settings.tint_color).is_none() {
+        problems.push(format!(
+            "Tint color `{}` is not #rrggbb",
+            settings.tint_color
+        ));
+    }
+    if settings.battery_threshold > 100 {
+        problems.push("Battery threshold is above 100%".to_string());
+    }
+    if !settings.filename_template.contains("{id}") {
+        problems.push(
+            "Filename template has no {id}, so downloads may overwrite each other".to_string(),
+        );
+    }
+    problems.extend(schedule::validate(settings));
+    problems
+}
+
+/// Gather the report. Only the network check leaves the machine.
+pub async fn run(settings: &WallpaperSettings, client: &reqwest::Client) -> DiagnosticsReport {
+    DiagnosticsReport {
+        app_version: env!("CARGO_PKG_VERSION"),
+        platform: wally_core::platform::get_platform(),
+        os: std::env::consts::OS,
+        arch: std::env::consts::ARCH,
+        desktop: DesktopInfo {
+            xdg_current_desktop: std::env::var("XDG_CURRENT_DESKTOP").ok(),
+            xdg_session_type: std::env::var("XDG_SESSION_TYPE").ok(),
+            kde: is_kde(),
+            gnome: is_gnome(),
+        },
+        portable: portable_dir().is_some(),
+        tools: TOOLS
+            .iter()
+            .map(|name| ToolCheck {
+                name,
+                path: find_in_path(name).map(|path| path.display().to_string()),
+            })
+            .collect(),
+        config_dir: check_dir(&get_config_dir()),
+        wallpaper_dir: check_dir(&get_wallpaper_dir()),
+        network: check_network(client).await,
+        settings_problems: check_settings(settings),
+    }
+}
@@ -0,0 +1,226 @@
+//! Optional JSON control API on 127.0.0.1 for Stream Deck buttons, launcher scripts
+//! and home automation:
+//!
+//! - `GET /current` returns the current wallpaper
+//! - `GET /status` returns the daemon state, see `get_daemon_state`
+//! - `POST /next` changes to a new wallpaper and returns it
+//! - `POST /pause` pauses or resumes rotation and returns `{"paused": bool}`
+//!
+//! Every request must send `Authorization: Bearer <http_api_token>`.
+
+use crate::{
+    change_now, daemon_state, toggle_rotation, AppState, BackgroundTask, WallpaperSettings,
+    WallyError,
+};
+use rand::distributions::{Alphanumeric, DistString};
+use serde::Serialize;
+use std::sync::atomic::Ordering;
+use tauri::Manager;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+/// Requests are tiny, anything bigger than this is not a client we serve
+const MAX_REQUEST_SIZE: u64 = 8 * 1024;
+
+/// The running API server and the port it was started on
+pub struct HttpApi {
+    port: u16,
+    task: BackgroundTask,
+}
+
+impl HttpApi {
+    pub async fn shutdown(self) {
+        self.task.shutdown().await;
+    }
+}
+
+/// A random token suitable for `http_api_token`
+pub fn generate_token() -> String {
+    Alphanumeric.sample_string(&mut rand::thread_rng(), 32)
+}
+
+/// Start, stop or move the API so it matches the settings
+pub fn sync(app: &tauri::AppHandle, settings: &WallpaperSettings) {
+    let state = app.state::<AppState>();
+    let Ok(mut server) = state.http_api.lock() else {
+        return;
+    };
+    if settings.http_api_enabled && settings.http_api_token.is_empty() {
+        warn!(target: "wally::http", "HTTP API is enabled but has no token, not starting it");
+    }
+    let port = (settings.http_api_enabled && !settings.http_api_token.is_empty())
+        .then_some(settings.http_api_port);
+    let running = server
+        .as_ref()
+        .filter(|api| api.task.is_running())
+        .map(|api| api.port);
+    if running == port {
+        return;
+    }
+
+    let previous = server.take();
+    if let Some(previous) = &previous {
+        previous.task.stop();
+    }
+    let Some(port) = port else {
+        return;
+    };
+    let app = app.clone();
+    *server = Some(HttpApi {
+        port,
+        task: BackgroundTask::spawn(move |cancel| async move {
+            // The old listener has to release its port before a new one can bind it
+            if let Some(previous) = previous {
+                previous.shutdown().await;
+            }
+            serve(app, port, cancel).await;
+        }),
+    });
+}
+
+async fn serve(app: tauri::AppHandle, port: u16, cancel: CancellationToken) {
+    let listener = match TcpListener::bind(("127.0.0.1", port)).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            warn!(target: "wally::http", "Failed to listen on 127.0.0.1:{}: {}", port, e);
+            return;
+        }
+    };
+    info!(target: "wally::http", "HTTP API listening on 127.0.0.1:{}", port);
+
+    loop {
+        tokio::select! {
+            _ = cancel.cancelled() => break,
+            accepted = listener.accept() => match accepted {
+                Ok((stream, _)) => {
+                    tauri::async_runtime::spawn(serve_connection(app.clone(), stream));
+                }
+                Err(e) => warn!(target: "wally::http", "Failed to accept connection: {}", e),
+            },
+        }
+    }
+    info!(target: "wally::http", "HTTP API stopped");
+}
+
+struct Request {
+    method: String,
+    path: String,
+    token: Option<String>,
+}
+
+/// Read the request line and headers. The body, if any, is ignored.
+async fn read_request(stream: &mut TcpStream) -> Option<Request> {
+    let mut reader = BufReader::new(stream.take(MAX_REQUEST_SIZE));
+    let mut line = String::new();
+    reader.read_line(&mut line).await.ok()?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next()?.to_string();
+    let path = parts.next()?.to_string();
+
+    let mut token = None;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).await.ok()? == 0 {
+            return None;
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("authorization") {
+                token = value.trim().strip_prefix("Bearer ").map(str::to_string);
+            }
+        }
+    }
+
+    Some(Request {
+        method,
+        path,
+        token,
+    })
+}
+
+#[derive(Serialize)]
+struct ErrorBody<'a> {
+    code: &'a str,
+    message: &'a str,
+}
+
+fn error_body(code: &str, message: &str) -> String {
+    serde_json::to_string(&ErrorBody { code, message }).unwrap_or_default()
+}
+
+/// Compare without returning early, so response times don't leak the token
+fn token_matches(given: &str, expected: &str) -> bool {
+    given.len() == expected.len()
+        && given
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+async fn handle(app: &tauri::AppHandle, request: &Request) -> Result<(u16, String), WallyError> {
+    let state = app.state::<AppState>();
+    let expected = state.settings.lock()?.http_api_token.clone();
+    if expected.is_empty()
+        || !request
+            .token
+            .as_deref()
+            .is_some_and(|token| token_matches(token, &expected))
+    {
+        return Ok((
+            401,
+            error_body("unauthorized", "Missing or invalid bearer token"),
+        ));
+    }
+
+    let path = request.path.split('?').next().unwrap_or_default();
+    match (request.method.as_str(), path) {
+        ("GET", "/current") => {}
+        ("GET", "/status") => return Ok((200, serde_json::to_string(&daemon_state(app))?)),
+        ("POST", "/next") => change_now(app, "http").await?,
+        ("POST", "/pause") => {
+            toggle_rotation(app);
+            let paused = state.rotation_paused.load(Ordering::SeqCst);
+            return Ok((200, serde_json::json!({ "paused": paused }).to_string()));
+        }
+        (_, "/current" | "/status" | "/next" | "/pause") => {
+            return Ok((405, error_body("method_not_allowed", "Method not allowed")));
+        }
+        _ => return Ok((404, error_body("not_found", "No such endpoint"))),
+    }
+    let current = state.current_wallpaper.lock()?.clone();
+    Ok((200, serde_json::to_string(&current)?))
+}
+
+async fn serve_connection(app: tauri::AppHandle, mut stream: TcpStream) {
+    let Some(request) = read_request(&mut stream).await else {
+        return;
+    };
+    info!(target: "wally::http", "{} {}", request.method, request.path);
+
+    let (status, body) = match handle(&app, &request).await {
+        Ok(response) => response,
+        Err(e) => (500, serde_json::to_string(&e).unwrap_or_default()),
+    };
+    let reason = match status {
+        200 => "OK",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        _ => "Internal Server Error",
+    };
+    let response = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        reason,
+        body.len(),
+        body
+    );
+    let _ = stream.write_all(response.as_bytes()).await;
+    let _ = stream.shutdown().await;
+}
@@ -0,0 +1,144 @@
+//! Server side of the `wallyctl` control socket, see `wally_core::ipc` for the
+//! protocol
+
+use crate::{
+    change_now, previous_wallpaper_internal, set_local_wallpaper, toggle_rotation, AppState,
+    WallyError,
+};
+use std::sync::atomic::Ordering;
+use tauri::Manager;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tracing::{info, warn};
+use wally_core::ipc::{Request, Response, Status};
+
+fn status(app: &tauri::AppHandle) -> Result<Status, WallyError> {
+    let state = app.state::<AppState>();
+    let current = state.current_wallpaper.lock()?.clone();
+    let auto_change = state.settings.lock()?.auto_change;
+    let next_change_at = *state.next_change_at.lock()?;
+    Ok(Status {
+        local_path: current.local_path,
+        author: current.image.map(|image| image.user.name),
+        auto_change,
+        paused: state.rotation_paused.load(Ordering::SeqCst),
+        next_change_at: next_change_at.map(|next| next.to_rfc3339()),
+    })
+}
+
+async fn handle(app: &tauri::AppHandle, request: Request) -> Result<Option<Status>, WallyError> {
+    match request {
+        Request::Next => change_now(app, "wallyctl").await?,
+        Request::Previous => {
+            previous_wallpaper_internal(app).await?;
+        }
+        Request::Pause => toggle_rotation(app),
+        Request::Status => {}
+        Request::Set { path } => {
+            set_local_wallpaper(app, &path).await?;
+        }
+    }
+    status(app).map(Some)
+}
+
+async fn serve_connection<S>(app: tauri::AppHandle, stream: S)
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut stream = BufReader::new(stream);
+    let mut line = String::new();
+    if stream.read_line(&mut line).await.is_err() {
+        return;
+    }
+
+    let result = match serde_json::from_str::<Request>(&line) {
+        Ok(request) => {
+            info!(target: "wally::ipc", "Received {:?}", request);
+            handle(&app, request).await
+        }
+        Err(e) => Err(WallyError::Other(format!("Invalid request: {}", e))),
+    };
+    let response = match result {
+        Ok(status) => Response {
+            ok: true,
+            status,
+            ..Default::default()
+        },
+        Err(e) => Response {
+            ok: false,
+            code: Some(e.code().to_string()),
+            message: Some(e.to_string()),
+            status: None,
+        },
+    };
+
+    let Ok(mut reply) = serde_json::to_string(&response) else {
+        return;
+    };
+    reply.push('\n');
+    let _ = stream.get_mut().write_all(reply.as_bytes()).await;
+    let _ = stream.get_mut().shutdown().await;
+}
+
+/// Accept control connections until the app exits
+#[cfg(unix)]
+pub async fn serve(app: tauri::AppHandle) {
+    use std::os::unix::fs::PermissionsExt;
+
+    let path = wally_core::ipc::socket_path();
+    // Left over from a previous run that didn't shut down cleanly
+    let _ = std::fs::remove_file(&path);
+    let listener = match tokio::net::UnixListener::bind(&path) {
+        Ok(listener) => listener,
+        Err(e) => {
+            warn!(target: "wally::ipc", "Failed to listen on {}: {}", path.display(), e);
+            return;
+        }
+    };
+    let _ = std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600));
+    info!(target: "wally::ipc", "Listening on {}", path.display());
+
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                tauri::async_runtime::spawn(serve_connection(app.clone(), stream));
+            }
+            Err(e) => warn!(target: "wally::ipc", "Failed to accept connection: {}", e),
+        }
+    }
+}
+
+/// Accept control connections until the app exits
+#[cfg(windows)]
+pub async fn serve(app: tauri::AppHandle) {
+    use tokio::net::windows::named_pipe::ServerOptions;
+
+    let mut server = match ServerOptions::new()
+        .first_pipe_instance(true)
+        .create(wally_core::ipc::PIPE_NAME)
+    {
+        Ok(server) => server,
+        Err(e) => {
+            warn!(target: "wally::ipc", "Failed to create {}: {}", wally_core::ipc::PIPE_NAME, e);
+            return;
+        }
+    };
+    info!(target: "wally::ipc", "Listening on {}", wally_core::ipc::PIPE_NAME);
+
+    loop {
+        if let Err(e) = server.connect().await {
+            warn!(target: "wally::ipc", "Failed to accept connection: {}", e);
+            continue;
+        }
+        // Create the next instance before handing this one off so clients
+        // never find the pipe missing
+        let connected = server;
+        server = match ServerOptions::new().create(wally_core::ipc::PIPE_NAME) {
+            Ok(server) => server,
+            Err(e) => {
+                warn!(target: "wally::ipc", "Failed to create {}: {}", wally_core::ipc::PIPE_NAME, e);
+                return;
+            }
+        };
+        tauri::async_runtime::spawn(serve_connection(app.clone(), connected));
+    }
+}
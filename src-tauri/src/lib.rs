@@ -1,330 +1,1098 @@
+#[cfg(target_os = "linux")]
+mod dbus;
+mod diagnostics;
+mod http_api;
+mod ipc;
+mod live_wallpaper;
+mod logging;
+mod session;
+mod share;
+mod updater;
+
 use serde::{Deserialize, Serialize};
 use std::fs;
-use std::io::Write;
-use std::path::PathBuf;
-#[cfg(any(target_os = "linux", target_os = "macos"))]
-use std::process::Command;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
-use tauri::menu::{Menu, MenuItem};
+use tauri::menu::{CheckMenuItem, IsMenuItem, Menu, MenuItem, Submenu};
 use tauri::tray::{MouseButton, MouseButtonState, TrayIconBuilder, TrayIconEvent};
 use tauri::{Emitter, Manager, State};
+use tauri_plugin_autostart::ManagerExt as _;
+use tauri_plugin_clipboard_manager::ClipboardExt;
+use tauri_plugin_deep_link::DeepLinkExt;
+use tauri_plugin_global_shortcut::{GlobalShortcutExt, ShortcutState};
+use tauri_plugin_notification::NotificationExt;
 use tokio::time::Duration;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+use wally_core::download::*;
+use wally_core::paths::*;
+use wally_core::platform::*;
+use wally_core::rotation::{self, extract_wallpaper_palette, wallpaper_phash, PreparedWallpaper};
+use wally_core::schedule::schedule_wait;
+use wally_core::settings::Playlist;
+use wally_core::storage::*;
+use wally_core::unsplash::*;
+use wally_core::{
+    api_cache, gallery, integrations, metrics, processing, schedule, settings_store, slideshow,
+    system, weather,
+};
+use wally_core::{
+    CurrentWallpaper, ScreenResolution, UnsplashImage, WallpaperSettings, WallyError,
+};
+
+pub struct AppState {
+    pub settings: Mutex<WallpaperSettings>,
+    pub current_wallpaper: Mutex<CurrentWallpaper>,
+    /// The auto-change daemon, if it has been started
+    pub daemon: Mutex<Option<BackgroundTask>>,
+    /// Temporarily holds the daemon's timer without touching the `auto_change` setting
+    pub rotation_paused: Arc<AtomicBool>,
+    /// When the daemon will next change the wallpaper, if it is running
+    pub next_change_at: Mutex<Option<chrono::DateTime<chrono::Utc>>>,
+    /// Cached responses for metadata requests to the provider API
+    pub api_cache: api_cache::ApiCache,
+    /// The next wallpaper, downloaded ahead of the scheduled change
+    pub prefetched: Mutex<Option<PreparedWallpaper>>,
+    /// Location looked up from the IP address for the "sun" schedule
+    pub location: Mutex<Option<schedule::Location>>,
+    /// Publishes saved settings to the running daemon
+    pub settings_watch: tokio::sync::watch::Sender<WallpaperSettings>,
+    /// The macOS space / Windows virtual desktop watcher, if it has been started
+    pub space_watcher: Mutex<Option<BackgroundTask>>,
+    /// The local HTTP control API, if it is enabled
+    pub http_api: Mutex<Option<http_api::HttpApi>>,
+    /// A wallpaper on trial from `preview_wallpaper`, until it is kept or reverted
+    pub preview: Mutex<Option<PendingPreview>>,
+    /// Set while `pin_wallpaper` holds the current wallpaper in place
+    pub wallpaper_pin: Mutex<Option<WallpaperPin>>,
+    /// Held for the duration of a wallpaper change, so changes from the tray, the
+    /// daemon and the UI run one after another instead of racing on the same files
+    pub change_lock: tokio::sync::Mutex<()>,
+    /// Why the most recent change failed, cleared by the next successful one
+    pub last_error: Mutex<Option<ChangeFailed>>,
+    /// The photo shown by the full-screen preview window, while it is open
+    pub fullscreen_preview: Mutex<Option<FullscreenPreview>>,
+    /// The video or animated image playing as the wallpaper, until a still one replaces it
+    pub live_wallpaper: Mutex<Option<live_wallpaper::LiveWallpaper>>,
+    /// Newer version found by the last update check, installable from the tray
+    pub update: Mutex<Option<tauri_plugin_updater::Update>>,
+    /// Cancels the download of the change in progress, replaced by every change
+    pub download_cancel: Mutex<CancellationToken>,
+    /// Cancels explicit downloads and prefetches, only by `cancel_download` and quitting
+    pub user_download_cancel: Mutex<CancellationToken>,
+    pub http_client: Mutex<reqwest::Client>,
+}
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct WallpaperSettings {
-    pub api_key: String,
-    pub collection_id: String,
-    pub interval_value: u32,
-    pub interval_unit: String,
-    pub auto_change: bool,
+/// A spawned background task and the token that stops it
+pub struct BackgroundTask {
+    cancel: CancellationToken,
+    handle: tauri::async_runtime::JoinHandle<()>,
 }
 
-impl Default for WallpaperSettings {
-    fn default() -> Self {
-        Self {
-            api_key: String::new(),
-            collection_id: "880012".to_string(),
-            interval_value: 3,
-            interval_unit: "hours".to_string(),
-            auto_change: false,
-        }
+impl BackgroundTask {
+    fn spawn<F, Fut>(task: F) -> Self
+    where
+        F: FnOnce(CancellationToken) -> Fut,
+        Fut: std::future::Future<Output = ()> + Send + 'static,
+    {
+        let cancel = CancellationToken::new();
+        let handle = tauri::async_runtime::spawn(task(cancel.clone()));
+        Self { cancel, handle }
+    }
+
+    fn is_running(&self) -> bool {
+        !self.cancel.is_cancelled() && !self.handle.inner().is_finished()
+    }
+
+    fn stop(&self) {
+        self.cancel.cancel();
+    }
+
+    /// Stop the task and wait for it to finish what it was doing
+    async fn shutdown(self) {
+        self.cancel.cancel();
+        let _ = self.handle.await;
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct UnsplashUrls {
-    pub raw: String,
-    pub full: String,
-    pub regular: String,
-    pub small: String,
-    pub thumb: String,
+/// A previewed wallpaper and the timer that reverts it
+pub struct PendingPreview {
+    local_path: Option<String>,
+    revert: BackgroundTask,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct UnsplashUser {
-    pub name: String,
-    pub username: String,
+/// The current wallpaper held in place, which pauses rotation until it is unpinned
+pub struct WallpaperPin {
+    /// When rotation resumes by itself, if ever
+    until: Option<chrono::DateTime<chrono::Utc>>,
+    /// Unpins at `until`
+    expiry: Option<BackgroundTask>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct UnsplashLinks {
-    pub html: String,
-    pub download: String,
-    pub download_location: String,
+impl AppState {
+    /// The shared, pooled HTTP client
+    fn http_client(&self) -> Result<reqwest::Client, String> {
+        Ok(self.http_client.lock().map_err(|e| e.to_string())?.clone())
+    }
+
+    /// Start a new wallpaper download, cancelling any download still in flight
+    fn begin_download(&self) -> Result<CancellationToken, String> {
+        let mut current = self.download_cancel.lock().map_err(|e| e.to_string())?;
+        current.cancel();
+        *current = CancellationToken::new();
+        Ok(current.clone())
+    }
+
+    /// Token for a download that should only stop when the user cancels explicitly
+    fn download_token(&self) -> Result<CancellationToken, String> {
+        let current = self
+            .user_download_cancel
+            .lock()
+            .map_err(|e| e.to_string())?;
+        Ok(current.child_token())
+    }
+
+    /// Cancel every download in flight, including explicit ones and prefetches
+    fn cancel_downloads(&self) -> Result<(), String> {
+        for token in [&self.download_cancel, &self.user_download_cancel] {
+            let mut current = token.lock().map_err(|e| e.to_string())?;
+            current.cancel();
+            *current = CancellationToken::new();
+        }
+        Ok(())
+    }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct UnsplashImage {
-    pub id: String,
-    pub description: Option<String>,
-    pub alt_description: Option<String>,
-    pub urls: UnsplashUrls,
-    pub user: UnsplashUser,
-    pub links: UnsplashLinks,
+/// The wallpapers on disk with thumbnails and photo details, newest first
+#[tauri::command]
+async fn list_cached_wallpapers() -> Result<Vec<gallery::CachedWallpaper>, WallyError> {
+    tauri::async_runtime::spawn_blocking(gallery::list_cached_wallpapers)
+        .await
+        .map_err(|e| WallyError::Other(e.to_string()))?
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
-pub struct CurrentWallpaper {
-    pub image: Option<UnsplashImage>,
-    pub local_path: Option<String>,
-    pub set_at: Option<String>,
+#[tauri::command]
+fn get_pinned_wallpapers() -> Vec<String> {
+    load_pins()
 }
 
-pub struct AppState {
-    pub settings: Mutex<WallpaperSettings>,
-    pub current_wallpaper: Mutex<CurrentWallpaper>,
-    pub daemon_running: Arc<AtomicBool>,
-    pub space_watcher_running: Arc<AtomicBool>,
+/// Pin or unpin a downloaded wallpaper so cleanup keeps or may delete it
+#[tauri::command]
+fn set_wallpaper_pinned(local_path: String, pinned: bool) -> Result<(), WallyError> {
+    let mut pins = load_pins();
+    pins.retain(|pin| *pin != local_path);
+    if pinned {
+        pins.push(local_path);
+    }
+    Ok(save_pins(&pins)?)
 }
 
-fn get_config_dir() -> PathBuf {
-    let config_dir = dirs::config_dir()
-        .unwrap_or_else(|| PathBuf::from("."))
-        .join("unsplash-wally");
-    fs::create_dir_all(&config_dir).ok();
-    config_dir
+#[tauri::command]
+fn get_settings(state: State<AppState>) -> Result<WallpaperSettings, WallyError> {
+    let settings = state.settings.lock()?;
+    Ok(settings.clone())
 }
 
-fn get_wallpaper_dir() -> PathBuf {
-    let wallpaper_dir = dirs::picture_dir()
-        .unwrap_or_else(|| dirs::home_dir().unwrap_or_else(|| PathBuf::from(".")))
-        .join("unsplash_wallpapers");
-    fs::create_dir_all(&wallpaper_dir).ok();
-    wallpaper_dir
+#[tauri::command]
+fn save_settings(
+    settings: WallpaperSettings,
+    app: tauri::AppHandle,
+    state: State<AppState>,
+) -> Result<(), WallyError> {
+    save_and_apply_settings(&app, &state, settings)
 }
 
-fn load_settings() -> WallpaperSettings {
-    let config_path = get_config_dir().join("settings.json");
-    if let Ok(content) = fs::read_to_string(&config_path) {
-        serde_json::from_str(&content).unwrap_or_default()
-    } else {
-        WallpaperSettings::default()
-    }
+/// Save settings and make them take effect. Settings that can't be applied, such as
+/// an invalid proxy, are not saved, or every later start would quietly ignore them.
+fn save_and_apply_settings(
+    app: &tauri::AppHandle,
+    state: &AppState,
+    settings: WallpaperSettings,
+) -> Result<(), WallyError> {
+    let client = build_http_client(&settings)?;
+    settings_store::save(&get_settings_path(), &settings)?;
+    apply_settings_with_client(app, state, settings, client)
 }
 
-fn load_current_wallpaper() -> CurrentWallpaper {
-    let config_path = get_config_dir().join("current_wallpaper.json");
-    if let Ok(content) = fs::read_to_string(&config_path) {
-        serde_json::from_str(&content).unwrap_or_default()
-    } else {
-        CurrentWallpaper::default()
+/// Make new settings take effect in the running app
+fn apply_settings(
+    app: &tauri::AppHandle,
+    state: &AppState,
+    settings: WallpaperSettings,
+) -> Result<(), WallyError> {
+    let client = build_http_client(&settings)?;
+    apply_settings_with_client(app, state, settings, client)
+}
+
+fn apply_settings_with_client(
+    app: &tauri::AppHandle,
+    state: &AppState,
+    settings: WallpaperSettings,
+    client: reqwest::Client,
+) -> Result<(), WallyError> {
+    *state.http_client.lock()? = client;
+    // Cached responses may belong to a different API key
+    state.api_cache.clear();
+
+    // Wake the daemon so interval changes apply immediately
+    state.settings_watch.send_replace(settings.clone());
+
+    register_shortcuts(app, &settings);
+    sync_autostart(app, &settings);
+    #[cfg(target_os = "macos")]
+    {
+        apply_dock_visibility(app, &settings);
+        // Restarting the Dock is disruptive, so it only happens when this is turned on
+        let was_on = state.settings.lock()?.apply_to_all_spaces;
+        if settings.apply_to_all_spaces && !was_on {
+            if let Some(path) = current_wallpaper(app).local_path {
+                tauri::async_runtime::spawn_blocking(move || {
+                    if let Err(e) = apply_to_all_spaces_now(&path) {
+                        warn!("Failed to apply wallpaper to all Spaces: {}", e);
+                    }
+                });
+            }
+        }
     }
+
+    http_api::sync(app, &settings);
+    wally_core::crash::set_enabled(settings.crash_reports);
+
+    *state.settings.lock()? = settings;
+    Ok(())
 }
 
+/// Version of the export format written by `export_config`
+const CONFIG_ARCHIVE_VERSION: u32 = 1;
+
+/// Everything needed to set wally up the same way on another machine
+#[derive(Debug, Serialize, Deserialize)]
+struct ConfigArchive {
+    version: u32,
+    app_version: String,
+    exported_at: String,
+    /// Includes the schedule, filters and API key
+    settings: serde_json::Value,
+    /// Pinned wallpapers, only restored where the file exists on the new machine
+    #[serde(default)]
+    pins: Vec<String>,
+}
+
+/// Write settings and pins to a single JSON file
 #[tauri::command]
-fn get_settings(state: State<AppState>) -> Result<WallpaperSettings, String> {
-    let settings = state.settings.lock().map_err(|e| e.to_string())?;
-    Ok(settings.clone())
+fn export_config(path: String, state: State<AppState>) -> Result<(), WallyError> {
+    let settings = state.settings.lock()?.clone();
+    let archive = ConfigArchive {
+        version: CONFIG_ARCHIVE_VERSION,
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        exported_at: chrono::Utc::now().to_rfc3339(),
+        settings: serde_json::to_value(settings)?,
+        pins: load_pins(),
+    };
+    let content = serde_json::to_string_pretty(&archive)?;
+    settings_store::write_atomic(Path::new(&path), content.as_bytes())?;
+    info!(target: "wally::settings", "Exported configuration to {}", path);
+    Ok(())
 }
 
+/// Replace settings and pins with the contents of a file written by `export_config`
 #[tauri::command]
-fn save_settings(settings: WallpaperSettings, state: State<AppState>) -> Result<(), String> {
-    let config_path = get_config_dir().join("settings.json");
-    let content = serde_json::to_string_pretty(&settings).map_err(|e| e.to_string())?;
-    fs::write(&config_path, content).map_err(|e| e.to_string())?;
+fn import_config(
+    path: String,
+    app: tauri::AppHandle,
+    state: State<AppState>,
+) -> Result<WallpaperSettings, WallyError> {
+    let content = fs::read_to_string(&path)?;
+    let archive: ConfigArchive = serde_json::from_str(&content)
+        .map_err(|e| format!("Not a wally configuration file: {}", e))?;
+    if archive.version > CONFIG_ARCHIVE_VERSION {
+        return Err(format!(
+            "Configuration was exported by a newer version of wally ({})",
+            archive.app_version
+        )
+        .into());
+    }
+    let settings = settings_store::from_value(archive.settings)?;
 
-    let mut state_settings = state.settings.lock().map_err(|e| e.to_string())?;
-    *state_settings = settings;
-    Ok(())
+    save_and_apply_settings(&app, &state, settings.clone())?;
+
+    let pins: Vec<String> = archive
+        .pins
+        .into_iter()
+        .filter(|pin| Path::new(pin).exists())
+        .collect();
+    save_pins(&pins)?;
+
+    info!(target: "wally::settings", "Imported configuration from {}", path);
+    let _ = app.emit("settings-changed", &settings);
+    Ok(settings)
+}
+
+fn settings_modified_time() -> Option<std::time::SystemTime> {
+    fs::metadata(get_settings_path())
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// Pick up edits made to settings.json outside the app, e.g. by a dotfile manager
+/// or sync tool
+async fn settings_file_watcher(app: tauri::AppHandle) {
+    let mut last_modified = settings_modified_time();
+
+    loop {
+        tokio::time::sleep(Duration::from_secs(2)).await;
+
+        let modified = settings_modified_time();
+        if modified == last_modified {
+            continue;
+        }
+        last_modified = modified;
+
+        // Unlike at startup, keep the current settings if the file doesn't parse,
+        // it may be halfway through being written
+        let settings = match settings_store::read(&get_settings_path()) {
+            Ok(settings) => settings,
+            Err(e) => {
+                warn!(target: "wally::settings", "Ignoring unreadable settings.json: {}", e);
+                continue;
+            }
+        };
+        let state = app.state::<AppState>();
+        // Our own saves also touch the file
+        let unchanged = state.settings.lock().is_ok_and(|current| {
+            serde_json::to_value(&*current).ok() == serde_json::to_value(&settings).ok()
+        });
+        if unchanged {
+            continue;
+        }
+
+        info!(target: "wally::settings", "settings.json changed on disk, reloading");
+        if let Err(e) = apply_settings(&app, &state, settings.clone()) {
+            warn!(target: "wally::settings", "Failed to apply reloaded settings: {}", e);
+            continue;
+        }
+        let _ = app.emit("settings-changed", &settings);
+    }
 }
 
 #[tauri::command]
-fn get_current_wallpaper(state: State<AppState>) -> Result<CurrentWallpaper, String> {
-    let current = state.current_wallpaper.lock().map_err(|e| e.to_string())?;
+fn get_current_wallpaper(state: State<AppState>) -> Result<CurrentWallpaper, WallyError> {
+    let current = state.current_wallpaper.lock()?;
     Ok(current.clone())
 }
 
 #[tauri::command]
-fn save_current_wallpaper(
+async fn save_current_wallpaper(
     image: UnsplashImage,
     local_path: String,
-    state: State<AppState>,
-) -> Result<(), String> {
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+) -> Result<(), WallyError> {
+    let palette = extract_wallpaper_palette(&local_path).await;
+    let phash = wallpaper_phash(PathBuf::from(&local_path)).await;
     let current = CurrentWallpaper {
         image: Some(image),
         local_path: Some(local_path),
         set_at: Some(chrono::Utc::now().to_rfc3339()),
+        palette,
+        phash: phash.map(|hash| format!("{:016x}", hash)),
     };
 
-    let config_path = get_config_dir().join("current_wallpaper.json");
-    let content = serde_json::to_string_pretty(&current).map_err(|e| e.to_string())?;
-    fs::write(&config_path, content).map_err(|e| e.to_string())?;
+    write_current_wallpaper(&current)?;
+
+    let settings = state.settings.lock()?.clone();
+    spawn_post_change_integrations(settings, current.clone(), state.http_client()?);
 
-    let mut state_current = state.current_wallpaper.lock().map_err(|e| e.to_string())?;
-    *state_current = current;
+    wallpaper_changed(&app, &current);
     Ok(())
 }
 
+/// Make `current` the current wallpaper and tell the UI, the tray and desktop
+/// listeners about it
+fn wallpaper_changed(app: &tauri::AppHandle, current: &CurrentWallpaper) {
+    // A still wallpaper replaces a live one
+    live_wallpaper::stop(app);
+    if let Ok(mut state_current) = app.state::<AppState>().current_wallpaper.lock() {
+        *state_current = current.clone();
+    }
+    let _ = app.emit("wallpaper-changed", ());
+    let _ = app.emit("palette-changed", &current.palette);
+    refresh_tray_menu(app);
+    #[cfg(target_os = "linux")]
+    dbus::emit_changed(current);
+}
+
+/// The wallpaper Wally last set, kept in sync with `current_wallpaper.json` by
+/// `wallpaper_changed` so readers don't go back to disk
+fn current_wallpaper(app: &tauri::AppHandle) -> CurrentWallpaper {
+    app.state::<AppState>()
+        .current_wallpaper
+        .lock()
+        .map(|current| current.clone())
+        .unwrap_or_default()
+}
+
+/// Run the post-change integrations in the background so they never delay a change
+fn spawn_post_change_integrations(
+    settings: WallpaperSettings,
+    current: CurrentWallpaper,
+    client: reqwest::Client,
+) {
+    metrics::record_change(&settings);
+    if settings.usage_metrics {
+        let settings = settings.clone();
+        let client = client.clone();
+        tauri::async_runtime::spawn(async move {
+            metrics::submit_due(&client, &settings).await;
+        });
+    }
+
+    if !settings.webhook_url.trim().is_empty() {
+        let url = settings.webhook_url.trim().to_string();
+        let current = current.clone();
+        tauri::async_runtime::spawn(async move {
+            if let Err(e) = integrations::send_webhook(&client, &url, &current).await {
+                warn!("Webhook notification failed: {}", e);
+            }
+        });
+    }
+
+    if !settings.pywal_integration && !settings.sync_accent_color && settings.hooks.is_empty() {
+        return;
+    }
+    tauri::async_runtime::spawn_blocking(move || {
+        integrations::run_post_change_integrations(&settings, &current);
+    });
+}
+
 #[tauri::command]
-async fn fetch_random_image(state: State<'_, AppState>) -> Result<UnsplashImage, String> {
-    let settings = state.settings.lock().map_err(|e| e.to_string())?.clone();
+async fn fetch_random_image(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+) -> Result<UnsplashImage, WallyError> {
+    let settings = state.settings.lock()?.clone();
 
     if settings.api_key.is_empty() {
-        return Err("API key not configured".to_string());
+        return Err(WallyError::ApiKeyMissing);
+    }
+
+    let location = resolve_location(&app, &settings).await;
+    let client = state.http_client()?;
+    weather::refresh(&client, &settings).await;
+    let params = random_photo_params(&settings, location);
+    let request = client
+        .get("https://api.unsplash.com/photos/random")
+        .query(&params)
+        .header("Authorization", format!("Client-ID {}", settings.api_key));
+    let response = send_with_retry(request, &settings).await?;
+
+    if !response.status().is_success() {
+        return Err(api_error(response, &random_photo_resource(&params)).await);
     }
 
-    let mut url = "https://api.unsplash.com/photos/random?orientation=landscape".to_string();
-    if !settings.collection_id.is_empty() {
-        url.push_str(&format!("&collections={}", settings.collection_id));
+    let image: UnsplashImage = response.json().await?;
+    Ok(image)
+}
+
+/// How long photo details are served from the cache before being revalidated
+const PHOTO_CACHE_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Full details of a single photo
+#[tauri::command]
+async fn get_photo(
+    photo_id: String,
+    state: State<'_, AppState>,
+) -> Result<UnsplashImage, WallyError> {
+    let settings = state.settings.lock()?.clone();
+
+    if settings.api_key.is_empty() {
+        return Err(WallyError::ApiKeyMissing);
     }
 
-    let client = reqwest::Client::new();
-    let response = client
+    let photo_id = photo_id.trim();
+    let url = format!("https://api.unsplash.com/photos/{}", photo_id);
+    let request = state
+        .http_client()?
         .get(&url)
-        .header("Authorization", format!("Client-ID {}", settings.api_key))
-        .send()
+        .header("Authorization", format!("Client-ID {}", settings.api_key));
+    let resource = format!("Photo {}", photo_id);
+    state
+        .api_cache
+        .get_json(&url, &resource, request, PHOTO_CACHE_TTL, &settings)
         .await
-        .map_err(|e| e.to_string())?;
+}
 
-    if !response.status().is_success() {
-        let status = response.status();
-        let body = response.text().await.unwrap_or_default();
-        return Err(format!("API error: {} - {}", status, body));
+/// How long collection details and listings are served from the cache
+const COLLECTION_CACHE_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// Photos listed per page when browsing a collection
+const COLLECTION_PAGE_SIZE: u32 = 30;
+
+/// GET an Unsplash API endpoint through the response cache
+async fn get_unsplash_json<T: serde::de::DeserializeOwned>(
+    state: &AppState,
+    url: &str,
+    query: &[(&str, String)],
+    resource: &str,
+) -> Result<T, WallyError> {
+    let settings = state.settings.lock()?.clone();
+    if settings.api_key.is_empty() {
+        return Err(WallyError::ApiKeyMissing);
     }
 
-    let image: UnsplashImage = response.json().await.map_err(|e| e.to_string())?;
-    Ok(image)
+    let url = reqwest::Url::parse_with_params(url, query).map_err(|e| e.to_string())?;
+    let request = state
+        .http_client()?
+        .get(url.clone())
+        .header("Authorization", format!("Client-ID {}", settings.api_key));
+    state
+        .api_cache
+        .get_json(
+            url.as_str(),
+            resource,
+            request,
+            COLLECTION_CACHE_TTL,
+            &settings,
+        )
+        .await
 }
 
+/// Title, owner and size of a collection, e.g. to validate a pasted ID
 #[tauri::command]
-async fn set_wallpaper(image_url: String, image_id: String) -> Result<String, String> {
-    let wallpaper_dir = get_wallpaper_dir();
-    let filename = format!("wallpaper_{}.jpg", image_id);
-    let file_path = wallpaper_dir.join(&filename);
+async fn get_collection_info(
+    id: String,
+    state: State<'_, AppState>,
+) -> Result<UnsplashCollection, WallyError> {
+    let id = id.trim();
+    let url = format!("https://api.unsplash.com/collections/{}", id);
+    get_unsplash_json(&state, &url, &[], &format!("Collection {}", id)).await
+}
 
-    // Download the image
-    let client = reqwest::Client::new();
-    let response = client
-        .get(&image_url)
-        .send()
-        .await
-        .map_err(|e| e.to_string())?;
+/// One page of a collection's photos, starting at page 1
+#[tauri::command]
+async fn list_collection_photos(
+    id: String,
+    page: u32,
+    state: State<'_, AppState>,
+) -> Result<Vec<UnsplashImage>, WallyError> {
+    let id = id.trim();
+    let url = format!("https://api.unsplash.com/collections/{}/photos", id);
+    let query = [
+        ("page", page.max(1).to_string()),
+        ("per_page", COLLECTION_PAGE_SIZE.to_string()),
+    ];
+    get_unsplash_json(&state, &url, &query, &format!("Collection {}", id)).await
+}
 
-    let bytes = response.bytes().await.map_err(|e| e.to_string())?;
+/// Collections matching a search, most relevant first
+#[tauri::command]
+async fn search_collections(
+    query: String,
+    state: State<'_, AppState>,
+) -> Result<Vec<UnsplashCollection>, WallyError> {
+    let query = query.trim();
+    if query.is_empty() {
+        return Ok(Vec::new());
+    }
+    let page: SearchPage<UnsplashCollection> = get_unsplash_json(
+        &state,
+        "https://api.unsplash.com/search/collections",
+        &[("query", query.to_string()), ("per_page", "20".to_string())],
+        &format!("Collections matching \"{}\"", query),
+    )
+    .await?;
+    Ok(page.results)
+}
 
-    let mut file = fs::File::create(&file_path).map_err(|e| e.to_string())?;
-    file.write_all(&bytes).map_err(|e| e.to_string())?;
+/// Persist settings changed by a command and let the app and UI pick them up
+fn update_settings(
+    app: &tauri::AppHandle,
+    state: &AppState,
+    update: impl FnOnce(&mut WallpaperSettings),
+) -> Result<WallpaperSettings, WallyError> {
+    let mut settings = state.settings.lock()?.clone();
+    update(&mut settings);
+    save_and_apply_settings(app, state, settings.clone())?;
+    let _ = app.emit("settings-changed", &settings);
+    Ok(settings)
+}
+
+/// Add a playlist, or replace the one with the same name
+#[tauri::command]
+fn save_playlist(
+    playlist: Playlist,
+    app: tauri::AppHandle,
+    state: State<AppState>,
+) -> Result<Vec<Playlist>, WallyError> {
+    let name = playlist.name.trim().to_string();
+    if name.is_empty() {
+        return Err("A playlist needs a name".into());
+    }
+    let playlist = Playlist { name, ..playlist };
+    let settings = update_settings(&app, &state, |settings| {
+        match settings
+            .playlists
+            .iter_mut()
+            .find(|existing| existing.name == playlist.name)
+        {
+            Some(existing) => *existing = playlist,
+            None => settings.playlists.push(playlist),
+        }
+    })?;
+    Ok(settings.playlists)
+}
+
+#[tauri::command]
+fn delete_playlist(
+    name: String,
+    app: tauri::AppHandle,
+    state: State<AppState>,
+) -> Result<Vec<Playlist>, WallyError> {
+    let settings = update_settings(&app, &state, |settings| {
+        settings.playlists.retain(|playlist| playlist.name != name);
+    })?;
+    Ok(settings.playlists)
+}
+
+/// Name of the playlist the next change picks from, if any is active right now
+#[tauri::command]
+fn get_active_playlist(state: State<AppState>) -> Result<Option<String>, WallyError> {
+    let settings = state.settings.lock()?;
+    Ok(
+        schedule::active_playlist(&settings.playlists, chrono::Utc::now())
+            .map(|playlist| playlist.name.clone()),
+    )
+}
+
+/// Coordinates for the "sun" schedule: the configured ones, or a cached IP geolocation
+async fn resolve_location(
+    app: &tauri::AppHandle,
+    settings: &WallpaperSettings,
+) -> Option<schedule::Location> {
+    if settings.schedule_mode != "sun" {
+        return None;
+    }
+    if let (Some(latitude), Some(longitude)) = (settings.sun_latitude, settings.sun_longitude) {
+        return Some((latitude, longitude));
+    }
+
+    let state = app.state::<AppState>();
+    if let Some(location) = state.location.lock().ok().and_then(|location| *location) {
+        return Some(location);
+    }
+
+    let client = state.http_client().ok()?;
+    let location = rotation::lookup_location(&client).await?;
+    if let Ok(mut cached) = state.location.lock() {
+        *cached = Some(location);
+    }
+    Some(location)
+}
+
+/// Detect the largest connected screen, in physical pixels
+fn get_largest_screen(app: &tauri::AppHandle) -> Option<ScreenResolution> {
+    app.available_monitors()
+        .ok()?
+        .iter()
+        .map(|monitor| ScreenResolution {
+            width: monitor.size().width,
+            height: monitor.size().height,
+            scale_factor: monitor.scale_factor(),
+        })
+        .max_by_key(|screen| screen.width as u64 * screen.height as u64)
+}
+
+#[tauri::command]
+async fn set_wallpaper(
+    image_url: String,
+    image_id: String,
+    image: Option<UnsplashImage>,
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+) -> Result<String, WallyError> {
+    let _changing = state.change_lock.lock().await;
+    let settings = state.settings.lock()?.clone();
+    let cancel = state.begin_download()?;
+    let wallpaper_dir = get_wallpaper_dir();
+
+    // Prefer the size matching the quality setting when the caller passes the full image
+    let image_url = image
+        .as_ref()
+        .map(|image| image.urls.for_quality(&settings.quality))
+        .unwrap_or(&image_url);
+    let screen = get_largest_screen(&app);
+    let image_url = sized_image_url(image_url, screen, settings.smart_crop);
+
+    // Download the image
+    let client = state.http_client()?;
+    let stem = match &image {
+        Some(image) => filename_stem(&settings.filename_template, image),
+        None => format!("wallpaper_{}", image_id),
+    };
+    let file_path = fetch_wallpaper_file(
+        &client,
+        &settings,
+        &image_url,
+        &wallpaper_dir,
+        &stem,
+        &cancel,
+    )
+    .await?;
+    let caption = image.as_ref().map(UnsplashImage::attribution);
+    let file_path = process_downloaded_wallpaper(file_path, &settings, screen, caption).await;
 
     let file_path_str = file_path.to_string_lossy().to_string();
 
     // Set the wallpaper based on platform
-    set_wallpaper_platform(&file_path_str)?;
+    rotation::set_wallpaper_file(file_path_str.clone(), settings.apply_to_all_spaces).await?;
 
-    // Clean up old wallpapers (keep last 10)
-    cleanup_old_wallpapers(&wallpaper_dir)?;
+    // Clean up old wallpapers
+    cleanup_old_wallpapers(&wallpaper_dir, &settings, &file_path_str)?;
 
     Ok(file_path_str)
 }
 
-fn set_wallpaper_platform(file_path: &str) -> Result<(), String> {
-    #[cfg(target_os = "macos")]
-    {
-        set_wallpaper_macos(file_path)
-    }
+/// Download and set a specific Unsplash photo, e.g. from a `wally://set/unsplash/<id>` link
+#[tauri::command]
+async fn set_wallpaper_by_id(
+    photo_id: String,
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+) -> Result<CurrentWallpaper, WallyError> {
+    let _changing = state.change_lock.lock().await;
+    let image = get_photo(photo_id, state.clone()).await?;
+    let settings = state.settings.lock()?.clone();
+    let client = state.http_client()?;
+    let cancel = state.begin_download()?;
+    let screen = get_largest_screen(&app);
+    let prepared = rotation::prepare_photo(&client, &settings, image, screen, &cancel).await?;
+    let current = rotation::apply_prepared_wallpaper(&client, &settings, prepared).await?;
+    wallpaper_changed(&app, &current);
+    spawn_post_change_integrations(settings, current.clone(), client);
+    Ok(current)
+}
 
-    #[cfg(target_os = "linux")]
-    {
-        set_wallpaper_linux(file_path)
+/// How long a preview stays up when the caller doesn't say
+const DEFAULT_PREVIEW_SECS: u64 = 30;
+
+/// Try a photo as the wallpaper, going back to the previous one after
+/// `revert_after_secs` unless `confirm_preview` keeps it
+#[tauri::command]
+async fn preview_wallpaper(
+    image: UnsplashImage,
+    revert_after_secs: Option<u64>,
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+) -> Result<CurrentWallpaper, WallyError> {
+    let _changing = state.change_lock.lock().await;
+    let settings = state.settings.lock()?.clone();
+    let client = state.http_client()?;
+    let cancel = state.begin_download()?;
+    let screen = get_largest_screen(&app);
+    let prepared = rotation::prepare_photo(&client, &settings, image, screen, &cancel).await?;
+    let current = rotation::apply_prepared_wallpaper(&client, &settings, prepared).await?;
+    info!(
+        "Previewing wallpaper: {}",
+        current.local_path.as_deref().unwrap_or_default()
+    );
+
+    // A preview replacing another takes its place in the history, so reverting still
+    // goes back to the wallpaper from before the first one
+    let replaced = state.preview.lock()?.take();
+    if let Some(replaced) = replaced {
+        replaced.revert.stop();
+        let mut history = load_history();
+        if let Some(index) = history
+            .iter()
+            .skip(1)
+            .position(|entry| entry.local_path == replaced.local_path)
+        {
+            history.remove(index + 1);
+            save_history(&history)?;
+        }
     }
+    wallpaper_changed(&app, &current);
+
+    let delay = Duration::from_secs(revert_after_secs.unwrap_or(DEFAULT_PREVIEW_SECS));
+    let app_handle = app.clone();
+    let revert = BackgroundTask::spawn(move |cancel| async move {
+        tokio::select! {
+            _ = cancel.cancelled() => {}
+            _ = tokio::time::sleep(delay) => {
+                if let Err(e) = end_preview(&app_handle, false).await {
+                    warn!("Failed to revert previewed wallpaper: {}", e);
+                }
+            }
+        }
+    });
+    *state.preview.lock()? = Some(PendingPreview {
+        local_path: current.local_path.clone(),
+        revert,
+    });
+    Ok(current)
+}
 
-    #[cfg(target_os = "windows")]
-    {
-        set_wallpaper_windows(file_path)
+/// Finish a pending preview, keeping it or going back to the wallpaper before it
+async fn end_preview(app: &tauri::AppHandle, keep: bool) -> Result<(), WallyError> {
+    let state = app.state::<AppState>();
+    let Some(preview) = state.preview.lock()?.take() else {
+        return Ok(());
+    };
+    preview.revert.stop();
+    // Anything that changed the wallpaper since wins over the preview
+    let current = state.current_wallpaper.lock()?.clone();
+    if current.local_path != preview.local_path {
+        return Ok(());
     }
 
-    #[cfg(not(any(target_os = "macos", target_os = "linux", target_os = "windows")))]
-    {
-        Err("Unsupported platform".to_string())
+    if keep {
+        info!("Keeping previewed wallpaper");
+        let settings = state.settings.lock()?.clone();
+        spawn_post_change_integrations(settings, current, state.http_client()?);
+        return Ok(());
     }
+    info!("Reverting previewed wallpaper");
+    previous_wallpaper_internal(app).await?;
+    Ok(())
 }
 
-#[cfg(target_os = "macos")]
-fn set_wallpaper_macos(file_path: &str) -> Result<(), String> {
-    eprintln!("[wally] Setting macOS wallpaper: {}", file_path);
-
-    // Use NSWorkspace via AppleScript - this is the most reliable method
-    let script = format!(
-        r#"
-        use framework "AppKit"
-        use scripting additions
-
-        set imageURL to current application's NSURL's fileURLWithPath:"{}"
-        set sharedWorkspace to current application's NSWorkspace's sharedWorkspace()
-        set allScreens to current application's NSScreen's screens()
-
-        repeat with aScreen in allScreens
-            set theOptions to current application's NSDictionary's dictionary()
-            sharedWorkspace's setDesktopImageURL:imageURL forScreen:aScreen options:theOptions |error|:(missing value)
-        end repeat
-        "#,
-        file_path
-    );
+/// Keep the wallpaper being previewed
+#[tauri::command]
+async fn confirm_preview(app: tauri::AppHandle) -> Result<(), WallyError> {
+    end_preview(&app, true).await
+}
 
-    let output = Command::new("osascript")
-        .arg("-e")
-        .arg(&script)
-        .output()
-        .map_err(|e| format!("AppleScript failed: {}", e))?;
-
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        eprintln!("[wally] AppleScript error: {}", stderr);
-
-        // Fallback to System Events
-        let fallback_script = format!(
-            r#"
-            tell application "System Events"
-                tell every desktop
-                    set picture to "{}"
-                end tell
-            end tell
-            "#,
-            file_path
-        );
+/// Go back to the wallpaper from before the preview right away
+#[tauri::command]
+async fn revert_preview(app: tauri::AppHandle) -> Result<(), WallyError> {
+    end_preview(&app, false).await
+}
 
-        let fallback_output = Command::new("osascript")
-            .arg("-e")
-            .arg(&fallback_script)
-            .output()
-            .map_err(|e| format!("Fallback AppleScript failed: {}", e))?;
+/// Label of the borderless window from `open_fullscreen_preview`
+const FULLSCREEN_PREVIEW_WINDOW: &str = "fullscreen-preview";
+
+/// A connected monitor, in physical pixels
+#[derive(Debug, Clone, Serialize)]
+struct MonitorInfo {
+    name: Option<String>,
+    width: u32,
+    height: u32,
+    x: i32,
+    y: i32,
+    scale_factor: f64,
+    primary: bool,
+}
 
-        if !fallback_output.status.success() {
-            return Err(format!(
-                "All methods failed: {}",
-                String::from_utf8_lossy(&fallback_output.stderr)
-            ));
-        }
+#[tauri::command]
+fn get_monitors(app: tauri::AppHandle) -> Result<Vec<MonitorInfo>, WallyError> {
+    let primary = app
+        .primary_monitor()
+        .ok()
+        .flatten()
+        .and_then(|monitor| monitor.name().cloned());
+    let monitors = app.available_monitors().map_err(|e| e.to_string())?;
+    Ok(monitors
+        .iter()
+        .map(|monitor| MonitorInfo {
+            name: monitor.name().cloned(),
+            width: monitor.size().width,
+            height: monitor.size().height,
+            x: monitor.position().x,
+            y: monitor.position().y,
+            scale_factor: monitor.scale_factor(),
+            primary: monitor.name().is_some() && monitor.name() == primary.as_ref(),
+        })
+        .collect())
+}
+
+/// The photo the full-screen preview window shows, sized for its monitor
+#[derive(Debug, Clone, Serialize)]
+pub struct FullscreenPreview {
+    url: String,
+    image: UnsplashImage,
+}
+
+/// Show a photo full-screen on one monitor, without setting it, so it can be judged
+/// at its real size first. Falls back to the primary monitor when `monitor` isn't connected.
+#[tauri::command]
+async fn open_fullscreen_preview(
+    image: UnsplashImage,
+    monitor: Option<String>,
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+) -> Result<(), WallyError> {
+    let monitors = app.available_monitors().map_err(|e| e.to_string())?;
+    let target = monitor
+        .and_then(|name| {
+            monitors
+                .into_iter()
+                .find(|candidate| candidate.name() == Some(&name))
+        })
+        .or_else(|| app.primary_monitor().ok().flatten())
+        .ok_or("No monitor to show the preview on")?;
+
+    let screen = ScreenResolution {
+        width: target.size().width,
+        height: target.size().height,
+        scale_factor: target.scale_factor(),
+    };
+    let smart_crop = state.settings.lock()?.smart_crop;
+    *state.fullscreen_preview.lock()? = Some(FullscreenPreview {
+        url: sized_image_url(&image.urls.full, Some(screen), smart_crop),
+        image,
+    });
+
+    // Open a fresh window each time so it lands on the chosen monitor
+    if let Some(window) = app.get_webview_window(FULLSCREEN_PREVIEW_WINDOW) {
+        let _ = window.destroy();
     }
+    let window = tauri::WebviewWindowBuilder::new(
+        &app,
+        FULLSCREEN_PREVIEW_WINDOW,
+        tauri::WebviewUrl::App("preview".into()),
+    )
+    .title("Wally Preview")
+    .decorations(false)
+    .always_on_top(true)
+    .skip_taskbar(true)
+    .visible(false)
+    .build()
+    .map_err(|e| e.to_string())?;
+    window
+        .set_position(*target.position())
+        .map_err(|e| e.to_string())?;
+    window.set_fullscreen(true).map_err(|e| e.to_string())?;
+    window.show().map_err(|e| e.to_string())?;
+    let _ = window.set_focus();
+    Ok(())
+}
 
+/// The photo for the full-screen preview window to show
+#[tauri::command]
+fn get_fullscreen_preview(state: State<AppState>) -> Result<Option<FullscreenPreview>, WallyError> {
+    Ok(state.fullscreen_preview.lock()?.clone())
+}
+
+#[tauri::command]
+fn close_fullscreen_preview(
+    app: tauri::AppHandle,
+    state: State<AppState>,
+) -> Result<(), WallyError> {
+    state.fullscreen_preview.lock()?.take();
+    if let Some(window) = app.get_webview_window(FULLSCREEN_PREVIEW_WINDOW) {
+        window.close().map_err(|e| e.to_string())?;
+    }
     Ok(())
 }
 
-/// Get the current desktop picture path on macOS
-#[cfg(target_os = "macos")]
-fn get_current_desktop_picture() -> Option<String> {
-    let output = Command::new("osascript")
-        .arg("-e")
-        .arg(r#"tell application "System Events" to get picture of current desktop"#)
-        .output()
-        .ok()?;
+/// Set a photo from its ID or a link to its page on unsplash.com
+#[tauri::command]
+async fn set_wallpaper_by_photo_id(
+    id_or_url: String,
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+) -> Result<CurrentWallpaper, WallyError> {
+    let photo_id = parse_photo_id(&id_or_url)
+        .ok_or_else(|| format!("Not an Unsplash photo ID or link: {}", id_or_url.trim()))?;
+    set_wallpaper_by_id(photo_id, app, state).await
+}
 
-    if output.status.success() {
-        let path = String::from_utf8_lossy(&output.stdout).trim().to_string();
-        if !path.is_empty() {
-            return Some(path);
-        }
+/// Outcome of setting a generated test image and restoring the previous wallpaper
+#[derive(Debug, Clone, Serialize)]
+struct BackendTestResult {
+    /// Backend that was tested, as returned by `get_platform`
+    backend: String,
+    set_ok: bool,
+    error: Option<String>,
+    /// Whether the desktop reported the test image back, `None` when the backend
+    /// can't be queried
+    verified: Option<bool>,
+    /// Wallpaper put back afterwards, if there was one to restore
+    restored_path: Option<String>,
+    restore_error: Option<String>,
+}
+
+/// Render a small gradient to set as a test wallpaper
+fn write_test_image(path: &Path) -> Result<(), WallyError> {
+    let (width, height) = (640, 360);
+    let image = image::RgbImage::from_fn(width, height, |x, y| {
+        image::Rgb([(x * 255 / width) as u8, (y * 255 / height) as u8, 160])
+    });
+    image
+        .save_with_format(path, image::ImageFormat::Png)
+        .map_err(|e| WallyError::Other(format!("Failed to create test image: {}", e)))
+}
+
+/// Check the wallpaper backend works on this machine by setting a generated test
+/// image, reading it back where the desktop allows, and restoring the previous wallpaper
+#[tauri::command]
+async fn test_wallpaper_backend(
+    state: State<'_, AppState>,
+) -> Result<BackendTestResult, WallyError> {
+    let _changing = state.change_lock.lock().await;
+    let all_spaces = state.settings.lock()?.apply_to_all_spaces;
+    tauri::async_runtime::spawn_blocking(move || test_wallpaper_backend_blocking(all_spaces))
+        .await
+        .map_err(|e| WallyError::Other(e.to_string()))?
+}
+
+fn test_wallpaper_backend_blocking(all_spaces: bool) -> Result<BackendTestResult, WallyError> {
+    // Each run gets a new file name, as some desktops ignore a path they already show
+    let test_path = get_config_dir().join(format!(
+        "backend-test-{}.png",
+        chrono::Utc::now().timestamp_millis()
+    ));
+    write_test_image(&test_path)?;
+    let test_path_str = test_path.to_string_lossy().to_string();
+
+    // Prefer our own wallpaper, falling back to whatever the desktop currently shows
+    let previous = load_current_wallpaper()
+        .local_path
+        .filter(|path| Path::new(path).exists())
+        .or_else(get_current_desktop_picture);
+
+    info!("Testing wallpaper backend with {}", test_path_str);
+    // The test image only goes to the active Space
+    let set_result = set_wallpaper_platform(&test_path_str, false);
+    let verified = set_result.as_ref().ok().and_then(|_| {
+        // Give the desktop a moment to apply the change before asking for it
+        std::thread::sleep(std::time::Duration::from_millis(500));
+        get_current_desktop_picture().map(|current| {
+            Path::new(&current) == test_path.as_path()
+                || current.eq_ignore_ascii_case(&test_path_str)
+        })
+    });
+
+    let restore_result = match &previous {
+        Some(previous) if set_result.is_ok() => Some(set_wallpaper_platform(previous, all_spaces)),
+        _ => None,
+    };
+    // Keep the test image while it is still the wallpaper
+    if set_result.is_err() || restore_result.as_ref().is_some_and(Result::is_ok) {
+        let _ = fs::remove_file(&test_path);
     }
-    None
+
+    Ok(BackendTestResult {
+        backend: wally_core::platform::get_platform(),
+        set_ok: set_result.is_ok(),
+        error: set_result.err().map(|e| e.to_string()),
+        verified,
+        restored_path: previous.filter(|_| restore_result.as_ref().is_some_and(Result::is_ok)),
+        restore_error: restore_result.and_then(Result::err).map(|e| e.to_string()),
+    })
 }
 
 /// Space watcher daemon - monitors current space wallpaper and re-applies if different
 #[cfg(target_os = "macos")]
-async fn space_watcher_daemon(running: Arc<AtomicBool>) {
-    eprintln!("[wally space-watcher] Starting space watcher");
+async fn space_watcher_daemon(cancel: CancellationToken) {
+    info!(target: "wally::space-watcher", "Starting space watcher");
 
-    while running.load(Ordering::SeqCst) {
-        tokio::time::sleep(Duration::from_millis(500)).await;
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep(Duration::from_millis(500)) => {}
+            _ = cancel.cancelled() => break,
+        }
 
         // Load our desired wallpaper
         let desired = load_current_wallpaper();
@@ -337,565 +1105,1743 @@ async fn space_watcher_daemon(running: Arc<AtomicBool>) {
             if let Some(current_picture) = get_current_desktop_picture() {
                 // If current space has different wallpaper, apply ours
                 if current_picture != desired_path {
-                    eprintln!(
-                        "[wally space-watcher] Wallpaper mismatch detected. Current: {}, Desired: {}",
+                    info!(
+                        target: "wally::space-watcher",
+                        "Wallpaper mismatch detected. Current: {}, Desired: {}",
                         current_picture, desired_path
                     );
                     if let Err(e) = set_wallpaper_macos(&desired_path) {
-                        eprintln!("[wally space-watcher] Failed to set wallpaper: {}", e);
+                        warn!(target: "wally::space-watcher", "Failed to set wallpaper: {}", e);
                     } else {
-                        eprintln!("[wally space-watcher] Wallpaper re-applied successfully");
+                        info!(target: "wally::space-watcher", "Wallpaper re-applied successfully");
                     }
                 }
             }
         }
     }
 
-    eprintln!("[wally space-watcher] Space watcher stopped");
+    info!(target: "wally::space-watcher", "Space watcher stopped");
 }
 
+/// How long after a virtual desktop switch, creation or removal the wallpaper is
+/// checked, Windows resets it a moment after the change rather than right away
 #[cfg(target_os = "windows")]
-fn set_wallpaper_windows(file_path: &str) -> Result<(), String> {
-    use std::path::Path;
-    use windows::core::{HSTRING, PCWSTR};
-    use windows::Win32::System::Com::{
-        CoCreateInstance, CoInitializeEx, CLSCTX_ALL, COINIT_APARTMENTTHREADED,
+const DESKTOP_CHANGE_SETTLE: Duration = Duration::from_secs(5);
+
+/// Virtual desktop watcher - re-applies our wallpaper when Windows resets it after
+/// a virtual desktop is created, removed, or switched to. A wallpaper set through
+/// Windows Settings at any other time is left alone.
+#[cfg(target_os = "windows")]
+async fn virtual_desktop_watcher_daemon(app: tauri::AppHandle, cancel: CancellationToken) {
+    info!(target: "wally::desktop-watcher", "Starting virtual desktop watcher");
+
+    let mut last_desktop = (get_current_virtual_desktop(), get_virtual_desktop_count());
+    let mut check_until: Option<std::time::Instant> = None;
+
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep(Duration::from_secs(1)) => {}
+            _ = cancel.cancelled() => break,
+        }
+
+        let desktop = (get_current_virtual_desktop(), get_virtual_desktop_count());
+        if desktop != last_desktop {
+            info!(target: "wally::desktop-watcher", "Virtual desktops changed");
+            last_desktop = desktop;
+            check_until = Some(std::time::Instant::now() + DESKTOP_CHANGE_SETTLE);
+        }
+        if check_until.is_none_or(|until| std::time::Instant::now() > until) {
+            check_until = None;
+            continue;
+        }
+
+        let Some(desired_path) = current_wallpaper(&app).local_path else {
+            continue;
+        };
+        if !std::path::Path::new(&desired_path).exists() {
+            continue;
+        }
+
+        if let Some(current_picture) = get_current_desktop_picture() {
+            if !current_picture.eq_ignore_ascii_case(&desired_path) {
+                info!(
+                    target: "wally::desktop-watcher",
+                    "Wallpaper reset after a desktop change. Current: {}, Desired: {}",
+                    current_picture, desired_path
+                );
+                if let Err(e) = set_wallpaper_windows(&desired_path) {
+                    warn!(target: "wally::desktop-watcher", "Failed to set wallpaper: {}", e);
+                } else {
+                    info!(target: "wally::desktop-watcher", "Wallpaper re-applied successfully");
+                    check_until = None;
+                }
+            }
+        }
+    }
+
+    info!(target: "wally::desktop-watcher", "Virtual desktop watcher stopped");
+}
+
+#[tauri::command]
+async fn download_image(
+    image_url: String,
+    filename: String,
+    image: Option<UnsplashImage>,
+    state: State<'_, AppState>,
+) -> Result<String, WallyError> {
+    let settings = state.settings.lock()?.clone();
+    let cancel = state.download_token()?;
+    let download_dir = dirs::download_dir()
+        .unwrap_or_else(|| dirs::home_dir().unwrap_or_else(|| PathBuf::from(".")));
+
+    // Name the file from the template when the photo is known, otherwise keep the
+    // requested name. Either way the extension is the format actually served.
+    let stem = match &image {
+        Some(image) => filename_stem(&settings.filename_template, image),
+        None => Path::new(&filename)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_else(|| filename.clone()),
     };
-    use windows::Win32::UI::Shell::{DesktopWallpaper, IDesktopWallpaper, DWPOS_FILL};
 
-    eprintln!("[wally] Setting Windows wallpaper: {}", file_path);
+    let client = state.http_client()?;
+    let response = send_with_retry(client.get(&image_url), &settings)
+        .await
+        .map_err(|e| WallyError::DownloadFailed(e.to_string()))?;
+
+    let file_path = download_image_file(response, &download_dir, &stem, &cancel).await?;
+
+    Ok(file_path.to_string_lossy().to_string())
+}
 
-    // Verify file exists
-    if !Path::new(file_path).exists() {
-        return Err(format!("Wallpaper file does not exist: {}", file_path));
+#[tauri::command]
+async fn trigger_download(
+    download_location: String,
+    state: State<'_, AppState>,
+) -> Result<(), WallyError> {
+    let settings = state.settings.lock()?.clone();
+
+    if settings.api_key.is_empty() {
+        return Ok(());
     }
-    eprintln!("[wally] File exists, proceeding with IDesktopWallpaper");
 
-    unsafe {
-        // Initialize COM
-        let _ = CoInitializeEx(None, COINIT_APARTMENTTHREADED);
+    // Trigger download endpoint to track downloads per Unsplash guidelines
+    let client = state.http_client()?;
+    let _ = client
+        .get(&download_location)
+        .header("Authorization", format!("Client-ID {}", settings.api_key))
+        .send()
+        .await;
 
-        // Create IDesktopWallpaper instance
-        let wallpaper: IDesktopWallpaper = CoCreateInstance(&DesktopWallpaper, None, CLSCTX_ALL)
-            .map_err(|e| format!("Failed to create IDesktopWallpaper: {}", e))?;
+    Ok(())
+}
 
-        // Convert path to HSTRING
-        let path = HSTRING::from(file_path);
+/// The wallpaper backend and what it can do
+#[derive(Debug, Clone, Serialize)]
+struct PlatformInfo {
+    /// "macos", "windows", "linux-kde", "linux-gnome" or "linux"
+    backend: String,
+    /// Whether `set_live_wallpaper` can play videos and animated images here
+    live_wallpaper: bool,
+}
 
-        // Set wallpaper position to Fill
-        wallpaper
-            .SetPosition(DWPOS_FILL)
-            .map_err(|e| format!("Failed to set wallpaper position: {}", e))?;
+#[tauri::command]
+fn get_platform(state: State<AppState>) -> Result<PlatformInfo, WallyError> {
+    Ok(PlatformInfo {
+        backend: wally_core::platform::get_platform(),
+        live_wallpaper: live_wallpaper::supported(&state.settings.lock()?),
+    })
+}
 
-        // Set the wallpaper (pass None for monitor ID to set on all monitors)
-        wallpaper
-            .SetWallpaper(PCWSTR::null(), &path)
-            .map_err(|e| format!("Failed to set wallpaper: {}", e))?;
+/// Play a video or animated image as the wallpaper until a still one replaces it
+#[tauri::command]
+async fn set_live_wallpaper(
+    path: String,
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+) -> Result<(), WallyError> {
+    let _changing = state.change_lock.lock().await;
+    let app_handle = app.clone();
+    tauri::async_runtime::spawn_blocking(move || live_wallpaper::start(&app_handle, &path))
+        .await
+        .map_err(|e| e.to_string())?
+}
 
-        eprintln!("[wally] Windows wallpaper set successfully via IDesktopWallpaper");
-        Ok(())
+#[tauri::command]
+fn stop_live_wallpaper(app: tauri::AppHandle) {
+    live_wallpaper::stop(&app);
+}
+
+#[tauri::command]
+fn get_live_wallpaper(
+    state: State<AppState>,
+) -> Result<Option<live_wallpaper::LiveWallpaper>, WallyError> {
+    Ok(state.live_wallpaper.lock()?.clone())
+}
+
+#[tauri::command]
+fn list_kde_activities() -> Result<Vec<KdeActivity>, WallyError> {
+    #[cfg(target_os = "linux")]
+    {
+        if !is_kde() {
+            return Err(WallyError::UnsupportedDesktop(
+                "KDE Activities require a KDE Plasma session".to_string(),
+            ));
+        }
+        Ok(list_kde_activities_linux()?)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        Err(WallyError::UnsupportedDesktop(
+            "KDE Activities are only available on Linux".to_string(),
+        ))
     }
 }
 
-#[cfg(target_os = "linux")]
-fn set_wallpaper_linux(file_path: &str) -> Result<(), String> {
-    eprintln!("[wally] Setting wallpaper for Linux");
-    eprintln!("[wally] File path: {}", file_path);
-
-    // Log environment for debugging
-    eprintln!(
-        "[wally] XDG_CURRENT_DESKTOP: {:?}",
-        std::env::var("XDG_CURRENT_DESKTOP")
-    );
-    eprintln!(
-        "[wally] KDE_FULL_SESSION: {:?}",
-        std::env::var("KDE_FULL_SESSION")
-    );
-    eprintln!(
-        "[wally] XDG_SESSION_TYPE: {:?}",
-        std::env::var("XDG_SESSION_TYPE")
-    );
+#[tauri::command]
+fn assign_wallpaper_to_activity(
+    activity_id: String,
+    state: State<AppState>,
+) -> Result<(), WallyError> {
+    let local_path = state
+        .current_wallpaper
+        .lock()?
+        .local_path
+        .clone()
+        .ok_or("No current wallpaper to assign")?;
 
-    // Check if file exists
-    if !std::path::Path::new(file_path).exists() {
-        return Err(format!("Wallpaper file does not exist: {}", file_path));
+    #[cfg(target_os = "linux")]
+    {
+        if !is_kde() {
+            return Err(WallyError::UnsupportedDesktop(
+                "KDE Activities require a KDE Plasma session".to_string(),
+            ));
+        }
+        Ok(set_wallpaper_kde_activity(&local_path, &activity_id)?)
     }
-    eprintln!("[wally] File exists: true");
 
-    // Try KDE Plasma first
-    if is_kde() {
-        eprintln!("[wally] Detected KDE Plasma");
-        return set_wallpaper_kde(file_path);
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = (local_path, activity_id);
+        Err(WallyError::UnsupportedDesktop(
+            "KDE Activities are only available on Linux".to_string(),
+        ))
     }
+}
 
-    // Try GNOME
-    if is_gnome() {
-        eprintln!("[wally] Detected GNOME");
-        return set_wallpaper_gnome(file_path);
+/// Record how long until the daemon's next change, refresh the tray tooltip and
+/// let the frontend update its countdown
+fn set_next_change_at(app: &tauri::AppHandle, remaining: Option<Duration>) {
+    let next_change_at = remaining.and_then(|remaining| {
+        chrono::Duration::from_std(remaining)
+            .ok()
+            .map(|remaining| chrono::Utc::now() + remaining)
+    });
+    if let Ok(mut state_next) = app.state::<AppState>().next_change_at.lock() {
+        *state_next = next_change_at;
     }
+    refresh_tray_tooltip(app);
+    if let Some(remaining) = remaining {
+        let _ = app.emit("daemon-tick", remaining.as_secs());
+    }
+}
 
-    Err(
-        "Unsupported Linux desktop environment. Currently supports KDE Plasma and GNOME."
-            .to_string(),
-    )
+#[tauri::command]
+fn get_next_change_time(state: State<AppState>) -> Result<Option<String>, WallyError> {
+    let next_change_at = state.next_change_at.lock()?;
+    Ok(next_change_at.map(|next| next.to_rfc3339()))
 }
 
-#[cfg(target_os = "linux")]
-fn is_kde() -> bool {
-    std::env::var("KDE_FULL_SESSION").is_ok()
-        || std::env::var("XDG_CURRENT_DESKTOP")
-            .map(|d| d.to_lowercase().contains("kde"))
-            .unwrap_or(false)
+/// Human-readable remaining time, e.g. "1h 5m"
+fn format_remaining(remaining: chrono::Duration) -> String {
+    let secs = remaining.num_seconds().max(0);
+    if secs >= 3600 {
+        format!("{}h {}m", secs / 3600, secs % 3600 / 60)
+    } else if secs >= 60 {
+        format!("{}m", secs / 60)
+    } else {
+        format!("{}s", secs)
+    }
 }
 
-#[cfg(target_os = "linux")]
-fn is_gnome() -> bool {
-    std::env::var("GNOME_DESKTOP_SESSION_ID").is_ok()
-        || std::env::var("XDG_CURRENT_DESKTOP")
-            .map(|d| d.to_lowercase().contains("gnome"))
-            .unwrap_or(false)
+/// Fetch a random photo and download it, sized for the largest screen
+async fn prepare_wallpaper(
+    app: &tauri::AppHandle,
+    settings: &WallpaperSettings,
+    cancel: &CancellationToken,
+) -> Result<PreparedWallpaper, WallyError> {
+    let location = resolve_location(app, settings).await;
+    let client = app.state::<AppState>().http_client()?;
+    let screen = get_largest_screen(app);
+    rotation::prepare_wallpaper(&client, settings, location, screen, cancel).await
 }
 
-#[cfg(target_os = "linux")]
-#[allow(unused_assignments)]
-fn set_wallpaper_kde(file_path: &str) -> Result<(), String> {
-    // Plasma 6 script for setting wallpaper
-    let script = format!(
-        r#"
-        const allDesktops = desktops();
-        for (const desktop of allDesktops) {{
-            desktop.currentConfigGroup = ['Wallpaper', 'org.kde.image', 'General'];
-            desktop.writeConfig('Image', 'file://{}');
-        }}
-        "#,
-        file_path
-    );
+/// Take the prefetched wallpaper, if one is ready and still on disk
+fn take_prefetched(app: &tauri::AppHandle) -> Option<PreparedWallpaper> {
+    let state = app.state::<AppState>();
+    let prefetched = state.prefetched.lock().ok()?.take()?;
+    prefetched.local_path.exists().then_some(prefetched)
+}
 
-    eprintln!("[wally] KDE script:\n{}", script);
-
-    // Try qdbus6 first (Plasma 6 / Qt6), then fall back to qdbus
-    let qdbus_commands = ["qdbus6", "qdbus"];
-    let mut last_error = String::from("No qdbus command succeeded");
-
-    for qdbus_cmd in qdbus_commands {
-        eprintln!("[wally] Trying {} command...", qdbus_cmd);
-
-        let output = Command::new(qdbus_cmd)
-            .args([
-                "org.kde.plasmashell",
-                "/PlasmaShell",
-                "org.kde.PlasmaShell.evaluateScript",
-                &script,
-            ])
-            .output();
-
-        match output {
-            Ok(output) => {
-                let stdout = String::from_utf8_lossy(&output.stdout);
-                let stderr = String::from_utf8_lossy(&output.stderr);
-                eprintln!("[wally] {} exit status: {}", qdbus_cmd, output.status);
-                eprintln!("[wally] {} stdout: {}", qdbus_cmd, stdout);
-                eprintln!("[wally] {} stderr: {}", qdbus_cmd, stderr);
-
-                if output.status.success() {
-                    eprintln!("[wally] Successfully set wallpaper via {}", qdbus_cmd);
-                    return Ok(());
-                }
+/// Download the next wallpaper ahead of time so the scheduled change is instant
+async fn prefetch_wallpaper(app: tauri::AppHandle, settings: WallpaperSettings) {
+    if app
+        .state::<AppState>()
+        .prefetched
+        .lock()
+        .map(|prefetched| prefetched.is_some())
+        .unwrap_or(true)
+    {
+        return;
+    }
 
-                // Check if the error is about the script itself vs command not found
-                last_error = format!("{} failed: {}", qdbus_cmd, stderr);
-            }
-            Err(e) => {
-                eprintln!(
-                    "[wally] {} not found or failed to execute: {}",
-                    qdbus_cmd, e
-                );
-                last_error = format!("{} error: {}", qdbus_cmd, e);
-                // Continue to try the next command
+    // Don't spend battery or a metered data plan on a download that may never be used
+    let threshold = settings.battery_threshold;
+    let ignore_metered = settings.ignore_metered;
+    let battery_policy = settings.battery_policy.clone();
+    let save_data = tauri::async_runtime::spawn_blocking(move || {
+        (battery_policy != "ignore" && system::is_low_battery(threshold))
+            || (!ignore_metered && system::is_metered_connection())
+    })
+    .await
+    .unwrap_or(true);
+    if save_data {
+        return;
+    }
+
+    let Ok(cancel) = app.state::<AppState>().download_token() else {
+        return;
+    };
+    match prepare_wallpaper(&app, &settings, &cancel).await {
+        Ok(prepared) => {
+            info!(
+                target: "wally::daemon",
+                "Prefetched next wallpaper: {}",
+                prepared.image.id
+            );
+            if let Ok(mut prefetched) = app.state::<AppState>().prefetched.lock() {
+                *prefetched = Some(prepared);
             }
         }
+        Err(e) => warn!(target: "wally::daemon", "Prefetch failed: {}", e),
     }
+}
 
-    // If qdbus methods fail, try plasma-apply-wallpaperimage (Plasma 6)
-    eprintln!("[wally] Trying plasma-apply-wallpaperimage...");
-    let output = Command::new("plasma-apply-wallpaperimage")
-        .arg(file_path)
-        .output();
-
-    match output {
-        Ok(output) => {
-            let stdout = String::from_utf8_lossy(&output.stdout);
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            eprintln!(
-                "[wally] plasma-apply-wallpaperimage exit status: {}",
-                output.status
+/// Fetch and set a new wallpaper (used by daemon)
+async fn change_wallpaper_internal(
+    app: &tauri::AppHandle,
+    settings: &WallpaperSettings,
+) -> Result<(), WallyError> {
+    let state = app.state::<AppState>();
+    let _changing = state.change_lock.lock().await;
+    let prepared = match take_prefetched(app) {
+        Some(prepared) => {
+            info!(
+                target: "wally::daemon",
+                "Using prefetched wallpaper: {}",
+                prepared.image.id
             );
-            eprintln!("[wally] plasma-apply-wallpaperimage stdout: {}", stdout);
-            eprintln!("[wally] plasma-apply-wallpaperimage stderr: {}", stderr);
+            prepared
+        }
+        None => {
+            let cancel = state.begin_download()?;
+            prepare_wallpaper(app, settings, &cancel).await?
+        }
+    };
+    let client = state.http_client()?;
+    let current = rotation::apply_prepared_wallpaper(&client, settings, prepared).await?;
+    wallpaper_changed(app, &current);
+    spawn_post_change_integrations(settings.clone(), current, client);
+
+    Ok(())
+}
+
+/// Show the next image of the slideshow folder in place of a new photo
+async fn show_next_slide(
+    app: &tauri::AppHandle,
+    settings: &WallpaperSettings,
+) -> Result<(), WallyError> {
+    let state = app.state::<AppState>();
+    let _changing = state.change_lock.lock().await;
+    let slide_settings = settings.clone();
+    let slide =
+        tauri::async_runtime::spawn_blocking(move || slideshow::next_slide(&slide_settings))
+            .await
+            .map_err(|e| e.to_string())??;
+    let local_path = slide.to_string_lossy().to_string();
+    info!(target: "wally::daemon", "Showing slide {}", local_path);
+    let entry = CurrentWallpaper {
+        palette: extract_wallpaper_palette(&local_path).await,
+        local_path: Some(local_path),
+        ..Default::default()
+    };
+    let all_spaces = settings.apply_to_all_spaces;
+    let current = tauri::async_runtime::spawn_blocking(move || {
+        rotation::set_cached_wallpaper(entry, all_spaces)
+    })
+    .await
+    .map_err(|e| e.to_string())??;
+    wallpaper_changed(app, &current);
+    Ok(())
+}
+
+/// Go back to the wallpaper set before the current one.
+/// The current entry is dropped from the history so repeated calls keep walking back.
+async fn previous_wallpaper_internal(app: &tauri::AppHandle) -> Result<(), WallyError> {
+    let state = app.state::<AppState>();
+    let _changing = state.change_lock.lock().await;
+    let mut history = load_history();
+    if !history.is_empty() {
+        history.remove(0);
+    }
+    // Skip entries whose files have since been cleaned up
+    while history.first().is_some_and(|entry| {
+        !entry
+            .local_path
+            .as_deref()
+            .is_some_and(|path| Path::new(path).exists())
+    }) {
+        history.remove(0);
+    }
+
+    let Some(previous) = history.first().cloned() else {
+        return Err("No previous wallpaper in history".into());
+    };
+    let local_path = previous.local_path.clone().unwrap_or_default();
+    info!("Restoring previous wallpaper: {}", local_path);
+
+    let all_spaces = state.settings.lock()?.apply_to_all_spaces;
+    rotation::set_wallpaper_file(local_path, all_spaces).await?;
+
+    let current = CurrentWallpaper {
+        set_at: Some(chrono::Utc::now().to_rfc3339()),
+        ..previous
+    };
+    history[0] = current.clone();
+    save_history(&history)?;
+    persist_current_wallpaper(&current)?;
+
+    wallpaper_changed(app, &current);
+    Ok(())
+}
+
+/// Re-apply a wallpaper from the history straight from the local cache
+fn restore_recent_wallpaper(app: &tauri::AppHandle, index: usize) -> Result<(), WallyError> {
+    let entry = load_history()
+        .into_iter()
+        .nth(index)
+        .ok_or("Wallpaper is no longer in history")?;
+    apply_cached_wallpaper(app, entry)
+}
+
+/// Set a wallpaper that is already on disk and make it the current one.
+/// Blocking, so it must not be called from the async runtime.
+fn apply_cached_wallpaper(
+    app: &tauri::AppHandle,
+    entry: CurrentWallpaper,
+) -> Result<(), WallyError> {
+    let state = app.state::<AppState>();
+    let _changing = state.change_lock.blocking_lock();
+    let all_spaces = state.settings.lock()?.apply_to_all_spaces;
+    let current = rotation::set_cached_wallpaper(entry, all_spaces)?;
+
+    wallpaper_changed(app, &current);
+    Ok(())
+}
+
+/// Set an image from anywhere on disk as the wallpaper. A copy is kept in the
+/// wallpaper folder and recorded in the history like a downloaded photo.
+async fn set_local_wallpaper(
+    app: &tauri::AppHandle,
+    path: &str,
+) -> Result<CurrentWallpaper, WallyError> {
+    let state = app.state::<AppState>();
+    let _changing = state.change_lock.lock().await;
+    let settings = state.settings.lock()?.clone();
+    let source = PathBuf::from(path);
+    let wallpaper_dir = get_wallpaper_dir();
+    let dir = wallpaper_dir.clone();
+    let file_path = tauri::async_runtime::spawn_blocking(move || import_local_image(&source, &dir))
+        .await
+        .map_err(|e| e.to_string())??;
+    info!("Setting local image: {}", file_path.display());
+    let file_path =
+        process_downloaded_wallpaper(file_path, &settings, get_largest_screen(app), None).await;
+
+    let local_path = file_path.to_string_lossy().to_string();
+    let entry = CurrentWallpaper {
+        palette: extract_wallpaper_palette(&local_path).await,
+        phash: wallpaper_phash(file_path)
+            .await
+            .map(|hash| format!("{:016x}", hash)),
+        local_path: Some(local_path.clone()),
+        ..Default::default()
+    };
+    let all_spaces = settings.apply_to_all_spaces;
+    let current = tauri::async_runtime::spawn_blocking(move || {
+        rotation::set_cached_wallpaper(entry, all_spaces)
+    })
+    .await
+    .map_err(|e| e.to_string())??;
+    wallpaper_changed(app, &current);
+    let _ = cleanup_old_wallpapers(&wallpaper_dir, &settings, &local_path);
+    spawn_post_change_integrations(settings, current.clone(), state.http_client()?);
+    Ok(current)
+}
+
+/// Set a local image file as the wallpaper
+#[tauri::command]
+async fn set_wallpaper_from_path(
+    path: String,
+    app: tauri::AppHandle,
+) -> Result<CurrentWallpaper, WallyError> {
+    set_local_wallpaper(&app, &path).await
+}
+
+/// Set the image on the clipboard as the wallpaper, e.g. a screenshot or an image
+/// copied from the browser
+#[tauri::command]
+async fn set_wallpaper_from_clipboard(
+    app: tauri::AppHandle,
+) -> Result<CurrentWallpaper, WallyError> {
+    let image = app
+        .clipboard()
+        .read_image()
+        .map_err(|e| format!("The clipboard doesn't contain an image ({})", e))?;
+    let (width, height) = (image.width(), image.height());
+    let rgba = image.rgba().to_vec();
+    let file_path = get_wallpaper_dir().join(format!(
+        "wallpaper_clipboard_{}.png",
+        chrono::Local::now().format("%Y%m%d_%H%M%S")
+    ));
+    let target = file_path.clone();
+    tauri::async_runtime::spawn_blocking(move || -> Result<(), WallyError> {
+        let image = image::RgbaImage::from_raw(width, height, rgba)
+            .ok_or("The clipboard image is malformed")?;
+        image
+            .save_with_format(&target, image::ImageFormat::Png)
+            .map_err(|e| WallyError::Other(format!("Failed to save clipboard image: {}", e)))
+    })
+    .await
+    .map_err(|e| e.to_string())??;
+    set_local_wallpaper(&app, &file_path.to_string_lossy()).await
+}
+
+/// Set the first image dropped onto the window as the wallpaper
+fn handle_dropped_files(app: &tauri::AppHandle, paths: &[PathBuf]) {
+    let Some(path) = paths.iter().find(|path| {
+        path.extension()
+            .is_some_and(|ext| is_image_extension(&ext.to_string_lossy()))
+    }) else {
+        return;
+    };
+    let app = app.clone();
+    let path = path.to_string_lossy().to_string();
+    tauri::async_runtime::spawn(async move {
+        let result = set_local_wallpaper(&app, &path).await.map(|_| ());
+        report_change_result(&app, "drop", &result);
+    });
+}
+
+/// Rotate to another previously downloaded wallpaper, for when Unsplash is unreachable
+fn rotate_cached_wallpaper(app: &tauri::AppHandle) -> Result<(), WallyError> {
+    let settings = app.state::<AppState>().settings.lock()?.clone();
+    apply_cached_wallpaper(app, rotation::next_cached_wallpaper(&settings)?)
+}
+
+/// Number of wallpapers listed in the tray's Recent submenu
+const RECENT_MENU_SIZE: usize = 5;
+
+/// Short tray label for a wallpaper, e.g. "Foggy mountain lake — Jane Doe"
+fn recent_menu_label(entry: &CurrentWallpaper) -> String {
+    let Some(image) = &entry.image else {
+        return entry
+            .local_path
+            .as_deref()
+            .and_then(|path| Path::new(path).file_name())
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| "Unknown wallpaper".to_string());
+    };
+
+    let full = image
+        .description
+        .as_deref()
+        .or(image.alt_description.as_deref())
+        .unwrap_or("Untitled");
+    let mut description: String = full.chars().take(40).collect();
+    if full.chars().count() > 40 {
+        description.push('…');
+    }
+    format!("{} — {}", description, image.user.name)
+}
+
+fn build_tray_menu(app: &tauri::AppHandle) -> tauri::Result<Menu<tauri::Wry>> {
+    let show_item = MenuItem::with_id(app, "show", "Show Wally", true, None::<&str>)?;
+    let change_item = MenuItem::with_id(app, "change", "Change Wallpaper", true, None::<&str>)?;
+    let paused = app
+        .state::<AppState>()
+        .rotation_paused
+        .load(Ordering::SeqCst);
+    let pause_item =
+        CheckMenuItem::with_id(app, "pause", "Pause Rotation", true, paused, None::<&str>)?;
+    let pinned = pin_status(app).pinned;
+    let pin_item = CheckMenuItem::with_id(
+        app,
+        "pin",
+        "Keep This Wallpaper",
+        true,
+        pinned,
+        None::<&str>,
+    )?;
+    let quit_item = MenuItem::with_id(app, "quit", "Quit", true, None::<&str>)?;
+    let update_item = app
+        .state::<AppState>()
+        .update
+        .lock()
+        .ok()
+        .and_then(|update| update.as_ref().map(|update| update.version.clone()))
+        .map(|version| {
+            MenuItem::with_id(
+                app,
+                "update",
+                format!("Install Update {}", version),
+                true,
+                None::<&str>,
+            )
+        })
+        .transpose()?;
+
+    let recent_items = load_history()
+        .iter()
+        .take(RECENT_MENU_SIZE)
+        .enumerate()
+        .map(|(index, entry)| {
+            MenuItem::with_id(
+                app,
+                format!("recent-{}", index),
+                recent_menu_label(entry),
+                true,
+                None::<&str>,
+            )
+        })
+        .collect::<tauri::Result<Vec<_>>>()?;
+    let recent_refs: Vec<&dyn IsMenuItem<tauri::Wry>> = recent_items
+        .iter()
+        .map(|item| item as &dyn IsMenuItem<tauri::Wry>)
+        .collect();
+    let recent_menu = Submenu::with_id_and_items(
+        app,
+        "recent",
+        "Recent",
+        !recent_refs.is_empty(),
+        &recent_refs,
+    )?;
+
+    let mut items: Vec<&dyn IsMenuItem<tauri::Wry>> = vec![
+        &show_item,
+        &change_item,
+        &pause_item,
+        &pin_item,
+        &recent_menu,
+    ];
+    if let Some(update_item) = &update_item {
+        items.push(update_item);
+    }
+    items.push(&quit_item);
+    Menu::with_items(app, &items)
+}
+
+/// Tray tooltip with the current photo's author and the time until the next change
+fn tray_tooltip(app: &tauri::AppHandle) -> String {
+    let state = app.state::<AppState>();
+    let mut lines = vec!["Wally - Wallpaper Manager".to_string()];
+
+    if let Some(image) = current_wallpaper(app).image {
+        lines.push(image.attribution());
+    }
+
+    let pin = pin_status(app);
+    if pin.pinned {
+        lines.push(match pin.until_local() {
+            Some(until) => format!("Wallpaper kept until {}", until.format("%a %H:%M")),
+            None => "Wallpaper kept".to_string(),
+        });
+    } else if state.rotation_paused.load(Ordering::SeqCst) {
+        lines.push("Rotation paused".to_string());
+    } else if let Some(next_change_at) = state.next_change_at.lock().ok().and_then(|next| *next) {
+        lines.push(format!(
+            "Next change in {}",
+            format_remaining(next_change_at - chrono::Utc::now())
+        ));
+    }
+    if let Some(failed) = state.last_error.lock().ok().and_then(|error| error.clone()) {
+        lines.push(format!("Last change failed: {}", failed.reason));
+    }
+
+    lines.join("\n")
+}
+
+fn refresh_tray_tooltip(app: &tauri::AppHandle) {
+    if let Some(tray) = app.tray_by_id("main") {
+        let _ = tray.set_tooltip(Some(tray_tooltip(app)));
+    }
+}
+
+/// Rebuild the tray menu and tooltip so they reflect the latest history and pause state
+fn refresh_tray_menu(app: &tauri::AppHandle) {
+    let Some(tray) = app.tray_by_id("main") else {
+        return;
+    };
+    match build_tray_menu(app) {
+        Ok(menu) => {
+            let _ = tray.set_menu(Some(menu));
+        }
+        Err(e) => warn!(target: "wally::tray", "Failed to rebuild menu: {}", e),
+    }
+    refresh_tray_tooltip(app);
+}
+
+/// Payload of the `change-failed` event
+#[derive(Debug, Clone, Serialize)]
+pub struct ChangeFailed {
+    /// Stable code of the error, see `WallyError::code`
+    code: &'static str,
+    reason: String,
+}
+
+/// Log the outcome of a wallpaper change and tell the frontend about it, so
+/// failures such as an invalid API key are visible outside the logs
+fn report_change_result(app: &tauri::AppHandle, source: &str, result: &Result<(), WallyError>) {
+    let failed = result.as_ref().err().map(|e| ChangeFailed {
+        code: e.code(),
+        reason: e.to_string(),
+    });
+    if let (Some(failed), Ok(settings)) = (&failed, app.state::<AppState>().settings.lock()) {
+        metrics::record_failure(&settings, failed.code);
+    }
+    if let Ok(mut last_error) = app.state::<AppState>().last_error.lock() {
+        *last_error = failed.clone();
+    }
+    refresh_tray_tooltip(app);
+    match failed {
+        None => {
+            info!(source, "Wallpaper changed successfully");
+            let _ = app.emit("change-succeeded", current_wallpaper(app));
+        }
+        Some(failed) => {
+            warn!(source, "Failed to change wallpaper: {}", failed.reason);
+            let _ = app.emit("change-failed", failed);
+        }
+    }
+}
+
+/// Show a system notification crediting the photo after an automatic change
+fn notify_wallpaper_changed(app: &tauri::AppHandle, current: &CurrentWallpaper) {
+    let Some(image) = &current.image else {
+        return;
+    };
+
+    let description = image
+        .description
+        .as_deref()
+        .or(image.alt_description.as_deref())
+        .unwrap_or("New wallpaper");
+
+    // Action buttons (Undo, Favorite) are only available on mobile in the notification plugin
+    if let Err(e) = app
+        .notification()
+        .builder()
+        .title("Wallpaper changed")
+        .body(format!("{}\n{}", description, image.attribution()))
+        .show()
+    {
+        warn!(target: "wally::daemon", "Failed to show notification: {}", e);
+    }
+}
+
+/// How long before a scheduled change the next wallpaper is prefetched
+const PREFETCH_LEAD: Duration = Duration::from_secs(5 * 60);
+
+/// How far a daemon tick may overrun before it is treated as a system sleep
+const SLEEP_DETECTION_SLACK: Duration = Duration::from_secs(30);
+
+/// Daemon loop that periodically changes wallpaper.
+/// A change that fell due while the system was asleep happens right after wake.
+async fn wallpaper_daemon(app: tauri::AppHandle, cancel: CancellationToken) {
+    info!(target: "wally::daemon", "Starting wallpaper daemon");
+    let _ = app.emit("daemon-started", ());
+    let mut settings_rx = app.state::<AppState>().settings_watch.subscribe();
+    // Pick up the schedule where the previous run left off
+    let mut last_change = load_schedule_state().last_changed_at();
+
+    'daemon: while !cancel.is_cancelled() {
+        // Load fresh settings each iteration
+        let mut settings = load_settings();
+
+        if !settings.auto_change {
+            info!(target: "wally::daemon", "Auto-change disabled, stopping daemon");
+            break;
+        }
+
+        let mut location = resolve_location(&app, &settings).await;
+        let (mut interval_duration, mut elapsed) = schedule_wait(&settings, last_change, location);
+        info!(
+            target: "wally::daemon",
+            "Next wallpaper change in {} seconds",
+            interval_duration.saturating_sub(elapsed).as_secs()
+        );
+        save_next_change_at(interval_duration.saturating_sub(elapsed));
 
-            if output.status.success() {
-                eprintln!("[wally] Successfully set wallpaper via plasma-apply-wallpaperimage");
-                return Ok(());
+        // Sleep for the interval in steps, stopping as soon as the daemon is cancelled.
+        // The timer does not advance while rotation is paused
+        let check_interval = Duration::from_secs(10);
+        let rotation_paused = app.state::<AppState>().rotation_paused.clone();
+        let mut last_tick = chrono::Utc::now();
+        let mut holding = false;
+        let mut idle_gate = rotation::IdleGate::default();
+        let mut waiting_for_idle = false;
+        let mut prefetch_started = false;
+
+        loop {
+            if elapsed >= interval_duration {
+                let now = chrono::Utc::now();
+                if let Some(quiet_end) = schedule::quiet_hours_end(&settings.quiet_hours, now) {
+                    // Defer a due change until the current quiet window ends
+                    let remaining = (quiet_end - now).to_std().unwrap_or(check_interval);
+                    info!(
+                        target: "wally::daemon",
+                        "Quiet hours, deferring change by {} seconds",
+                        remaining.as_secs()
+                    );
+                    interval_duration = elapsed + remaining;
+                } else if settings.pause_when_presenting
+                    && tauri::async_runtime::spawn_blocking(system::is_presenting)
+                        .await
+                        .unwrap_or(false)
+                {
+                    // Hold the change, checking again every step until the presentation ends
+                    if !holding {
+                        info!(
+                            target: "wally::daemon",
+                            "Fullscreen app or presentation active, holding change"
+                        );
+                        holding = true;
+                    }
+                    interval_duration = elapsed + check_interval;
+                } else if settings.idle_change_mode != "off"
+                    && idle_gate.holds(
+                        &settings,
+                        tauri::async_runtime::spawn_blocking(system::idle_time)
+                            .await
+                            .unwrap_or(None),
+                    )
+                {
+                    // Don't flip the desktop while the user is looking at it
+                    if !waiting_for_idle {
+                        info!(
+                            target: "wally::daemon",
+                            "Waiting for the user to be idle before changing"
+                        );
+                        waiting_for_idle = true;
+                    }
+                    interval_duration = elapsed + check_interval;
+                } else {
+                    break;
+                }
+            }
+
+            // Download the next wallpaper shortly before it is due
+            // The photo of the day is picked by date, so it can't be fetched before midnight
+            if settings.prefetch_next
+                && settings.schedule_mode != "daily"
+                && !settings.slideshow_enabled
+                && !prefetch_started
+                && interval_duration.saturating_sub(elapsed) <= PREFETCH_LEAD
+            {
+                prefetch_started = true;
+                tauri::async_runtime::spawn(prefetch_wallpaper(app.clone(), settings.clone()));
+            }
+
+            set_next_change_at(&app, Some(interval_duration.saturating_sub(elapsed)));
+            tokio::select! {
+                _ = cancel.cancelled() => {
+                    info!(target: "wally::daemon", "Daemon stop requested");
+                    break 'daemon;
+                }
+                _ = tokio::time::sleep(check_interval) => {
+                    // Count wall-clock time, since the monotonic timer behind sleep stops
+                    // while the system is suspended
+                    let now = chrono::Utc::now();
+                    let step = (now - last_tick).to_std().unwrap_or(check_interval);
+                    last_tick = now;
+                    if step > check_interval + SLEEP_DETECTION_SLACK {
+                        info!(
+                            target: "wally::daemon",
+                            "System resumed after {} seconds",
+                            step.as_secs()
+                        );
+                        let _ = app.emit("system-resumed", step.as_secs());
+                    }
+                    if !rotation_paused.load(Ordering::SeqCst) {
+                        elapsed += step;
+                    }
+                }
+                Ok(()) = settings_rx.changed() => {
+                    // Apply a new interval right away instead of after the old one runs out
+                    settings = settings_rx.borrow_and_update().clone();
+                    if !settings.auto_change {
+                        continue 'daemon;
+                    }
+                    if settings.schedule_mode == "interval" {
+                        (interval_duration, _) = schedule_wait(&settings, last_change, location);
+                    } else {
+                        location = resolve_location(&app, &settings).await;
+                        let (wait, _) = schedule_wait(&settings, last_change, location);
+                        interval_duration = elapsed + wait;
+                    }
+                    info!(
+                        target: "wally::daemon",
+                        "Settings changed, next wallpaper change in {} seconds",
+                        interval_duration.saturating_sub(elapsed).as_secs()
+                    );
+                    save_next_change_at(interval_duration.saturating_sub(elapsed));
+                }
             }
-            last_error = format!("plasma-apply-wallpaperimage failed: {}", stderr);
         }
-        Err(e) => {
-            eprintln!("[wally] plasma-apply-wallpaperimage not found: {}", e);
-            last_error = format!("plasma-apply-wallpaperimage error: {}", e);
+
+        // The slideshow cycles its local folder, so there is nothing to download
+        let result = if settings.slideshow_enabled {
+            show_next_slide(&app, &settings).await
+        } else {
+            // Save battery and mobile data by skipping the change or downloading less
+            let policy_settings = settings.clone();
+            match tauri::async_runtime::spawn_blocking(move || {
+                rotation::change_policy(&policy_settings)
+            })
+            .await
+            .unwrap_or(rotation::ChangePolicy::Normal)
+            {
+                rotation::ChangePolicy::Skip(reason) => {
+                    let _ = app.emit("change-skipped", reason);
+                    last_change = Some(chrono::Utc::now());
+                    continue;
+                }
+                rotation::ChangePolicy::SmallImage => settings.quality = "small".to_string(),
+                rotation::ChangePolicy::Normal => {}
+            }
+
+            // Change the wallpaper, falling back to the local cache while offline
+            let mut result = change_wallpaper_internal(&app, &settings).await;
+            if let (Err(e), Ok(client)) = (&result, app.state::<AppState>().http_client()) {
+                if !rotation::is_online(&client).await {
+                    info!(
+                        target: "wally::daemon",
+                        "Offline ({}), rotating through cached wallpapers",
+                        e
+                    );
+                    let app_handle = app.clone();
+                    result = tauri::async_runtime::spawn_blocking(move || {
+                        rotate_cached_wallpaper(&app_handle)
+                    })
+                    .await
+                    .unwrap_or_else(|e| Err(e.to_string().into()));
+                }
+            }
+            result
+        };
+        report_change_result(&app, "daemon", &result);
+        last_change = Some(chrono::Utc::now());
+        if result.is_ok() {
+            let mut schedule = load_schedule_state();
+            schedule.last_changed_at = Some(chrono::Utc::now().to_rfc3339());
+            save_schedule_state(&schedule);
+            if settings.notify_on_change {
+                notify_wallpaper_changed(&app, &current_wallpaper(&app));
+            }
         }
     }
 
-    Err(format!(
-        "Failed to set KDE wallpaper. Last error: {}",
-        last_error
-    ))
+    set_next_change_at(&app, None);
+    info!(target: "wally::daemon", "Wallpaper daemon stopped");
+    let _ = app.emit("daemon-stopped", ());
 }
 
-#[cfg(target_os = "linux")]
-fn set_wallpaper_gnome(file_path: &str) -> Result<(), String> {
-    let file_uri = format!("file://{}", file_path);
-
-    let output = Command::new("gsettings")
-        .args([
-            "set",
-            "org.gnome.desktop.background",
-            "picture-uri",
-            &file_uri,
-        ])
-        .output()
-        .map_err(|e| e.to_string())?;
+#[tauri::command]
+fn start_auto_change(app: tauri::AppHandle) -> Result<(), WallyError> {
+    Ok(spawn_daemon(&app)?)
+}
 
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(format!("Failed to set GNOME wallpaper: {}", stderr));
+/// Start the auto-change daemon unless it is already running.
+/// The lock is held while spawning so concurrent calls can never start two daemons.
+fn spawn_daemon(app: &tauri::AppHandle) -> Result<(), String> {
+    let state = app.state::<AppState>();
+    let mut daemon = state.daemon.lock().map_err(|e| e.to_string())?;
+    if daemon.as_ref().is_some_and(BackgroundTask::is_running) {
+        info!("Daemon already running");
+        return Ok(());
     }
 
-    // Also set for dark mode
-    let _ = Command::new("gsettings")
-        .args([
-            "set",
-            "org.gnome.desktop.background",
-            "picture-uri-dark",
-            &file_uri,
-        ])
-        .output();
-
+    info!("Starting auto-change daemon");
+    let app = app.clone();
+    *daemon = Some(BackgroundTask::spawn(move |cancel| {
+        wallpaper_daemon(app, cancel)
+    }));
     Ok(())
 }
 
-fn cleanup_old_wallpapers(wallpaper_dir: &PathBuf) -> Result<(), String> {
-    let mut entries: Vec<_> = fs::read_dir(wallpaper_dir)
-        .map_err(|e| e.to_string())?
-        .filter_map(|e| e.ok())
-        .filter(|e| {
-            e.path()
-                .file_name()
-                .and_then(|n| n.to_str())
-                .map(|n| n.starts_with("wallpaper_") && n.ends_with(".jpg"))
-                .unwrap_or(false)
-        })
-        .collect();
-
-    // Sort by modification time (newest first)
-    entries.sort_by(|a, b| {
-        let a_time = a.metadata().and_then(|m| m.modified()).ok();
-        let b_time = b.metadata().and_then(|m| m.modified()).ok();
-        b_time.cmp(&a_time)
-    });
-
-    // Remove all but the 10 most recent
-    for entry in entries.into_iter().skip(10) {
-        let _ = fs::remove_file(entry.path());
+#[tauri::command]
+fn stop_auto_change(state: State<AppState>) -> Result<(), WallyError> {
+    info!("Stopping auto-change daemon");
+    if let Some(daemon) = state.daemon.lock()?.take() {
+        daemon.stop();
     }
-
     Ok(())
 }
 
-#[tauri::command]
-async fn download_image(image_url: String, filename: String) -> Result<String, String> {
-    let download_dir = dirs::download_dir()
-        .unwrap_or_else(|| dirs::home_dir().unwrap_or_else(|| PathBuf::from(".")));
+/// How long quitting waits for background tasks before exiting anyway
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(3);
 
-    let file_path = download_dir.join(&filename);
+/// Stop the daemon and watchers, cancel downloads, write out the settings and current
+/// wallpaper and exit
+async fn shutdown(app: tauri::AppHandle) {
+    shutdown_then(app, |app| app.exit(0)).await;
+}
 
-    let client = reqwest::Client::new();
-    let response = client
-        .get(&image_url)
-        .send()
+/// Everything `shutdown` does, ending with `finish` instead of exiting, e.g. to
+/// restart into an update. `finish` runs while no other change can start.
+pub(crate) async fn shutdown_then(app: tauri::AppHandle, finish: impl FnOnce(&tauri::AppHandle)) {
+    // Quit, a signal and the OS asking to exit may all arrive together
+    static SHUTTING_DOWN: AtomicBool = AtomicBool::new(false);
+    if SHUTTING_DOWN.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    info!("Quitting");
+    let state = app.state::<AppState>();
+    let _ = state.cancel_downloads();
+    // A preview nobody confirmed doesn't outlive the app. Reverting is a change of its
+    // own, so it runs before the change lock is taken below.
+    match tokio::time::timeout(SHUTDOWN_TIMEOUT, end_preview(&app, false)).await {
+        Ok(Err(e)) => warn!("Failed to revert previewed wallpaper: {}", e),
+        Err(_) => warn!("Reverting the previewed wallpaper did not finish in time"),
+        Ok(Ok(())) => {}
+    }
+    // Let a change in progress finish writing, its download was just cancelled, and
+    // keep any other from starting while everything is written out
+    let _changing = tokio::time::timeout(SHUTDOWN_TIMEOUT, state.change_lock.lock())
         .await
-        .map_err(|e| e.to_string())?;
-
-    let bytes = response.bytes().await.map_err(|e| e.to_string())?;
+        .inspect_err(|_| warn!("Wallpaper change did not finish in time"))
+        .ok();
+    live_wallpaper::stop(&app);
+    let tasks: Vec<BackgroundTask> = [&state.daemon, &state.space_watcher]
+        .into_iter()
+        .filter_map(|task| task.lock().ok()?.take())
+        .collect();
+    let http_api = state.http_api.lock().ok().and_then(|mut api| api.take());
+    let stopped = futures_util::future::join(
+        futures_util::future::join_all(tasks.into_iter().map(BackgroundTask::shutdown)),
+        async {
+            if let Some(http_api) = http_api {
+                http_api.shutdown().await;
+            }
+        },
+    );
+    if tokio::time::timeout(SHUTDOWN_TIMEOUT, stopped)
+        .await
+        .is_err()
+    {
+        warn!("Background tasks did not stop in time");
+    }
 
-    let mut file = fs::File::create(&file_path).map_err(|e| e.to_string())?;
-    file.write_all(&bytes).map_err(|e| e.to_string())?;
+    // Settings are saved as they change, so a file that reads differently was edited
+    // by hand since and the watcher hasn't caught up, keep the edit. Only a missing
+    // or broken file is written again.
+    if settings_store::read(&get_settings_path()).is_err() {
+        if let Ok(settings) = state.settings.lock() {
+            if let Err(e) = settings_store::save(&get_settings_path(), &settings) {
+                warn!("Failed to save settings: {}", e);
+            }
+        }
+    }
+    if let Ok(current) = state.current_wallpaper.lock() {
+        if let Err(e) = persist_current_wallpaper(&current) {
+            warn!("Failed to save current wallpaper: {}", e);
+        }
+    }
+    #[cfg(unix)]
+    let _ = fs::remove_file(wally_core::ipc::socket_path());
+    finish(&app);
+}
 
-    Ok(file_path.to_string_lossy().to_string())
+/// Replace the HTTP API token with a new random one and save it
+#[tauri::command]
+fn regenerate_http_api_token(
+    app: tauri::AppHandle,
+    state: State<AppState>,
+) -> Result<String, WallyError> {
+    let settings = update_settings(&app, &state, |settings| {
+        settings.http_api_token = http_api::generate_token();
+    })?;
+    Ok(settings.http_api_token)
 }
 
+/// Quit the app, as opposed to closing the window
 #[tauri::command]
-async fn trigger_download(
-    download_location: String,
-    state: State<'_, AppState>,
-) -> Result<(), String> {
-    let settings = state.settings.lock().map_err(|e| e.to_string())?.clone();
+fn quit_app(app: tauri::AppHandle) {
+    tauri::async_runtime::spawn(shutdown(app));
+}
 
-    if settings.api_key.is_empty() {
-        return Ok(());
-    }
+/// Pause rotation if it is running, otherwise resume it
+fn toggle_rotation(app: &tauri::AppHandle) {
+    let paused = app
+        .state::<AppState>()
+        .rotation_paused
+        .load(Ordering::SeqCst);
+    set_rotation_paused_internal(app, !paused);
+}
 
-    // Trigger download endpoint to track downloads per Unsplash guidelines
-    let client = reqwest::Client::new();
-    let _ = client
-        .get(&download_location)
-        .header("Authorization", format!("Client-ID {}", settings.api_key))
-        .send()
-        .await;
+fn set_rotation_paused_internal(app: &tauri::AppHandle, paused: bool) {
+    if paused {
+        info!("Pausing rotation");
+    } else {
+        info!("Resuming rotation");
+    }
+    let state = app.state::<AppState>();
+    state.rotation_paused.store(paused, Ordering::SeqCst);
+    // Resuming rotation by any means releases a pinned wallpaper
+    let unpinned = if paused {
+        None
+    } else {
+        state
+            .wallpaper_pin
+            .lock()
+            .ok()
+            .and_then(|mut pin| pin.take())
+    };
+    if let Some(pin) = unpinned {
+        if let Some(expiry) = pin.expiry {
+            expiry.stop();
+        }
+        let _ = app.emit("wallpaper-pinned", pin_status(app));
+    }
+    refresh_tray_menu(app);
+    let _ = app.emit("rotation-paused", paused);
+}
 
-    Ok(())
+/// Whether the wallpaper is pinned and until when, the payload of `wallpaper-pinned`
+#[derive(Debug, Clone, Serialize)]
+struct PinStatus {
+    pinned: bool,
+    until: Option<String>,
 }
 
-#[tauri::command]
-fn get_platform() -> String {
-    #[cfg(target_os = "macos")]
-    {
-        "macos".to_string()
+impl PinStatus {
+    fn until_local(&self) -> Option<chrono::DateTime<chrono::Local>> {
+        let until = chrono::DateTime::parse_from_rfc3339(self.until.as_deref()?).ok()?;
+        Some(until.with_timezone(&chrono::Local))
     }
+}
 
-    #[cfg(target_os = "linux")]
-    {
-        if is_kde() {
-            "linux-kde".to_string()
-        } else if is_gnome() {
-            "linux-gnome".to_string()
-        } else {
-            "linux".to_string()
-        }
+fn pin_status(app: &tauri::AppHandle) -> PinStatus {
+    let state = app.state::<AppState>();
+    let pin = state.wallpaper_pin.lock();
+    let pin = pin.as_deref().ok().and_then(Option::as_ref);
+    PinStatus {
+        pinned: pin.is_some(),
+        until: pin
+            .and_then(|pin| pin.until)
+            .map(|until| until.to_rfc3339()),
     }
+}
 
-    #[cfg(target_os = "windows")]
-    {
-        "windows".to_string()
+/// Keep the current wallpaper, pausing rotation until `until` or until it is unpinned
+fn pin_wallpaper_internal(app: &tauri::AppHandle, until: Option<chrono::DateTime<chrono::Utc>>) {
+    match until {
+        Some(until) => info!("Keeping the current wallpaper until {}", until.to_rfc3339()),
+        None => info!("Keeping the current wallpaper"),
     }
+    let expiry = until.map(|until| {
+        let app_handle = app.clone();
+        BackgroundTask::spawn(move |cancel| async move {
+            // Check the wall clock in steps, the monotonic timer stops while suspended
+            loop {
+                let remaining = (until - chrono::Utc::now()).to_std().unwrap_or_default();
+                if remaining.is_zero() {
+                    break;
+                }
+                tokio::select! {
+                    _ = cancel.cancelled() => return,
+                    _ = tokio::time::sleep(remaining.min(PIN_CHECK_INTERVAL)) => {}
+                }
+            }
+            info!("Pin expired");
+            set_rotation_paused_internal(&app_handle, false);
+        })
+    });
 
-    #[cfg(not(any(target_os = "macos", target_os = "linux", target_os = "windows")))]
-    {
-        "unknown".to_string()
+    let state = app.state::<AppState>();
+    let previous = state
+        .wallpaper_pin
+        .lock()
+        .ok()
+        .and_then(|mut pin| pin.replace(WallpaperPin { until, expiry }));
+    if let Some(expiry) = previous.and_then(|pin| pin.expiry) {
+        expiry.stop();
     }
+    state.rotation_paused.store(true, Ordering::SeqCst);
+    refresh_tray_menu(app);
+    let _ = app.emit("rotation-paused", true);
+    let _ = app.emit("wallpaper-pinned", pin_status(app));
 }
 
-#[cfg(not(target_os = "linux"))]
-#[allow(dead_code)]
-fn is_kde() -> bool {
-    false
+/// Pin the current wallpaper if it isn't, otherwise let rotation continue
+fn toggle_pin(app: &tauri::AppHandle) {
+    if pin_status(app).pinned {
+        set_rotation_paused_internal(app, false);
+    } else {
+        pin_wallpaper_internal(app, None);
+    }
 }
 
-#[cfg(not(target_os = "linux"))]
-#[allow(dead_code)]
-fn is_gnome() -> bool {
-    false
-}
+/// How often a pin with an end time checks whether it has expired
+const PIN_CHECK_INTERVAL: Duration = Duration::from_secs(60);
 
-/// Convert interval settings to Duration
-fn get_interval_duration(value: u32, unit: &str) -> Duration {
-    match unit {
-        "minutes" => Duration::from_secs(value as u64 * 60),
-        "hours" => Duration::from_secs(value as u64 * 3600),
-        "days" => Duration::from_secs(value as u64 * 86400),
-        "weeks" => Duration::from_secs(value as u64 * 604800),
-        _ => Duration::from_secs(3600), // Default to 1 hour
+/// Keep the current wallpaper, until an RFC 3339 time or until unpinned. Rotation
+/// is paused meanwhile, the daemon keeps running.
+#[tauri::command]
+fn pin_wallpaper(until: Option<String>, app: tauri::AppHandle) -> Result<PinStatus, WallyError> {
+    let until = until
+        .map(|until| {
+            chrono::DateTime::parse_from_rfc3339(&until)
+                .map(|until| until.with_timezone(&chrono::Utc))
+                .map_err(|e| format!("Invalid time {}: {}", until, e))
+        })
+        .transpose()?;
+    if until.is_some_and(|until| until <= chrono::Utc::now()) {
+        return Err("The pin would end in the past".into());
     }
+    pin_wallpaper_internal(&app, until);
+    Ok(pin_status(&app))
 }
 
-/// Fetch and set a new wallpaper (used by daemon)
-async fn change_wallpaper_internal(settings: &WallpaperSettings) -> Result<(), String> {
-    if settings.api_key.is_empty() {
-        return Err("API key not configured".to_string());
+#[tauri::command]
+fn unpin_wallpaper(app: tauri::AppHandle) {
+    if pin_status(&app).pinned {
+        set_rotation_paused_internal(&app, false);
     }
+}
 
-    eprintln!("[wally daemon] Fetching new wallpaper...");
-
-    // Fetch random image from Unsplash
-    let mut url = "https://api.unsplash.com/photos/random?orientation=landscape".to_string();
-    if !settings.collection_id.is_empty() {
-        url.push_str(&format!("&collections={}", settings.collection_id));
-    }
+#[tauri::command]
+fn get_wallpaper_pin(app: tauri::AppHandle) -> PinStatus {
+    pin_status(&app)
+}
 
-    let client = reqwest::Client::new();
-    let response = client
-        .get(&url)
-        .header("Authorization", format!("Client-ID {}", settings.api_key))
-        .send()
-        .await
-        .map_err(|e| format!("Failed to fetch image: {}", e))?;
+#[tauri::command]
+fn set_rotation_paused(paused: bool, app: tauri::AppHandle) {
+    set_rotation_paused_internal(&app, paused);
+}
 
-    if !response.status().is_success() {
-        let status = response.status();
-        let body = response.text().await.unwrap_or_default();
-        return Err(format!("API error: {} - {}", status, body));
-    }
+#[tauri::command]
+fn get_rotation_paused(state: State<AppState>) -> bool {
+    state.rotation_paused.load(Ordering::SeqCst)
+}
 
-    let image: UnsplashImage = response
-        .json()
-        .await
-        .map_err(|e| format!("Failed to parse response: {}", e))?;
+/// Fetch and set a new wallpaper right away, outside the daemon's schedule
+fn spawn_change_now(app: &tauri::AppHandle, source: &'static str) {
+    let app_handle = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let _ = change_now(&app_handle, source).await;
+    });
+}
 
-    eprintln!("[wally daemon] Got image: {}", image.id);
+/// Change the wallpaper right away and report the outcome like the daemon does
+async fn change_now(app: &tauri::AppHandle, source: &'static str) -> Result<(), WallyError> {
+    let settings = load_settings();
+    let result = if settings.slideshow_enabled {
+        show_next_slide(app, &settings).await
+    } else {
+        change_wallpaper_internal(app, &settings).await
+    };
+    report_change_result(app, source, &result);
+    result
+}
 
-    // Download the image
-    let wallpaper_dir = get_wallpaper_dir();
-    let filename = format!("wallpaper_{}.jpg", image.id);
-    let file_path = wallpaper_dir.join(&filename);
+/// Go back to the previous wallpaper in the background
+fn spawn_previous_wallpaper(app: &tauri::AppHandle, source: &'static str) {
+    let app_handle = app.clone();
+    tauri::async_runtime::spawn(async move {
+        if let Err(e) = previous_wallpaper_internal(&app_handle).await {
+            warn!("Failed to restore wallpaper from {}: {}", source, e);
+        }
+    });
+}
 
-    let response = client
-        .get(&image.urls.full)
-        .send()
-        .await
-        .map_err(|e| format!("Failed to download image: {}", e))?;
+fn show_main_window(app: &tauri::AppHandle) {
+    if let Some(window) = app.get_webview_window("main") {
+        let _ = window.unminimize();
+        let _ = window.show();
+        let _ = window.set_focus();
+    }
+}
 
-    let bytes = response
-        .bytes()
-        .await
-        .map_err(|e| format!("Failed to read image bytes: {}", e))?;
+/// Run the actions requested on the command line, e.g. `wally --change-now`.
+/// Returns whether there were any.
+fn handle_cli_args(app: &tauri::AppHandle, args: &[String]) -> bool {
+    let mut handled = false;
+    for arg in args {
+        match arg.as_str() {
+            "--change-now" => spawn_change_now(app, "cli"),
+            "--previous" => spawn_previous_wallpaper(app, "cli"),
+            "--toggle-pause" => toggle_rotation(app),
+            "--show" => show_main_window(app),
+            // Linux and Windows pass deep links on the command line
+            url if url.starts_with(DEEP_LINK_PREFIX) => handle_deep_link(app, url),
+            _ => continue,
+        }
+        handled = true;
+    }
+    handled
+}
 
-    let mut file =
-        fs::File::create(&file_path).map_err(|e| format!("Failed to create file: {}", e))?;
-    file.write_all(&bytes)
-        .map_err(|e| format!("Failed to write file: {}", e))?;
+const DEEP_LINK_PREFIX: &str = "wally://";
+
+/// Act on a `wally://` link: `next`, `previous`, `pause`, `show` or
+/// `set/unsplash/<photo-id>`
+fn handle_deep_link(app: &tauri::AppHandle, url: &str) {
+    info!("Opening {}", url);
+    let link = url.trim_start_matches(DEEP_LINK_PREFIX);
+    let link = link.split(['?', '#']).next().unwrap_or_default();
+    let parts: Vec<&str> = link.split('/').filter(|part| !part.is_empty()).collect();
+    match parts[..] {
+        ["next"] => spawn_change_now(app, "deep link"),
+        ["previous"] => spawn_previous_wallpaper(app, "deep link"),
+        ["pause"] => toggle_rotation(app),
+        ["show"] => show_main_window(app),
+        ["set", "unsplash", photo_id]
+            if photo_id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') =>
+        {
+            let app = app.clone();
+            let photo_id = photo_id.to_string();
+            tauri::async_runtime::spawn(async move {
+                let state = app.state::<AppState>();
+                let result = set_wallpaper_by_id(photo_id, app.clone(), state)
+                    .await
+                    .map(|_| ());
+                report_change_result(&app, "deep link", &result);
+            });
+        }
+        _ => warn!("Unsupported link: {}", url),
+    }
+}
 
-    let file_path_str = file_path.to_string_lossy().to_string();
-    eprintln!("[wally daemon] Downloaded to: {}", file_path_str);
+/// (Re-)register the global shortcuts configured in settings
+fn register_shortcuts(app: &tauri::AppHandle, settings: &WallpaperSettings) {
+    let shortcuts = app.global_shortcut();
+    if let Err(e) = shortcuts.unregister_all() {
+        warn!("Failed to unregister shortcuts: {}", e);
+    }
 
-    // Set the wallpaper
-    set_wallpaper_platform(&file_path_str)?;
-    eprintln!("[wally daemon] Wallpaper set successfully");
+    let actions = [
+        (settings.shortcut_next.as_str(), "next"),
+        (settings.shortcut_previous.as_str(), "previous"),
+        (settings.shortcut_pause.as_str(), "pause"),
+    ];
+    for (accelerator, action) in actions {
+        let accelerator = accelerator.trim();
+        if accelerator.is_empty() {
+            continue;
+        }
+        let result = shortcuts.on_shortcut(accelerator, move |app, _shortcut, event| {
+            if event.state() != ShortcutState::Pressed {
+                return;
+            }
+            match action {
+                "next" => spawn_change_now(app, "shortcut"),
+                "previous" => spawn_previous_wallpaper(app, "shortcut"),
+                _ => toggle_rotation(app),
+            }
+        });
+        match result {
+            Ok(()) => info!("Registered {} shortcut: {}", action, accelerator),
+            Err(e) => warn!(
+                "Failed to register {} shortcut `{}`: {}",
+                action, accelerator, e
+            ),
+        }
+    }
+}
 
-    // Trigger download tracking (per Unsplash guidelines)
-    let _ = client
-        .get(&image.links.download_location)
-        .header("Authorization", format!("Client-ID {}", settings.api_key))
-        .send()
-        .await;
+/// Add or remove the login item so it matches `launch_at_login`
+fn sync_autostart(app: &tauri::AppHandle, settings: &WallpaperSettings) {
+    let autolaunch = app.autolaunch();
+    let enabled = match autolaunch.is_enabled() {
+        Ok(enabled) => enabled,
+        Err(e) => {
+            warn!("Failed to query launch at login: {}", e);
+            return;
+        }
+    };
+    if enabled == settings.launch_at_login {
+        return;
+    }
+    let result = if settings.launch_at_login {
+        autolaunch.enable()
+    } else {
+        autolaunch.disable()
+    };
+    match result {
+        Ok(()) => info!(
+            "Launch at login {}",
+            if settings.launch_at_login {
+                "enabled"
+            } else {
+                "disabled"
+            }
+        ),
+        Err(e) => warn!("Failed to update launch at login: {}", e),
+    }
+}
 
-    // Save current wallpaper info
-    let current = CurrentWallpaper {
-        image: Some(image),
-        local_path: Some(file_path_str),
-        set_at: Some(chrono::Utc::now().to_rfc3339()),
+/// Show or hide the Dock icon according to `hide_dock_icon`
+#[cfg(target_os = "macos")]
+fn apply_dock_visibility(app: &tauri::AppHandle, settings: &WallpaperSettings) {
+    let policy = if settings.hide_dock_icon {
+        tauri::ActivationPolicy::Accessory
+    } else {
+        tauri::ActivationPolicy::Regular
     };
-    let config_path = get_config_dir().join("current_wallpaper.json");
-    if let Ok(content) = serde_json::to_string_pretty(&current) {
-        let _ = fs::write(&config_path, content);
+    if let Err(e) = app.set_activation_policy(policy) {
+        warn!("Failed to change Dock icon visibility: {}", e);
     }
+}
 
-    // Clean up old wallpapers
-    let _ = cleanup_old_wallpapers(&wallpaper_dir);
+/// Turn launch at login on or off and remember the choice in settings
+#[tauri::command]
+fn set_autostart(
+    enabled: bool,
+    app: tauri::AppHandle,
+    state: State<AppState>,
+) -> Result<(), WallyError> {
+    let mut settings = state.settings.lock()?.clone();
+    settings.launch_at_login = enabled;
+    save_and_apply_settings(&app, &state, settings)
+}
 
-    Ok(())
+#[tauri::command]
+async fn previous_wallpaper(app: tauri::AppHandle) -> Result<(), WallyError> {
+    previous_wallpaper_internal(&app).await
 }
 
-/// Daemon loop that periodically changes wallpaper
-async fn wallpaper_daemon(daemon_running: Arc<AtomicBool>) {
-    eprintln!("[wally daemon] Starting wallpaper daemon");
+#[tauri::command]
+fn get_wallpaper_history() -> Vec<CurrentWallpaper> {
+    load_history()
+}
 
-    while daemon_running.load(Ordering::SeqCst) {
-        // Load fresh settings each iteration
-        let settings = load_settings();
+/// Add a photo to the queue the daemon shows before random ones, at `position` or at
+/// the end. A photo that is already queued moves instead.
+#[tauri::command]
+fn enqueue_image(
+    image: UnsplashImage,
+    position: Option<usize>,
+    app: tauri::AppHandle,
+) -> Result<Vec<UnsplashImage>, WallyError> {
+    let mut queue = load_queue();
+    queue.retain(|queued| queued.id != image.id);
+    let position = position.unwrap_or(queue.len()).min(queue.len());
+    info!("Queueing photo {} at position {}", image.id, position);
+    queue.insert(position, image);
+    save_queue(&queue)?;
+    let _ = app.emit("queue-changed", &queue);
+    Ok(queue)
+}
 
-        if !settings.auto_change {
-            eprintln!("[wally daemon] Auto-change disabled, stopping daemon");
-            break;
-        }
+#[tauri::command]
+fn list_queue() -> Vec<UnsplashImage> {
+    load_queue()
+}
 
-        let interval_duration =
-            get_interval_duration(settings.interval_value, &settings.interval_unit);
-        eprintln!(
-            "[wally daemon] Next wallpaper change in {} seconds",
-            interval_duration.as_secs()
-        );
+/// Put the queue in the order of `photo_ids`. Queued photos left out are removed.
+#[tauri::command]
+fn reorder_queue(
+    photo_ids: Vec<String>,
+    app: tauri::AppHandle,
+) -> Result<Vec<UnsplashImage>, WallyError> {
+    let mut queued = load_queue();
+    let queue: Vec<UnsplashImage> = photo_ids
+        .iter()
+        .filter_map(|id| {
+            let index = queued.iter().position(|image| image.id == *id)?;
+            Some(queued.remove(index))
+        })
+        .collect();
+    save_queue(&queue)?;
+    let _ = app.emit("queue-changed", &queue);
+    Ok(queue)
+}
 
-        // Sleep for the interval (check periodically if we should stop)
-        let check_interval = Duration::from_secs(10);
-        let mut elapsed = Duration::ZERO;
+#[tauri::command]
+fn cancel_download(state: State<AppState>) -> Result<(), WallyError> {
+    info!("Cancelling in-flight downloads");
+    Ok(state.cancel_downloads()?)
+}
 
-        while elapsed < interval_duration && daemon_running.load(Ordering::SeqCst) {
-            tokio::time::sleep(check_interval).await;
-            elapsed += check_interval;
-        }
+/// Collect platform, desktop, tool, filesystem, network and settings checks into a
+/// report for bug reports
+#[tauri::command]
+async fn run_diagnostics(
+    state: State<'_, AppState>,
+) -> Result<diagnostics::DiagnosticsReport, WallyError> {
+    let settings = state.settings.lock()?.clone();
+    let client = state.http_client()?;
+    Ok(diagnostics::run(&settings, &client).await)
+}
 
-        // Check if we should stop
-        if !daemon_running.load(Ordering::SeqCst) {
-            eprintln!("[wally daemon] Daemon stop requested");
-            break;
-        }
+/// The most recent crash report, to attach to a bug report
+#[tauri::command]
+fn get_last_crash_report() -> Option<wally_core::crash::CrashReport> {
+    wally_core::crash::last_report()
+}
 
-        // Change the wallpaper
-        match change_wallpaper_internal(&settings).await {
-            Ok(()) => eprintln!("[wally daemon] Wallpaper changed successfully"),
-            Err(e) => eprintln!("[wally daemon] Failed to change wallpaper: {}", e),
-        }
-    }
+/// Usage counted locally while usage metrics are on
+#[tauri::command]
+fn get_usage_summary() -> metrics::UsageSummary {
+    metrics::summary()
+}
 
-    eprintln!("[wally daemon] Wallpaper daemon stopped");
+#[tauri::command]
+fn clear_usage_metrics() {
+    metrics::clear();
 }
 
+/// Look for a newer version on the configured release channel
 #[tauri::command]
-fn start_auto_change(state: State<AppState>) -> Result<(), String> {
-    let daemon_running = state.daemon_running.clone();
+async fn check_for_updates(
+    app: tauri::AppHandle,
+) -> Result<Option<updater::AvailableUpdate>, WallyError> {
+    updater::check(&app).await
+}
 
-    // Check if already running
-    if daemon_running.load(Ordering::SeqCst) {
-        eprintln!("[wally] Daemon already running");
-        return Ok(());
-    }
+/// Install the update found by the last check and restart into it
+#[tauri::command]
+async fn install_update(app: tauri::AppHandle) -> Result<(), WallyError> {
+    updater::install(&app).await
+}
 
-    // Mark as running
-    daemon_running.store(true, Ordering::SeqCst);
-    eprintln!("[wally] Starting auto-change daemon");
+/// Entries shown in the log viewer when the frontend doesn't ask for a number
+const DEFAULT_LOG_LIMIT: usize = 500;
 
-    // Spawn the daemon task
-    let daemon_flag = daemon_running.clone();
-    tauri::async_runtime::spawn(async move {
-        wallpaper_daemon(daemon_flag).await;
-    });
+/// Recent log entries at `level` ("error", "warn", "info", "debug") or more severe
+#[tauri::command]
+fn get_recent_logs(
+    level: Option<String>,
+    limit: Option<usize>,
+) -> Result<Vec<logging::LogEntry>, WallyError> {
+    logging::recent_logs(
+        &get_log_dir(),
+        level.as_deref(),
+        limit.unwrap_or(DEFAULT_LOG_LIMIT),
+    )
+}
 
+#[tauri::command]
+fn open_log_folder() -> Result<(), WallyError> {
+    open::that(get_log_dir())?;
     Ok(())
 }
 
+/// Copy the current photo's credit line to the clipboard and return it
+#[tauri::command]
+fn copy_attribution(
+    format: CreditFormat,
+    app: tauri::AppHandle,
+    state: State<AppState>,
+) -> Result<String, WallyError> {
+    let credit = state
+        .current_wallpaper
+        .lock()?
+        .image
+        .as_ref()
+        .map(|image| image.credit(format))
+        .ok_or("The current wallpaper is not an Unsplash photo")?;
+    app.clipboard()
+        .write_text(credit.clone())
+        .map_err(|e| format!("Failed to copy to the clipboard: {}", e))?;
+    Ok(credit)
+}
+
+/// Open Finder, Explorer or the file manager with the current wallpaper selected
 #[tauri::command]
-fn stop_auto_change(state: State<AppState>) -> Result<(), String> {
-    eprintln!("[wally] Stopping auto-change daemon");
-    state.daemon_running.store(false, Ordering::SeqCst);
+fn reveal_current_wallpaper(state: State<AppState>) -> Result<(), WallyError> {
+    let local_path = state
+        .current_wallpaper
+        .lock()?
+        .local_path
+        .clone()
+        .filter(|path| Path::new(path).exists())
+        .ok_or("The current wallpaper is not on disk")?;
+    tauri_plugin_opener::reveal_item_in_dir(&local_path)
+        .map_err(|e| format!("Failed to show {}: {}", local_path, e))?;
     Ok(())
 }
 
+/// Open the system share sheet with the current photo's link or image file. Without
+/// a choice, Unsplash photos share their link and local images their file.
+#[tauri::command]
+fn share_current_wallpaper(
+    content: Option<share::ShareContent>,
+    app: tauri::AppHandle,
+    state: State<AppState>,
+) -> Result<(), WallyError> {
+    let current = state.current_wallpaper.lock()?.clone();
+    let content = content.unwrap_or(if current.image.is_some() {
+        share::ShareContent::Link
+    } else {
+        share::ShareContent::File
+    });
+    let item = match content {
+        share::ShareContent::Link => {
+            let image = current
+                .image
+                .as_ref()
+                .ok_or("The current wallpaper is not an Unsplash photo")?;
+            share::ShareItem::Link {
+                url: image.photo_url(),
+                title: image.attribution(),
+            }
+        }
+        share::ShareContent::File => share::ShareItem::File(
+            current
+                .local_path
+                .map(PathBuf::from)
+                .filter(|path| path.exists())
+                .ok_or("The current wallpaper is not on disk")?,
+        ),
+    };
+    let window = app
+        .get_webview_window("main")
+        .ok_or("The main window is not open")?;
+    share::share(&window, item)
+}
+
 #[tauri::command]
-async fn open_url(url: String) -> Result<(), String> {
-    open::that(&url).map_err(|e| e.to_string())
+async fn open_url(url: String) -> Result<(), WallyError> {
+    open::that(&url)?;
+    Ok(())
+}
+
+/// What the daemon is doing and how the last change went
+#[derive(Debug, Clone, Serialize)]
+struct DaemonState {
+    running: bool,
+    paused: bool,
+    last_change_at: Option<String>,
+    last_error: Option<ChangeFailed>,
+    next_change_at: Option<String>,
+    /// Where the next photo comes from, e.g. "Collection 880012"
+    source: String,
+    /// Unsplash requests left this hour, as of the latest response
+    rate_limit_remaining: Option<u32>,
+}
+
+fn daemon_state(app: &tauri::AppHandle) -> DaemonState {
+    let state = app.state::<AppState>();
+    let settings = state
+        .settings
+        .lock()
+        .map(|settings| settings.clone())
+        .unwrap_or_default();
+    let location = state.location.lock().ok().and_then(|location| *location);
+    let location = match (settings.sun_latitude, settings.sun_longitude) {
+        (Some(latitude), Some(longitude)) => Some((latitude, longitude)),
+        _ => location,
+    };
+
+    DaemonState {
+        running: state
+            .daemon
+            .lock()
+            .is_ok_and(|daemon| daemon.as_ref().is_some_and(BackgroundTask::is_running)),
+        paused: state.rotation_paused.load(Ordering::SeqCst),
+        last_change_at: current_wallpaper(app).set_at,
+        last_error: state.last_error.lock().ok().and_then(|error| error.clone()),
+        next_change_at: state
+            .next_change_at
+            .lock()
+            .ok()
+            .and_then(|next| *next)
+            .map(|next| next.to_rfc3339()),
+        source: random_photo_resource(&random_photo_params(&settings, location)),
+        rate_limit_remaining: rate_limit_remaining(),
+    }
 }
 
 #[tauri::command]
-fn get_daemon_status(state: State<AppState>) -> bool {
-    state.daemon_running.load(Ordering::SeqCst)
+fn get_daemon_state(app: tauri::AppHandle) -> DaemonState {
+    daemon_state(&app)
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
+    logging::init(&get_log_dir());
+    wally_core::crash::install(env!("CARGO_PKG_VERSION"));
+    if let Some(dir) = portable_dir() {
+        info!("Portable mode, storing data in {}", dir.display());
+    }
+
     let settings = load_settings();
+    wally_core::crash::set_enabled(settings.crash_reports);
     let current_wallpaper = load_current_wallpaper();
     let auto_change_enabled = settings.auto_change;
-    let daemon_running = Arc::new(AtomicBool::new(false));
-    let space_watcher_running = Arc::new(AtomicBool::new(false));
+    let http_client = build_http_client(&settings).unwrap_or_else(|e| {
+        warn!("{}, falling back to default client", e);
+        reqwest::Client::new()
+    });
 
     tauri::Builder::default()
+        // Must come first so a second launch exits before setting anything up
+        .plugin(tauri_plugin_single_instance::init(|app, args, _cwd| {
+            info!("Started again with {:?}", args);
+            let args = args.get(1..).unwrap_or_default();
+            if !handle_cli_args(app, args) {
+                show_main_window(app);
+            }
+        }))
+        .plugin(tauri_plugin_deep_link::init())
+        .plugin(tauri_plugin_clipboard_manager::init())
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_shell::init())
         .plugin(tauri_plugin_os::init())
+        .plugin(tauri_plugin_notification::init())
+        .plugin(tauri_plugin_updater::Builder::new().build())
+        .plugin(tauri_plugin_global_shortcut::Builder::new().build())
+        .plugin(tauri_plugin_autostart::init(
+            tauri_plugin_autostart::MacosLauncher::LaunchAgent,
+            None,
+        ))
         .manage(AppState {
+            settings_watch: tokio::sync::watch::Sender::new(settings.clone()),
             settings: Mutex::new(settings),
             current_wallpaper: Mutex::new(current_wallpaper),
-            daemon_running: daemon_running.clone(),
-            space_watcher_running: space_watcher_running.clone(),
+            daemon: Mutex::new(None),
+            rotation_paused: Arc::new(AtomicBool::new(false)),
+            next_change_at: Mutex::new(None),
+            location: Mutex::new(None),
+            prefetched: Mutex::new(None),
+            api_cache: api_cache::ApiCache::default(),
+            space_watcher: Mutex::new(None),
+            http_api: Mutex::new(None),
+            preview: Mutex::new(None),
+            wallpaper_pin: Mutex::new(None),
+            change_lock: tokio::sync::Mutex::new(()),
+            last_error: Mutex::new(None),
+            fullscreen_preview: Mutex::new(None),
+            live_wallpaper: Mutex::new(None),
+            update: Mutex::new(None),
+            download_cancel: Mutex::new(CancellationToken::new()),
+            user_download_cancel: Mutex::new(CancellationToken::new()),
+            http_client: Mutex::new(http_client),
         })
         .invoke_handler(tauri::generate_handler![
             get_settings,
@@ -903,79 +2849,196 @@ pub fn run() {
             get_current_wallpaper,
             save_current_wallpaper,
             fetch_random_image,
+            get_photo,
+            get_collection_info,
+            list_collection_photos,
+            search_collections,
+            save_playlist,
+            delete_playlist,
+            get_active_playlist,
             set_wallpaper,
             download_image,
+            cancel_download,
             trigger_download,
             get_platform,
+            set_live_wallpaper,
+            stop_live_wallpaper,
+            get_live_wallpaper,
+            list_kde_activities,
+            assign_wallpaper_to_activity,
             start_auto_change,
             stop_auto_change,
-            get_daemon_status,
+            previous_wallpaper,
+            get_wallpaper_history,
+            enqueue_image,
+            list_queue,
+            reorder_queue,
+            get_pinned_wallpapers,
+            list_cached_wallpapers,
+            set_wallpaper_pinned,
+            export_config,
+            import_config,
+            set_autostart,
+            set_wallpaper_by_id,
+            set_wallpaper_by_photo_id,
+            preview_wallpaper,
+            confirm_preview,
+            get_monitors,
+            open_fullscreen_preview,
+            get_fullscreen_preview,
+            close_fullscreen_preview,
+            revert_preview,
+            set_wallpaper_from_path,
+            set_wallpaper_from_clipboard,
+            quit_app,
+            regenerate_http_api_token,
+            set_rotation_paused,
+            get_rotation_paused,
+            pin_wallpaper,
+            unpin_wallpaper,
+            get_wallpaper_pin,
+            get_next_change_time,
+            get_daemon_state,
             open_url,
+            get_recent_logs,
+            run_diagnostics,
+            get_last_crash_report,
+            get_usage_summary,
+            clear_usage_metrics,
+            check_for_updates,
+            install_update,
+            test_wallpaper_backend,
+            open_log_folder,
+            reveal_current_wallpaper,
+            copy_attribution,
+            share_current_wallpaper,
         ])
         .setup(move |app| {
-            // Start space watcher on macOS to re-apply wallpaper when switching spaces
+            // Start space watcher on macOS to re-apply wallpaper when switching spaces.
+            // Writing every Space's picture only shows once the Dock restarts, so
+            // until then switching to another Space briefly shows its old picture.
+            #[cfg(target_os = "macos")]
+            {
+                info!("Starting space watcher for macOS");
+                let watcher = BackgroundTask::spawn(space_watcher_daemon);
+                *app.state::<AppState>()
+                    .space_watcher
+                    .lock()
+                    .map_err(|e| e.to_string())? = Some(watcher);
+            }
+            // Start virtual desktop watcher on Windows to re-apply wallpaper when Windows resets it
+            #[cfg(target_os = "windows")]
+            {
+                info!("Starting virtual desktop watcher for Windows");
+                let app_handle = app.handle().clone();
+                let watcher = BackgroundTask::spawn(move |cancel| {
+                    virtual_desktop_watcher_daemon(app_handle, cancel)
+                });
+                *app.state::<AppState>()
+                    .space_watcher
+                    .lock()
+                    .map_err(|e| e.to_string())? = Some(watcher);
+            }
+            // The gallery loads thumbnails through the asset protocol, which sees nothing else
+            if let Err(e) = app
+                .asset_protocol_scope()
+                .allow_directory(get_thumbnail_dir(), false)
+            {
+                warn!("Failed to allow the thumbnail folder: {}", e);
+            }
+
+            let settings = load_settings();
+            register_shortcuts(app.handle(), &settings);
+            sync_autostart(app.handle(), &settings);
+            http_api::sync(app.handle(), &settings);
+            #[cfg(target_os = "macos")]
+            apply_dock_visibility(app.handle(), &settings);
+
+            let args: Vec<String> = std::env::args().skip(1).collect();
+            handle_cli_args(app.handle(), &args);
+
+            // Installers register the scheme, this covers AppImages and portable copies
+            #[cfg(any(target_os = "linux", target_os = "windows"))]
+            if let Err(e) = app.deep_link().register_all() {
+                warn!("Failed to register the wally:// scheme: {}", e);
+            }
             #[cfg(target_os = "macos")]
             {
-                let space_watcher_flag = space_watcher_running.clone();
-                space_watcher_flag.store(true, Ordering::SeqCst);
-                eprintln!("[wally] Starting space watcher for macOS");
-                tauri::async_runtime::spawn(async move {
-                    space_watcher_daemon(space_watcher_flag).await;
+                let app_handle = app.handle().clone();
+                app.deep_link().on_open_url(move |event| {
+                    for url in event.urls() {
+                        handle_deep_link(&app_handle, url.as_str());
+                    }
                 });
             }
-            #[cfg(not(target_os = "macos"))]
-            let _ = space_watcher_running; // Suppress unused variable warning
+
+            // The window is created hidden so starting in the tray doesn't flash it
+            if settings.start_minimized {
+                info!("Starting minimized to tray");
+            } else if let Some(window) = app.get_webview_window("main") {
+                let _ = window.show();
+            }
+            tauri::async_runtime::spawn(settings_file_watcher(app.handle().clone()));
+            tauri::async_runtime::spawn(ipc::serve(app.handle().clone()));
+            tauri::async_runtime::spawn(updater::watch(app.handle().clone()));
+            #[cfg(target_os = "linux")]
+            tauri::async_runtime::spawn(dbus::serve(app.handle().clone()));
+
+            let app_handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                system::shutdown_signal().await;
+                shutdown(app_handle).await;
+            });
+
+            session::watch(app.handle());
+            // Wally is launched as part of logging in
+            session::trigger(app.handle(), session::SessionEvent::Login);
 
             // Auto-start daemon if enabled in settings
             if auto_change_enabled {
-                eprintln!("[wally] Auto-change enabled, starting daemon on startup");
-                let daemon_flag = daemon_running.clone();
-                daemon_flag.store(true, Ordering::SeqCst);
-                tauri::async_runtime::spawn(async move {
-                    wallpaper_daemon(daemon_flag).await;
-                });
+                info!("Auto-change enabled, starting daemon on startup");
+                spawn_daemon(app.handle())?;
             }
 
             // Create tray menu
-            let show_item = MenuItem::with_id(app, "show", "Show Wally", true, None::<&str>)?;
-            let change_item =
-                MenuItem::with_id(app, "change", "Change Wallpaper", true, None::<&str>)?;
-            let quit_item = MenuItem::with_id(app, "quit", "Quit", true, None::<&str>)?;
-
-            let menu = Menu::with_items(app, &[&show_item, &change_item, &quit_item])?;
+            let menu = build_tray_menu(app.handle())?;
 
             // Build the tray icon
-            let _tray = TrayIconBuilder::new()
+            let _tray = TrayIconBuilder::with_id("main")
                 .icon(app.default_window_icon().unwrap().clone())
                 .menu(&menu)
                 .show_menu_on_left_click(false)
-                .tooltip("Wally - Wallpaper Manager")
+                .tooltip(tray_tooltip(app.handle()))
                 .on_menu_event(|app, event| match event.id.as_ref() {
-                    "show" => {
-                        if let Some(window) = app.get_webview_window("main") {
-                            let _ = window.show();
-                            let _ = window.set_focus();
-                        }
-                    }
-                    "change" => {
-                        // Trigger wallpaper change via the daemon logic
+                    "show" => show_main_window(app),
+                    "change" => spawn_change_now(app, "tray"),
+                    "pause" => toggle_rotation(app),
+                    "pin" => toggle_pin(app),
+                    "update" => {
                         let app_handle = app.clone();
                         tauri::async_runtime::spawn(async move {
-                            let settings = load_settings();
-                            match change_wallpaper_internal(&settings).await {
-                                Ok(()) => eprintln!("[wally tray] Wallpaper changed"),
-                                Err(e) => {
-                                    eprintln!("[wally tray] Failed to change wallpaper: {}", e)
-                                }
+                            if let Err(e) = updater::install(&app_handle).await {
+                                warn!(target: "wally::tray", "{}", e);
                             }
-                            // Emit event to update UI
-                            let _ = app_handle.emit("wallpaper-changed", ());
                         });
                     }
                     "quit" => {
-                        app.exit(0);
+                        tauri::async_runtime::spawn(shutdown(app.clone()));
+                    }
+                    id => {
+                        let Some(index) = id
+                            .strip_prefix("recent-")
+                            .and_then(|index| index.parse::<usize>().ok())
+                        else {
+                            return;
+                        };
+                        let app_handle = app.clone();
+                        tauri::async_runtime::spawn_blocking(move || {
+                            if let Err(e) = restore_recent_wallpaper(&app_handle, index) {
+                                warn!(target: "wally::tray", "Failed to restore wallpaper: {}", e);
+                            }
+                        });
                     }
-                    _ => {}
                 })
                 .on_tray_icon_event(|tray, event| {
                     if let TrayIconEvent::Click {
@@ -999,13 +3062,40 @@ pub fn run() {
 
             Ok(())
         })
-        .on_window_event(|window, event| {
-            // Minimize to tray on close
-            if let tauri::WindowEvent::CloseRequested { api, .. } = event {
-                let _ = window.hide();
+        .on_window_event(|window, event| match event {
+            // Other windows, like the full-screen preview, just close
+            tauri::WindowEvent::CloseRequested { api, .. } if window.label() == "main" => {
+                let app = window.app_handle();
+                let close_to_tray = app
+                    .state::<AppState>()
+                    .settings
+                    .lock()
+                    .map(|settings| settings.close_to_tray)
+                    .unwrap_or(true);
+                // Quitting also goes through `shutdown`, so keep the window until then
                 api.prevent_close();
+                if close_to_tray {
+                    let _ = window.hide();
+                } else {
+                    tauri::async_runtime::spawn(shutdown(app.clone()));
+                }
+            }
+            tauri::WindowEvent::DragDrop(tauri::DragDropEvent::Drop { paths, .. }) => {
+                handle_dropped_files(window.app_handle(), paths);
             }
+            _ => {}
         })
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            // Exits not started by `shutdown`, e.g. Cmd+Q or the session ending, go
+            // through it first so nothing is cut off mid-write
+            if let tauri::RunEvent::ExitRequested {
+                code: None, api, ..
+            } = event
+            {
+                api.prevent_exit();
+                tauri::async_runtime::spawn(shutdown(app.clone()));
+            }
+        });
 }
@@ -0,0 +1,216 @@
+//! Live wallpapers: videos and animated images played as the wallpaper, by mpvpaper
+//! or swww on wlroots compositors, or on Windows by a webview window placed behind
+//! the desktop icons
+
+use crate::{AppState, WallyError};
+use serde::Serialize;
+use tauri::Manager;
+use tracing::info;
+use wally_core::platform::is_video;
+use wally_core::WallpaperSettings;
+
+/// Label of the window playing the live wallpaper on Windows
+#[cfg(target_os = "windows")]
+const LIVE_WALLPAPER_WINDOW: &str = "live-wallpaper";
+
+/// The file being played as the live wallpaper
+#[derive(Debug, Clone, Serialize)]
+pub struct LiveWallpaper {
+    pub path: String,
+    /// Whether it plays as a video rather than showing as an image
+    pub video: bool,
+}
+
+/// Whether live wallpapers can be played with the current desktop and settings
+pub fn supported(settings: &WallpaperSettings) -> bool {
+    wally_core::platform::live_wallpaper_backend().is_some()
+        || (cfg!(target_os = "windows") && settings.live_wallpaper_renderer)
+}
+
+/// Play a video or animated image as the wallpaper until a still one replaces it.
+/// Blocking.
+pub fn start(app: &tauri::AppHandle, path: &str) -> Result<(), WallyError> {
+    let state = app.state::<AppState>();
+    let live = LiveWallpaper {
+        path: path.to_string(),
+        video: is_video(path),
+    };
+    // Set first, the Windows window asks for it as soon as it loads
+    let previous = state.live_wallpaper.lock()?.replace(live.clone());
+    if let Err(e) = play(app, &live) {
+        *state.live_wallpaper.lock()? = previous;
+        return Err(e);
+    }
+    info!("Playing live wallpaper: {}", path);
+    Ok(())
+}
+
+/// Stop the live wallpaper, if one is playing
+pub fn stop(app: &tauri::AppHandle) {
+    let stopped = app
+        .state::<AppState>()
+        .live_wallpaper
+        .lock()
+        .ok()
+        .and_then(|mut live| live.take());
+    if stopped.is_none() {
+        return;
+    }
+
+    #[cfg(target_os = "linux")]
+    wally_core::platform::stop_live_wallpaper_linux();
+
+    #[cfg(target_os = "windows")]
+    if let Some(window) = app.get_webview_window(LIVE_WALLPAPER_WINDOW) {
+        let _ = window.destroy();
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn play(_app: &tauri::AppHandle, live: &LiveWallpaper) -> Result<(), WallyError> {
+    wally_core::platform::set_live_wallpaper_linux(&live.path)
+}
+
+#[cfg(target_os = "windows")]
+fn play(app: &tauri::AppHandle, live: &LiveWallpaper) -> Result<(), WallyError> {
+    use tauri::{PhysicalPosition, PhysicalSize};
+
+    if !app
+        .state::<AppState>()
+        .settings
+        .lock()?
+        .live_wallpaper_renderer
+    {
+        return Err(WallyError::UnsupportedDesktop(
+            "Turn on the live wallpaper renderer in settings first".to_string(),
+        ));
+    }
+    if !std::path::Path::new(&live.path).exists() {
+        return Err(format!("Wallpaper file does not exist: {}", live.path).into());
+    }
+    app.asset_protocol_scope()
+        .allow_file(&live.path)
+        .map_err(|e| e.to_string())?;
+
+    // Reopen rather than reuse, so the new file starts from the beginning
+    if let Some(window) = app.get_webview_window(LIVE_WALLPAPER_WINDOW) {
+        let _ = window.destroy();
+    }
+
+    // One window spans every monitor, like the desktop it sits in
+    let monitors = app.available_monitors().map_err(|e| e.to_string())?;
+    let left = monitors.iter().map(|m| m.position().x).min().unwrap_or(0);
+    let top = monitors.iter().map(|m| m.position().y).min().unwrap_or(0);
+    let right = monitors
+        .iter()
+        .map(|m| m.position().x + m.size().width as i32)
+        .max()
+        .unwrap_or(0);
+    let bottom = monitors
+        .iter()
+        .map(|m| m.position().y + m.size().height as i32)
+        .max()
+        .unwrap_or(0);
+
+    let window = tauri::WebviewWindowBuilder::new(
+        app,
+        LIVE_WALLPAPER_WINDOW,
+        tauri::WebviewUrl::App("live".into()),
+    )
+    .title("Wally Live Wallpaper")
+    .decorations(false)
+    .skip_taskbar(true)
+    .focused(false)
+    .visible(false)
+    .build()
+    .map_err(|e| e.to_string())?;
+    let hwnd = window.hwnd().map_err(|e| e.to_string())?.0 as isize;
+    attach_to_desktop(hwnd)?;
+    // Inside the desktop, positions are relative to the top left of all monitors
+    window
+        .set_position(PhysicalPosition::new(0, 0))
+        .map_err(|e| e.to_string())?;
+    window
+        .set_size(PhysicalSize::new(
+            (right - left).max(1) as u32,
+            (bottom - top).max(1) as u32,
+        ))
+        .map_err(|e| e.to_string())?;
+    window.show().map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "windows")))]
+fn play(_app: &tauri::AppHandle, _live: &LiveWallpaper) -> Result<(), WallyError> {
+    Err(WallyError::UnsupportedDesktop(
+        "Live wallpapers aren't supported on this platform".to_string(),
+    ))
+}
+
+/// Make a window a child of the WorkerW window Explorer draws between the wallpaper
+/// and the desktop icons
+#[cfg(target_os = "windows")]
+fn attach_to_desktop(hwnd: isize) -> Result<(), WallyError> {
+    use windows::core::{w, PCWSTR};
+    use windows::Win32::Foundation::{HWND, LPARAM, WPARAM};
+    use windows::Win32::UI::WindowsAndMessaging::{
+        EnumWindows, FindWindowExW, FindWindowW, SendMessageTimeoutW, SetParent, SMTO_NORMAL,
+    };
+
+    unsafe {
+        let progman = FindWindowW(w!("Progman"), PCWSTR::null())
+            .map_err(|e| format!("Desktop window not found: {}", e))?;
+        // Undocumented message asking Explorer to create the WorkerW
+        let _ = SendMessageTimeoutW(
+            progman,
+            0x052C,
+            WPARAM(0),
+            LPARAM(0),
+            SMTO_NORMAL,
+            1000,
+            None,
+        );
+
+        let mut workerw = HWND::default();
+        let _ = EnumWindows(
+            Some(find_workerw),
+            LPARAM(&mut workerw as *mut HWND as isize),
+        );
+        // Windows 11 24H2 keeps the WorkerW inside Progman instead
+        if workerw.is_invalid() {
+            workerw = FindWindowExW(progman, HWND::default(), w!("WorkerW"), PCWSTR::null())
+                .unwrap_or_default();
+        }
+        if workerw.is_invalid() {
+            return Err("Desktop WorkerW window not found".into());
+        }
+        SetParent(HWND(hwnd as *mut _), workerw)
+            .map_err(|e| format!("Failed to place the window behind the desktop icons: {}", e))?;
+    }
+    Ok(())
+}
+
+/// `EnumWindows` callback storing the WorkerW that follows the window holding the
+/// desktop icons
+#[cfg(target_os = "windows")]
+unsafe extern "system" fn find_workerw(
+    hwnd: windows::Win32::Foundation::HWND,
+    lparam: windows::Win32::Foundation::LPARAM,
+) -> windows::Win32::Foundation::BOOL {
+    use windows::core::{w, PCWSTR};
+    use windows::Win32::Foundation::HWND;
+    use windows::Win32::UI::WindowsAndMessaging::FindWindowExW;
+
+    let icons = FindWindowExW(
+        hwnd,
+        HWND::default(),
+        w!("SHELLDLL_DefView"),
+        PCWSTR::null(),
+    )
+    .unwrap_or_default();
+    if !icons.is_invalid() {
+        *(lparam.0 as *mut HWND) =
+            FindWindowExW(HWND::default(), hwnd, w!("WorkerW"), PCWSTR::null()).unwrap_or_default();
+    }
+    true.into()
+}
@@ -0,0 +1,147 @@
+//! Log output to stderr and daily rotated files under the config dir, and reading
+//! recent entries back for the in-app viewer
+
+use crate::WallyError;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+use tracing::Level;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{Builder, Rotation};
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+
+const LOG_FILE_PREFIX: &str = "wally";
+const LOG_FILE_SUFFIX: &str = "log";
+
+/// Days of log files kept before the oldest is deleted
+const MAX_LOG_FILES: usize = 7;
+
+/// Keeps the background log writer alive, flushing it when the process exits
+static FILE_GUARD: OnceLock<WorkerGuard> = OnceLock::new();
+
+/// Send our own logs at debug level and other crates' warnings to stderr and to
+/// the log files in `log_dir`
+pub fn init(log_dir: &Path) {
+    let filter = Targets::new()
+        .with_target("wally", Level::DEBUG)
+        .with_target("unsplash_wally_lib", Level::DEBUG)
+        .with_target("wally_core", Level::DEBUG)
+        .with_default(Level::WARN);
+
+    let file_layer = match Builder::new()
+        .rotation(Rotation::DAILY)
+        .filename_prefix(LOG_FILE_PREFIX)
+        .filename_suffix(LOG_FILE_SUFFIX)
+        .max_log_files(MAX_LOG_FILES)
+        .build(log_dir)
+    {
+        Ok(appender) => {
+            let (writer, guard) = tracing_appender::non_blocking(appender);
+            let _ = FILE_GUARD.set(guard);
+            Some(
+                tracing_subscriber::fmt::layer()
+                    .with_ansi(false)
+                    .with_writer(writer),
+            )
+        }
+        Err(e) => {
+            eprintln!("[wally] Failed to open log file: {}", e);
+            None
+        }
+    };
+
+    let _ = tracing_subscriber::registry()
+        .with(filter)
+        .with(tracing_subscriber::fmt::layer().with_writer(std::io::stderr))
+        .with(file_layer)
+        .try_init();
+}
+
+/// A single log line, as shown in the in-app viewer
+#[derive(Debug, Clone, Serialize)]
+pub struct LogEntry {
+    pub timestamp: String,
+    pub level: String,
+    pub target: String,
+    pub message: String,
+}
+
+/// Parse a line written by the fmt layer: `<timestamp> <LEVEL> <target>: <message>`
+fn parse_line(line: &str) -> Option<LogEntry> {
+    let (timestamp, rest) = line.split_once(' ')?;
+    chrono::DateTime::parse_from_rfc3339(timestamp).ok()?;
+    let (level, rest) = rest.trim_start().split_once(' ')?;
+    let level: Level = level.parse().ok()?;
+    let (target, message) = rest.split_once(": ")?;
+    Some(LogEntry {
+        timestamp: timestamp.to_string(),
+        level: level.to_string(),
+        target: target.to_string(),
+        message: message.to_string(),
+    })
+}
+
+/// The last `limit` entries at `level` or more severe, oldest first
+pub fn recent_logs(
+    log_dir: &Path,
+    level: Option<&str>,
+    limit: usize,
+) -> Result<Vec<LogEntry>, WallyError> {
+    let min_level = match level {
+        Some(level) => level
+            .parse::<Level>()
+            .map_err(|_| WallyError::Other(format!("Unknown log level `{}`", level)))?,
+        None => Level::TRACE,
+    };
+    if limit == 0 {
+        return Ok(Vec::new());
+    }
+
+    // File names end in the date, so sorting by name keeps them in order
+    let mut files: Vec<_> = fs::read_dir(log_dir)?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with(LOG_FILE_PREFIX))
+        })
+        .collect();
+    files.sort();
+
+    let mut entries = VecDeque::new();
+    let mut last_matched = false;
+    for file in files {
+        let Ok(content) = fs::read_to_string(&file) else {
+            continue;
+        };
+        for line in content.lines() {
+            match parse_line(line) {
+                Some(entry) => {
+                    let matches = entry.level.parse::<Level>().is_ok_and(|l| l <= min_level);
+                    last_matched = matches;
+                    if matches {
+                        if entries.len() == limit {
+                            entries.pop_front();
+                        }
+                        entries.push_back(entry);
+                    }
+                }
+                // Continuation of a multi-line message
+                None if last_matched => {
+                    if let Some(entry) = entries.back_mut() {
+                        entry.message.push('\n');
+                        entry.message.push_str(line);
+                    }
+                }
+                None => {}
+            }
+        }
+    }
+
+    Ok(entries.into())
+}
@@ -0,0 +1,241 @@
+//! Session triggers: changing the wallpaper when the screen is unlocked or the user
+//! logs in, from WTS session notifications on Windows, logind on Linux and
+//! workspace notifications on macOS
+
+use crate::{spawn_change_now, AppState};
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::Manager;
+use tracing::{info, warn};
+
+pub enum SessionEvent {
+    Unlock,
+    /// Wally started with the session, or the user switched back to it
+    Login,
+}
+
+/// Switching back to a locked session reports both events, one change is enough
+const TRIGGER_DEBOUNCE: Duration = Duration::from_secs(10);
+
+static LAST_TRIGGER: Mutex<Option<Instant>> = Mutex::new(None);
+
+/// Change the wallpaper if the settings ask for it on this event
+pub fn trigger(app: &tauri::AppHandle, event: SessionEvent) {
+    let state = app.state::<AppState>();
+    let Ok(settings) = state.settings.lock().map(|settings| settings.clone()) else {
+        return;
+    };
+    let (enabled, source) = match event {
+        SessionEvent::Unlock => (settings.change_on_unlock, "unlock"),
+        SessionEvent::Login => (settings.change_on_login, "login"),
+    };
+    if !enabled {
+        return;
+    }
+    if state.rotation_paused.load(Ordering::SeqCst) {
+        info!(target: "wally::session", "Session {}, but rotation is paused", source);
+        return;
+    }
+    if let Ok(mut last) = LAST_TRIGGER.lock() {
+        if last.is_some_and(|last| last.elapsed() < TRIGGER_DEBOUNCE) {
+            return;
+        }
+        *last = Some(Instant::now());
+    }
+
+    info!(target: "wally::session", "Session {}, changing wallpaper", source);
+    spawn_change_now(app, source);
+}
+
+#[cfg(target_os = "windows")]
+static WATCHED_APP: std::sync::OnceLock<tauri::AppHandle> = std::sync::OnceLock::new();
+
+#[cfg(target_os = "windows")]
+unsafe extern "system" fn window_proc(
+    hwnd: windows::Win32::Foundation::HWND,
+    message: u32,
+    wparam: windows::Win32::Foundation::WPARAM,
+    lparam: windows::Win32::Foundation::LPARAM,
+) -> windows::Win32::Foundation::LRESULT {
+    use windows::Win32::UI::WindowsAndMessaging::{
+        DefWindowProcW, WM_WTSSESSION_CHANGE, WTS_CONSOLE_CONNECT, WTS_REMOTE_CONNECT,
+        WTS_SESSION_UNLOCK,
+    };
+
+    if message == WM_WTSSESSION_CHANGE {
+        let event = match wparam.0 as u32 {
+            WTS_SESSION_UNLOCK => Some(SessionEvent::Unlock),
+            WTS_CONSOLE_CONNECT | WTS_REMOTE_CONNECT => Some(SessionEvent::Login),
+            _ => None,
+        };
+        if let (Some(event), Some(app)) = (event, WATCHED_APP.get()) {
+            trigger(app, event);
+        }
+        return windows::Win32::Foundation::LRESULT(0);
+    }
+    DefWindowProcW(hwnd, message, wparam, lparam)
+}
+
+/// Receive session notifications on a hidden message-only window
+#[cfg(target_os = "windows")]
+fn run_message_loop() -> windows::core::Result<()> {
+    use windows::core::w;
+    use windows::Win32::System::LibraryLoader::GetModuleHandleW;
+    use windows::Win32::System::RemoteDesktop::{
+        WTSRegisterSessionNotification, NOTIFY_FOR_THIS_SESSION,
+    };
+    use windows::Win32::UI::WindowsAndMessaging::{
+        CreateWindowExW, DispatchMessageW, GetMessageW, RegisterClassW, HWND_MESSAGE, MSG,
+        WINDOW_EX_STYLE, WINDOW_STYLE, WNDCLASSW,
+    };
+
+    unsafe {
+        let instance = GetModuleHandleW(None)?;
+        let class = WNDCLASSW {
+            lpfnWndProc: Some(window_proc),
+            hInstance: instance.into(),
+            lpszClassName: w!("WallySessionWatcher"),
+            ..Default::default()
+        };
+        if RegisterClassW(&class) == 0 {
+            return Err(windows::core::Error::from_win32());
+        }
+        let hwnd = CreateWindowExW(
+            WINDOW_EX_STYLE(0),
+            w!("WallySessionWatcher"),
+            w!(""),
+            WINDOW_STYLE(0),
+            0,
+            0,
+            0,
+            0,
+            HWND_MESSAGE,
+            None,
+            instance,
+            None,
+        )?;
+        WTSRegisterSessionNotification(hwnd, NOTIFY_FOR_THIS_SESSION)?;
+
+        let mut message = MSG::default();
+        while GetMessageW(&mut message, None, 0, 0).as_bool() {
+            DispatchMessageW(&message);
+        }
+    }
+    Ok(())
+}
+
+/// Listen for unlocks and session switches for as long as the app runs
+#[cfg(target_os = "windows")]
+pub fn watch(app: &tauri::AppHandle) {
+    if WATCHED_APP.set(app.clone()).is_err() {
+        return;
+    }
+    std::thread::spawn(|| {
+        if let Err(e) = run_message_loop() {
+            warn!(target: "wally::session", "Failed to watch session events: {}", e);
+        }
+    });
+}
+
+/// Follow our logind session's `LockedHint` and `Active` properties
+#[cfg(target_os = "linux")]
+async fn watch_logind(app: tauri::AppHandle) -> zbus::Result<()> {
+    use futures_util::StreamExt;
+    use zbus::zvariant::{OwnedObjectPath, Value};
+
+    let connection = zbus::Connection::system().await?;
+    let manager = zbus::Proxy::new(
+        &connection,
+        "org.freedesktop.login1",
+        "/org/freedesktop/login1",
+        "org.freedesktop.login1.Manager",
+    )
+    .await?;
+    let session: OwnedObjectPath = match manager
+        .call("GetSessionByPID", &(std::process::id(),))
+        .await
+    {
+        Ok(session) => session,
+        // Apps started from a systemd user service are outside the session
+        Err(_) => manager.call("GetSession", &("auto",)).await?,
+    };
+    let properties = zbus::fdo::PropertiesProxy::builder(&connection)
+        .destination("org.freedesktop.login1")?
+        .path(session)?
+        .build()
+        .await?;
+
+    let mut changes = properties.receive_properties_changed().await?;
+    while let Some(change) = changes.next().await {
+        let Ok(args) = change.args() else {
+            continue;
+        };
+        let changed = args.changed_properties();
+        if matches!(changed.get("LockedHint"), Some(Value::Bool(false))) {
+            trigger(&app, SessionEvent::Unlock);
+        }
+        if matches!(changed.get("Active"), Some(Value::Bool(true))) {
+            trigger(&app, SessionEvent::Login);
+        }
+    }
+    Ok(())
+}
+
+/// Listen for unlocks and session switches for as long as the app runs
+#[cfg(target_os = "linux")]
+pub fn watch(app: &tauri::AppHandle) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        if let Err(e) = watch_logind(app).await {
+            warn!(target: "wally::session", "Failed to watch logind session: {}", e);
+        }
+    });
+}
+
+/// Listen for unlocks and session switches for as long as the app runs
+#[cfg(target_os = "macos")]
+pub fn watch(app: &tauri::AppHandle) {
+    use block2::RcBlock;
+    use objc2_app_kit::{NSWorkspace, NSWorkspaceSessionDidBecomeActiveNotification};
+    use objc2_foundation::{NSDistributedNotificationCenter, NSNotification, NSString};
+    use std::ptr::NonNull;
+
+    let app_handle = app.clone();
+    let result = app.run_on_main_thread(move || {
+        let unlock_app = app_handle.clone();
+        let on_unlock = RcBlock::new(move |_: NonNull<NSNotification>| {
+            trigger(&unlock_app, SessionEvent::Unlock)
+        });
+        let on_login = RcBlock::new(move |_: NonNull<NSNotification>| {
+            trigger(&app_handle, SessionEvent::Login)
+        });
+        let unlocked = NSString::from_str("com.apple.screenIsUnlocked");
+        // SAFETY: the blocks only capture app handles, which can be used from any thread.
+        // The observers are kept for the lifetime of the app.
+        unsafe {
+            std::mem::forget(
+                NSDistributedNotificationCenter::defaultCenter()
+                    .addObserverForName_object_queue_usingBlock(
+                        Some(&unlocked),
+                        None,
+                        None,
+                        &on_unlock,
+                    ),
+            );
+            std::mem::forget(
+                NSWorkspace::sharedWorkspace()
+                    .notificationCenter()
+                    .addObserverForName_object_queue_usingBlock(
+                        Some(NSWorkspaceSessionDidBecomeActiveNotification),
+                        None,
+                        None,
+                        &on_login,
+                    ),
+            );
+        }
+    });
+    if let Err(e) = result {
+        warn!(target: "wally::session", "Failed to watch session events: {}", e);
+    }
+}
@@ -0,0 +1,166 @@
+//! The system share sheet: `NSSharingServicePicker` on macOS and the Share UI on
+//! Windows. Linux desktops have no equivalent.
+
+use crate::WallyError;
+use serde::Deserialize;
+use std::path::PathBuf;
+
+/// Which part of the current wallpaper to share
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ShareContent {
+    /// The photo's page on unsplash.com
+    Link,
+    /// The downloaded image file
+    File,
+}
+
+/// What to hand to the share sheet
+#[cfg_attr(target_os = "linux", allow(dead_code))]
+pub enum ShareItem {
+    /// A web page, such as the photo on unsplash.com
+    Link {
+        url: String,
+        title: String,
+    },
+    File(PathBuf),
+}
+
+#[cfg(target_os = "macos")]
+pub fn share(window: &tauri::WebviewWindow, item: ShareItem) -> Result<(), WallyError> {
+    use objc2::rc::Retained;
+    use objc2::runtime::AnyObject;
+    use objc2::AnyThread;
+    use objc2_app_kit::{NSSharingServicePicker, NSView};
+    use objc2_foundation::{NSArray, NSPoint, NSRect, NSRectEdge, NSSize, NSString, NSURL};
+
+    let view = window.ns_view().map_err(|e| e.to_string())? as usize;
+    // Foundation objects can't cross threads, so only strings move to the main thread
+    let (title, url, is_file) = match item {
+        ShareItem::Link { url, title } => (Some(title), url, false),
+        ShareItem::File(path) => (None, path.to_string_lossy().into_owned(), true),
+    };
+    if !is_file && NSURL::URLWithString(&NSString::from_str(&url)).is_none() {
+        return Err(format!("Invalid link: {}", url).into());
+    }
+
+    window
+        .run_on_main_thread(move || {
+            let url = if is_file {
+                NSURL::fileURLWithPath(&NSString::from_str(&url))
+            } else {
+                match NSURL::URLWithString(&NSString::from_str(&url)) {
+                    Some(url) => url,
+                    None => return,
+                }
+            };
+            let mut items: Vec<Retained<AnyObject>> = Vec::new();
+            // Messages and Mail put the text in front of the link
+            if let Some(title) = title {
+                items.push(NSString::from_str(&title).into());
+            }
+            items.push(url.into());
+            let items = NSArray::from_retained_slice(&items);
+
+            // SAFETY: the window, and so its content view, outlives this call on the main thread
+            let view = unsafe { &*(view as *const NSView) };
+            let picker = unsafe {
+                NSSharingServicePicker::initWithItems(NSSharingServicePicker::alloc(), &items)
+            };
+            // Drop the picker down from the top center of the window
+            let bounds = view.bounds();
+            let anchor = NSRect::new(
+                NSPoint::new(bounds.size.width / 2.0, bounds.size.height - 40.0),
+                NSSize::new(1.0, 1.0),
+            );
+            picker.showRelativeToRect_ofView_preferredEdge(anchor, view, NSRectEdge::MinY);
+        })
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Registration of the handler that fills in the shared data, replaced on every share
+#[cfg(target_os = "windows")]
+static DATA_REQUESTED: std::sync::Mutex<Option<windows::Foundation::EventRegistrationToken>> =
+    std::sync::Mutex::new(None);
+
+#[cfg(target_os = "windows")]
+pub fn share(window: &tauri::WebviewWindow, item: ShareItem) -> Result<(), WallyError> {
+    use windows::core::{AgileReference, Interface, HSTRING};
+    use windows::ApplicationModel::DataTransfer::{DataRequestedEventArgs, DataTransferManager};
+    use windows::Foundation::Collections::IIterable;
+    use windows::Foundation::{TypedEventHandler, Uri};
+    use windows::Storage::{IStorageItem, StorageFile};
+    use windows::Win32::Foundation::HWND;
+    use windows::Win32::UI::Shell::IDataTransferManagerInterop;
+
+    let hwnd = window.hwnd().map_err(|e| e.to_string())?.0 as isize;
+    let (title, file) = match &item {
+        ShareItem::Link { title, .. } => (title.clone(), None),
+        ShareItem::File(path) => {
+            // Resolve the file here, the share UI thread must not block on it
+            let file = StorageFile::GetFileFromPathAsync(&HSTRING::from(path.as_os_str()))
+                .and_then(|operation| operation.get())
+                .and_then(|file| AgileReference::new(&file))
+                .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+            let title = path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            (title, Some(file))
+        }
+    };
+    let link = match item {
+        ShareItem::Link { url, .. } => Some(url),
+        ShareItem::File(_) => None,
+    };
+
+    window
+        .run_on_main_thread(move || {
+            let result = (|| -> windows::core::Result<()> {
+                let hwnd = HWND(hwnd as *mut _);
+                let interop =
+                    windows::core::factory::<DataTransferManager, IDataTransferManagerInterop>()?;
+                let manager: DataTransferManager = unsafe { interop.GetForWindow(hwnd)? };
+                if let Ok(mut registered) = DATA_REQUESTED.lock() {
+                    if let Some(token) = registered.take() {
+                        let _ = manager.RemoveDataRequested(token);
+                    }
+                    let token = manager.DataRequested(&TypedEventHandler::new(
+                        move |_, args: &Option<DataRequestedEventArgs>| {
+                            let Some(args) = args else {
+                                return Ok(());
+                            };
+                            let data = args.Request()?.Data()?;
+                            data.Properties()?
+                                .SetTitle(&HSTRING::from(title.as_str()))?;
+                            if let Some(link) = &link {
+                                data.SetWebLink(&Uri::CreateUri(&HSTRING::from(link.as_str()))?)?;
+                            }
+                            if let Some(file) = &file {
+                                let file = file.resolve()?;
+                                let items =
+                                    IIterable::<IStorageItem>::try_from(vec![Some(file.cast()?)])?;
+                                data.SetStorageItemsReadOnly(&items)?;
+                            }
+                            Ok(())
+                        },
+                    ))?;
+                    *registered = Some(token);
+                }
+                unsafe { interop.ShowShareUIForWindow(hwnd) }
+            })();
+            if let Err(e) = result {
+                tracing::warn!("Failed to open the share sheet: {}", e);
+            }
+        })
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+pub fn share(_window: &tauri::WebviewWindow, _item: ShareItem) -> Result<(), WallyError> {
+    Err(WallyError::UnsupportedDesktop(
+        "Linux desktops have no share sheet".to_string(),
+    ))
+}
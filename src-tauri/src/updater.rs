@@ -0,0 +1,147 @@
+//! Update checks against the stable or beta release channel, announcing new versions
+//! in the tray and with a notification
+
+use crate::{refresh_tray_menu, shutdown_then, AppState, WallyError};
+use serde::Serialize;
+use std::time::Duration;
+use tauri::{Emitter, Manager};
+use tauri_plugin_notification::NotificationExt;
+use tauri_plugin_updater::{Update, UpdaterExt};
+use tracing::{info, warn};
+use wally_core::settings::updater_pubkey;
+
+/// Each channel's update manifest is attached to this release
+const MANIFEST_RELEASE: &str = "https://github.com/NiHaiden/wally/releases/download/updater";
+
+/// Wait after startup before the first check, so it doesn't slow down logging in
+const STARTUP_DELAY: Duration = Duration::from_secs(60);
+
+/// How often the background check runs
+const CHECK_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// A newer version found by `check`
+#[derive(Debug, Clone, Serialize)]
+pub struct AvailableUpdate {
+    pub version: String,
+    pub current_version: String,
+    /// Release notes, if the manifest has any
+    pub notes: Option<String>,
+    pub date: Option<String>,
+}
+
+impl From<&Update> for AvailableUpdate {
+    fn from(update: &Update) -> Self {
+        Self {
+            version: update.version.clone(),
+            current_version: update.current_version.clone(),
+            notes: update.body.clone(),
+            date: update.date.map(|date| date.to_string()),
+        }
+    }
+}
+
+/// Manifest listing the latest version on `channel`, "stable" or "beta"
+fn manifest_url(channel: &str) -> String {
+    let channel = if channel == "beta" { "beta" } else { "stable" };
+    format!("{}/{}.json", MANIFEST_RELEASE, channel)
+}
+
+/// Look for a newer version on the configured channel, remembering it for the tray
+/// and `install`. A version is only announced the first time it's found.
+pub async fn check(app: &tauri::AppHandle) -> Result<Option<AvailableUpdate>, WallyError> {
+    let Some(pubkey) = updater_pubkey() else {
+        return Err("This build can't verify updates, install a release build to get them".into());
+    };
+    let state = app.state::<AppState>();
+    let channel = state.settings.lock()?.update_channel.clone();
+    let url = tauri::Url::parse(&manifest_url(&channel)).map_err(|e| e.to_string())?;
+
+    info!(target: "wally::updater", "Checking for updates on the {} channel", channel);
+    let update = app
+        .updater_builder()
+        .pubkey(pubkey)
+        .endpoints(vec![url])
+        .and_then(|builder| builder.build())
+        .map_err(|e| e.to_string())?
+        .check()
+        .await
+        .map_err(|e| format!("Failed to check for updates: {}", e))?;
+
+    let available = update.as_ref().map(AvailableUpdate::from);
+    let previous =
+        std::mem::replace(&mut *state.update.lock()?, update).map(|update| update.version);
+    refresh_tray_menu(app);
+
+    match &available {
+        Some(available) if previous.as_ref() != Some(&available.version) => {
+            info!(target: "wally::updater", "Version {} is available", available.version);
+            let _ = app.emit("update-available", available);
+            notify_update_available(app, available);
+        }
+        Some(_) => {}
+        None => info!(target: "wally::updater", "Wally is up to date"),
+    }
+    Ok(available)
+}
+
+/// Download the update found by the last check, then shut down as when quitting,
+/// install it and restart into it
+pub async fn install(app: &tauri::AppHandle) -> Result<(), WallyError> {
+    let update = app
+        .state::<AppState>()
+        .update
+        .lock()?
+        .clone()
+        .ok_or("No update available, check for updates first")?;
+
+    info!(target: "wally::updater", "Downloading version {}", update.version);
+    let bytes = update
+        .download(|_, _| {}, || {})
+        .await
+        .map_err(|e| format!("Failed to download the update: {}", e))?;
+
+    // The installer may end the process, so everything is written out first
+    shutdown_then(app.clone(), move |app| {
+        info!(target: "wally::updater", "Installing version {}", update.version);
+        if let Err(e) = update.install(&bytes) {
+            warn!(target: "wally::updater", "Failed to install the update: {}", e);
+        }
+        app.restart();
+    })
+    .await;
+    Ok(())
+}
+
+/// Check for updates shortly after startup and then daily, while automatic checks
+/// are on
+pub async fn watch(app: tauri::AppHandle) {
+    tokio::time::sleep(STARTUP_DELAY).await;
+    loop {
+        let enabled = app
+            .state::<AppState>()
+            .settings
+            .lock()
+            .is_ok_and(|settings| settings.check_for_updates);
+        if enabled && updater_pubkey().is_some() {
+            if let Err(e) = check(&app).await {
+                warn!(target: "wally::updater", "{}", e);
+            }
+        }
+        tokio::time::sleep(CHECK_INTERVAL).await;
+    }
+}
+
+fn notify_update_available(app: &tauri::AppHandle, update: &AvailableUpdate) {
+    if let Err(e) = app
+        .notification()
+        .builder()
+        .title("Update available")
+        .body(format!(
+            "Wally {} is ready to install from the tray menu",
+            update.version
+        ))
+        .show()
+    {
+        warn!(target: "wally::updater", "Failed to show notification: {}", e);
+    }
+}
@@ -0,0 +1,110 @@
+//! In-memory cache of provider API responses with TTL and ETag revalidation
+
+use crate::unsplash::{api_error, send_with_retry};
+use crate::{WallpaperSettings, WallyError};
+use reqwest::header::{ETAG, IF_NONE_MATCH};
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Entries kept before expired ones are evicted
+const MAX_ENTRIES: usize = 256;
+
+struct CacheEntry {
+    etag: Option<String>,
+    body: String,
+    fetched_at: Instant,
+    ttl: Duration,
+}
+
+impl CacheEntry {
+    fn is_fresh(&self) -> bool {
+        self.fetched_at.elapsed() < self.ttl
+    }
+}
+
+#[derive(Default)]
+pub struct ApiCache {
+    entries: Mutex<HashMap<String, CacheEntry>>,
+}
+
+impl ApiCache {
+    /// Send a GET request and parse its JSON body, answering from the cache while the
+    /// entry is fresh and revalidating it with If-None-Match once the TTL has passed.
+    /// `key` identifies the request, normally its full URL including the query, and
+    /// `resource` names what is requested for error messages.
+    pub async fn get_json<T: DeserializeOwned>(
+        &self,
+        key: &str,
+        resource: &str,
+        request: reqwest::RequestBuilder,
+        ttl: Duration,
+        settings: &WallpaperSettings,
+    ) -> Result<T, WallyError> {
+        let etag = {
+            let entries = self.entries.lock()?;
+            match entries.get(key) {
+                Some(entry) if entry.is_fresh() => return parse(&entry.body),
+                Some(entry) => entry.etag.clone(),
+                None => None,
+            }
+        };
+
+        let request = match &etag {
+            Some(etag) => request.header(IF_NONE_MATCH, etag),
+            None => request,
+        };
+        let response = send_with_retry(request, settings).await?;
+
+        if response.status() == StatusCode::NOT_MODIFIED {
+            let mut entries = self.entries.lock()?;
+            if let Some(entry) = entries.get_mut(key) {
+                entry.fetched_at = Instant::now();
+                entry.ttl = ttl;
+                return parse(&entry.body);
+            }
+            return Err("Server reported an unchanged response that is no longer cached".into());
+        }
+
+        if !response.status().is_success() {
+            return Err(api_error(response, resource).await);
+        }
+
+        let etag = response
+            .headers()
+            .get(ETAG)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        let body = response.text().await?;
+        let parsed = parse(&body)?;
+
+        let mut entries = self.entries.lock()?;
+        if entries.len() >= MAX_ENTRIES {
+            entries.retain(|_, entry| entry.is_fresh());
+        }
+        entries.insert(
+            key.to_string(),
+            CacheEntry {
+                etag,
+                body,
+                fetched_at: Instant::now(),
+                ttl,
+            },
+        );
+
+        Ok(parsed)
+    }
+
+    /// Drop every cached response, e.g. after the API key changes
+    pub fn clear(&self) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.clear();
+        }
+    }
+}
+
+fn parse<T: DeserializeOwned>(body: &str) -> Result<T, WallyError> {
+    Ok(serde_json::from_str(body)?)
+}
@@ -0,0 +1,303 @@
+//! Rotate wallpapers on the configured schedule without the app or a webview, for
+//! systemd user services and window-manager-only setups
+
+use std::process::ExitCode;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn, Level};
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use wally_core::rotation::{self, ChangePolicy};
+use wally_core::schedule::{self, schedule_wait};
+use wally_core::storage::{
+    load_current_wallpaper, load_schedule_state, load_settings, save_next_change_at,
+    save_schedule_state,
+};
+use wally_core::unsplash::build_http_client;
+use wally_core::{
+    crash, integrations, metrics, slideshow, system, CurrentWallpaper, WallpaperSettings,
+    WallyError,
+};
+
+const USAGE: &str = "\
+Usage: wally-daemon [--once]
+
+Changes the wallpaper on the schedule in wally's settings.json, without a window.
+Don't run it while the wally app is running, both would rotate the wallpaper.
+
+Options:
+  --once    Change the wallpaper once and exit";
+
+/// How often the settings are re-read and the schedule re-checked while waiting
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Coordinates for the "sun" schedule: the configured ones, or a cached IP geolocation
+async fn resolve_location(
+    client: &reqwest::Client,
+    settings: &WallpaperSettings,
+    cached: &mut Option<schedule::Location>,
+) -> Option<schedule::Location> {
+    if settings.schedule_mode != "sun" {
+        return None;
+    }
+    if let (Some(latitude), Some(longitude)) = (settings.sun_latitude, settings.sun_longitude) {
+        return Some((latitude, longitude));
+    }
+    if cached.is_none() {
+        *cached = rotation::lookup_location(client).await;
+    }
+    *cached
+}
+
+async fn run_integrations(
+    settings: &WallpaperSettings,
+    client: &reqwest::Client,
+    current: &CurrentWallpaper,
+) {
+    metrics::record_change(settings);
+    metrics::submit_due(client, settings).await;
+
+    if !settings.webhook_url.trim().is_empty() {
+        if let Err(e) =
+            integrations::send_webhook(client, settings.webhook_url.trim(), current).await
+        {
+            warn!("Webhook notification failed: {}", e);
+        }
+    }
+
+    let settings = settings.clone();
+    let current = current.clone();
+    let _ = tokio::task::spawn_blocking(move || {
+        integrations::run_post_change_integrations(&settings, &current);
+    })
+    .await;
+}
+
+/// Fetch and set a new wallpaper, falling back to the local cache while offline, or
+/// show the next slide of the slideshow
+async fn change_wallpaper(
+    client: &reqwest::Client,
+    settings: &WallpaperSettings,
+    location: Option<schedule::Location>,
+) -> Result<(), WallyError> {
+    let cancel = CancellationToken::new();
+    let result = if settings.slideshow_enabled {
+        // The slideshow cycles its local folder, there is nothing to download
+        let settings = settings.clone();
+        tokio::task::spawn_blocking(move || slideshow::show_next_slide(&settings))
+            .await
+            .unwrap_or_else(|e| Err(e.to_string().into()))
+    } else {
+        match rotation::prepare_wallpaper(client, settings, location, None, &cancel).await {
+            Ok(prepared) => rotation::apply_prepared_wallpaper(client, settings, prepared).await,
+            Err(e) if !rotation::is_online(client).await => {
+                info!(
+                    target: "wally::daemon",
+                    "Offline ({}), rotating through cached wallpapers",
+                    e
+                );
+                let settings = settings.clone();
+                tokio::task::spawn_blocking(move || {
+                    rotation::set_cached_wallpaper(
+                        rotation::next_cached_wallpaper(&settings)?,
+                        settings.apply_to_all_spaces,
+                    )
+                })
+                .await
+                .unwrap_or_else(|e| Err(e.to_string().into()))
+            }
+            Err(e) => Err(e),
+        }
+    };
+    if let Err(e) = &result {
+        metrics::record_failure(settings, e.code());
+    }
+    let current = result?;
+
+    let mut schedule = load_schedule_state();
+    schedule.last_changed_at = Some(chrono::Utc::now().to_rfc3339());
+    save_schedule_state(&schedule);
+    run_integrations(settings, client, &current).await;
+    Ok(())
+}
+
+async fn run() {
+    // Created once, so a signal arriving while a change runs isn't lost
+    let shutdown = system::shutdown_signal();
+    tokio::pin!(shutdown);
+    // Pick up the schedule where the app or a previous run left off
+    let mut last_change = load_schedule_state().last_changed_at();
+    let mut cached_location = None;
+    let mut announced: Option<chrono::DateTime<chrono::Utc>> = None;
+    // Why a due change is being held, logged once when it starts
+    let mut holding: Option<&str> = None;
+    let mut idle_gate = rotation::IdleGate::default();
+    // Kept when the settings no longer make a valid client, e.g. a mistyped proxy
+    let mut client: Option<reqwest::Client> = None;
+    let mut client_error: Option<String> = None;
+
+    loop {
+        let mut settings = load_settings();
+        crash::set_enabled(settings.crash_reports);
+        match build_http_client(&settings) {
+            Ok(built) => {
+                client = Some(built);
+                client_error = None;
+            }
+            Err(e) => {
+                let e = e.to_string();
+                if client_error.as_ref() != Some(&e) {
+                    error!(target: "wally::daemon", "Invalid network settings: {}", e);
+                    client_error = Some(e);
+                }
+            }
+        }
+        let Some(client) = client.clone() else {
+            tokio::select! {
+                _ = &mut shutdown => return,
+                _ = tokio::time::sleep(CHECK_INTERVAL) => {}
+            }
+            continue;
+        };
+        let now = chrono::Utc::now();
+        let location = resolve_location(&client, &settings, &mut cached_location).await;
+        let (interval, elapsed) = schedule_wait(&settings, last_change, location);
+        let remaining = interval.saturating_sub(elapsed);
+
+        let hold = if !settings.auto_change {
+            Some("Auto-change is disabled in the settings, waiting")
+        } else if !remaining.is_zero() {
+            None
+        } else if schedule::quiet_hours_end(&settings.quiet_hours, now).is_some() {
+            Some("Quiet hours, deferring change")
+        } else if settings.pause_when_presenting
+            && tokio::task::spawn_blocking(system::is_presenting)
+                .await
+                .unwrap_or(false)
+        {
+            Some("Fullscreen app or presentation active, holding change")
+        } else if settings.idle_change_mode != "off"
+            && idle_gate.holds(
+                &settings,
+                tokio::task::spawn_blocking(system::idle_time)
+                    .await
+                    .unwrap_or(None),
+            )
+        {
+            Some("Waiting for the user to be idle before changing")
+        } else {
+            None
+        };
+        if hold != holding {
+            if let Some(reason) = hold {
+                info!(target: "wally::daemon", "{}", reason);
+                announced = None;
+            }
+            holding = hold;
+        }
+
+        if hold.is_none() && remaining.is_zero() {
+            let policy_settings = settings.clone();
+            let policy =
+                tokio::task::spawn_blocking(move || rotation::change_policy(&policy_settings))
+                    .await
+                    .unwrap_or(ChangePolicy::Normal);
+            if policy != ChangePolicy::Normal {
+                settings.quality = "small".to_string();
+            }
+            if !matches!(policy, ChangePolicy::Skip(_)) {
+                let result = tokio::select! {
+                    _ = &mut shutdown => return,
+                    result = change_wallpaper(&client, &settings, location) => result,
+                };
+                match result {
+                    Ok(()) => info!(target: "wally::daemon", "Wallpaper changed"),
+                    Err(e) => error!(target: "wally::daemon", "Failed to change wallpaper: {}", e),
+                }
+            }
+            last_change = Some(chrono::Utc::now());
+            announced = None;
+            idle_gate = rotation::IdleGate::default();
+            continue;
+        }
+
+        // Only log the next change when the schedule moves, not on every check
+        let next = now + chrono::Duration::from_std(remaining).unwrap_or_default();
+        if hold.is_none()
+            && announced.is_none_or(|announced| (announced - next).num_seconds().abs() > 5)
+        {
+            info!(
+                target: "wally::daemon",
+                "Next wallpaper change in {} seconds",
+                remaining.as_secs()
+            );
+            save_next_change_at(remaining);
+            announced = Some(next);
+        }
+
+        let wait = if remaining.is_zero() {
+            CHECK_INTERVAL
+        } else {
+            remaining.min(CHECK_INTERVAL)
+        };
+        tokio::select! {
+            _ = &mut shutdown => return,
+            _ = tokio::time::sleep(wait) => {}
+        }
+    }
+}
+
+fn init_logging() {
+    let filter = Targets::new()
+        .with_target("wally", Level::DEBUG)
+        .with_target("wally_core", Level::DEBUG)
+        .with_target("wally_daemon", Level::DEBUG)
+        .with_default(Level::WARN);
+    let _ = tracing_subscriber::registry()
+        .with(filter)
+        .with(tracing_subscriber::fmt::layer().with_writer(std::io::stderr))
+        .try_init();
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let once = match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        [] => false,
+        ["--once"] => true,
+        _ => {
+            eprintln!("{}", USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    init_logging();
+    crash::install(env!("CARGO_PKG_VERSION"));
+
+    if once {
+        let settings = load_settings();
+        crash::set_enabled(settings.crash_reports);
+        let result = async {
+            let client = build_http_client(&settings)?;
+            let location = resolve_location(&client, &settings, &mut None).await;
+            change_wallpaper(&client, &settings, location).await
+        }
+        .await;
+        return match result {
+            Ok(()) => {
+                let current = load_current_wallpaper();
+                println!("{}", current.local_path.unwrap_or_default());
+                ExitCode::SUCCESS
+            }
+            Err(e) => {
+                eprintln!("wally-daemon: {}", e);
+                ExitCode::FAILURE
+            }
+        };
+    }
+
+    info!(target: "wally::daemon", "Starting headless wallpaper daemon");
+    run().await;
+    info!(target: "wally::daemon", "Wallpaper daemon stopped");
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,69 @@
+//! Control a running wally from scripts and keybinding daemons
+
+use std::process::ExitCode;
+use wally_core::ipc::{self, Request, Status};
+
+const USAGE: &str = "\
+Usage: wallyctl <command>
+
+Commands:
+  next          Change to a new wallpaper
+  previous      Go back to the previous wallpaper
+  pause         Pause rotation, or resume it when paused
+  status        Show the current wallpaper and rotation state
+  set <path>    Set a local image as the wallpaper";
+
+fn print_status(status: &Status) {
+    println!(
+        "wallpaper: {}",
+        status.local_path.as_deref().unwrap_or("none")
+    );
+    if let Some(author) = &status.author {
+        println!("author:    {}", author);
+    }
+    let rotation = match (status.auto_change, status.paused) {
+        (false, _) => "off",
+        (true, true) => "paused",
+        (true, false) => "running",
+    };
+    println!("rotation:  {}", rotation);
+    if let Some(next) = &status.next_change_at {
+        println!("next:      {}", next);
+    }
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let request = match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        ["next"] => Request::Next,
+        ["previous"] => Request::Previous,
+        ["pause"] => Request::Pause,
+        ["status"] => Request::Status,
+        ["set", path] => Request::Set {
+            path: path.to_string(),
+        },
+        _ => {
+            eprintln!("{}", USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    let response = match ipc::send(&request) {
+        Ok(response) => response,
+        Err(e) => {
+            eprintln!("wallyctl: could not reach wally, is it running? ({})", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    if !response.ok {
+        eprintln!(
+            "wallyctl: {}",
+            response.message.as_deref().unwrap_or("request failed")
+        );
+        return ExitCode::FAILURE;
+    }
+    if let (Request::Status | Request::Pause, Some(status)) = (&request, &response.status) {
+        print_status(status);
+    }
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,71 @@
+//! Collages of portrait photos side by side, for screens too wide for a single photo
+
+use crate::processing::{fill, parse_hex_color, save_image};
+use crate::{ScreenResolution, UnsplashImage, WallpaperSettings};
+use image::imageops;
+use image::{DynamicImage, Rgba, RgbaImage};
+use std::path::{Path, PathBuf};
+
+/// Screens at least this many times wider than tall get a collage, e.g. 32:9
+/// ultrawides or two monitors spanned side by side
+const MIN_ASPECT_RATIO: f64 = 2.2;
+
+/// Whether random wallpapers should be collages on this screen
+pub fn wanted(settings: &WallpaperSettings, screen: ScreenResolution) -> bool {
+    settings.collage_enabled
+        && screen.height > 0
+        && f64::from(screen.width) / f64::from(screen.height) >= MIN_ASPECT_RATIO
+}
+
+/// Number of photos in a collage, 2 or 3
+pub fn photo_count(settings: &WallpaperSettings) -> usize {
+    settings.collage_photos.clamp(2, 3) as usize
+}
+
+/// Credit line naming every photographer in the collage
+pub fn attribution(images: &[UnsplashImage]) -> String {
+    let names: Vec<&str> = images
+        .iter()
+        .map(|image| image.user.name.as_str())
+        .collect();
+    let names = match names.split_last() {
+        Some((last, rest)) if !rest.is_empty() => format!("{} and {}", rest.join(", "), last),
+        _ => names.concat(),
+    };
+    format!("Photos by {} on Unsplash", names)
+}
+
+/// Lay the photos out side by side on a canvas the size of the screen, each cropped to
+/// fill its slot, with `collage_gap` pixels of `collage_background` between and around
+/// them, and save it to `out`
+pub fn compose(
+    photos: &[PathBuf],
+    out: &Path,
+    screen: ScreenResolution,
+    settings: &WallpaperSettings,
+) -> Result<(), String> {
+    if photos.is_empty() || screen.width == 0 || screen.height == 0 {
+        return Err("Nothing to compose".to_string());
+    }
+    let count = photos.len() as u32;
+    // Gaps never take more than a quarter of the screen
+    let gap = settings
+        .collage_gap
+        .min(screen.width / (4 * (count + 1)))
+        .min(screen.height / 8);
+    let slot_width = (screen.width - gap * (count + 1)) / count;
+    let slot_height = screen.height - gap * 2;
+
+    let [r, g, b] = parse_hex_color(&settings.collage_background).unwrap_or([0, 0, 0]);
+    let mut canvas = RgbaImage::from_pixel(screen.width, screen.height, Rgba([r, g, b, 255]));
+    for (index, photo) in photos.iter().enumerate() {
+        let image =
+            image::open(photo).map_err(|e| format!("Failed to open {}: {}", photo.display(), e))?;
+        let image = fill(image, slot_width, slot_height, settings.smart_crop);
+        let x = gap + index as u32 * (slot_width + gap);
+        imageops::overlay(&mut canvas, &image.to_rgba8(), x.into(), gap.into());
+    }
+
+    save_image(&DynamicImage::ImageRgba8(canvas), out)
+        .map_err(|e| format!("Failed to save collage: {}", e))
+}
@@ -0,0 +1,113 @@
+//! Opt-in crash reports: panics are written to the crash folder with a backtrace, so
+//! a crash on one desktop setup can be attached to a bug report
+
+use crate::paths::get_crash_dir;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::backtrace::Backtrace;
+use std::fs;
+use std::panic::PanicHookInfo;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::SystemTime;
+
+/// Crash reports kept, oldest removed first
+const MAX_REPORTS: usize = 10;
+
+/// Mirrors the `crash_reports` setting, read from the panic hook
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// What was known about a panic when it happened
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrashReport {
+    /// RFC 3339 time of the panic
+    pub timestamp: String,
+    pub app_version: String,
+    pub os: String,
+    pub arch: String,
+    /// `XDG_CURRENT_DESKTOP` on Linux, where most setter differences come from
+    pub desktop: Option<String>,
+    /// Name of the panicking thread, if it has one
+    pub thread: Option<String>,
+    pub message: String,
+    /// `file:line:column` of the panic
+    pub location: Option<String>,
+    pub backtrace: String,
+}
+
+/// Record panics as crash reports while they're turned on, after the default hook
+/// has printed them. Call once at startup.
+pub fn install(app_version: &'static str) {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        default_hook(info);
+        if ENABLED.load(Ordering::SeqCst) {
+            let report = report(info, app_version);
+            match save(&report) {
+                Ok(path) => eprintln!("[wally] Crash report written to {}", path.display()),
+                Err(e) => eprintln!("[wally] Failed to write crash report: {}", e),
+            }
+        }
+    }));
+}
+
+/// Turn crash reports on or off, following the `crash_reports` setting
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::SeqCst);
+}
+
+fn report(info: &PanicHookInfo, app_version: &str) -> CrashReport {
+    let payload = info.payload();
+    let message = payload
+        .downcast_ref::<&str>()
+        .map(|message| message.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "Unknown panic".to_string());
+    CrashReport {
+        timestamp: Utc::now().to_rfc3339(),
+        app_version: app_version.to_string(),
+        os: std::env::consts::OS.to_string(),
+        arch: std::env::consts::ARCH.to_string(),
+        desktop: std::env::var("XDG_CURRENT_DESKTOP").ok(),
+        thread: std::thread::current().name().map(str::to_string),
+        message,
+        location: info.location().map(|location| location.to_string()),
+        backtrace: Backtrace::force_capture().to_string(),
+    }
+}
+
+fn save(report: &CrashReport) -> std::io::Result<PathBuf> {
+    let path = get_crash_dir().join(format!(
+        "crash-{}.json",
+        Utc::now().format("%Y%m%d-%H%M%S%.3f")
+    ));
+    fs::write(&path, serde_json::to_vec_pretty(report)?)?;
+    for (old, _) in reports().into_iter().skip(MAX_REPORTS) {
+        let _ = fs::remove_file(old);
+    }
+    Ok(path)
+}
+
+/// Crash report files, newest first
+fn reports() -> Vec<(PathBuf, SystemTime)> {
+    let Ok(entries) = fs::read_dir(get_crash_dir()) else {
+        return Vec::new();
+    };
+    let mut reports: Vec<(PathBuf, SystemTime)> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .filter_map(|path| {
+            let modified = fs::metadata(&path).ok()?.modified().ok()?;
+            Some((path, modified))
+        })
+        .collect();
+    reports.sort_by_key(|(_, modified)| std::cmp::Reverse(*modified));
+    reports
+}
+
+/// The most recent crash report, if any was written
+pub fn last_report() -> Option<CrashReport> {
+    let (path, _) = reports().into_iter().next()?;
+    serde_json::from_slice(&fs::read(path).ok()?).ok()
+}
@@ -0,0 +1,557 @@
+//! Downloading wallpaper images to disk and keeping the download folder tidy
+
+use crate::platform::{is_dark_mode, is_kde};
+use crate::rotation::stable_hash;
+use crate::storage::{load_current_wallpaper, load_pins};
+use crate::unsplash::send_with_retry;
+use crate::{processing, system, ScreenResolution, UnsplashImage, WallpaperSettings, WallyError};
+use futures_util::StreamExt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tokio::io::AsyncWriteExt;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+/// Free space left on top of a download for post-processing and transcoding
+const MIN_FREE_SPACE: u64 = 64 * 1024 * 1024;
+
+/// Make sure `dir` can hold a response of the advertised size plus some headroom.
+/// Filesystems whose free space can't be determined are assumed to have room.
+fn check_disk_space(dir: &Path, response: &reqwest::Response) -> Result<(), WallyError> {
+    let required = response.content_length().unwrap_or(0) + MIN_FREE_SPACE;
+    match system::available_space(dir) {
+        Some(available) if available < required => Err(WallyError::InsufficientSpace {
+            required,
+            available,
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Stream a response body to disk through a temporary file that is renamed into
+/// place once complete, so large images are never buffered in memory and a failed
+/// download never leaves a truncated file behind
+async fn download_to_file(
+    response: reqwest::Response,
+    file_path: &Path,
+    cancel: &CancellationToken,
+) -> Result<(), WallyError> {
+    let response = response
+        .error_for_status()
+        .map_err(|e| WallyError::DownloadFailed(e.to_string()))?;
+    if let Some(dir) = file_path.parent() {
+        check_disk_space(dir, &response)?;
+    }
+
+    let mut temp_name = file_path.as_os_str().to_owned();
+    temp_name.push(".part");
+    let temp_path = PathBuf::from(temp_name);
+
+    let result = async {
+        let mut file = tokio::fs::File::create(&temp_path).await?;
+        let mut stream = response.bytes_stream();
+        loop {
+            let chunk = tokio::select! {
+                _ = cancel.cancelled() => return Err(WallyError::Cancelled),
+                chunk = stream.next() => chunk,
+            };
+            let Some(chunk) = chunk else {
+                break;
+            };
+            let chunk = chunk.map_err(|e| WallyError::DownloadFailed(e.to_string()))?;
+            file.write_all(&chunk).await?;
+        }
+        file.flush().await?;
+        tokio::fs::rename(&temp_path, file_path).await?;
+        Ok(())
+    }
+    .await;
+
+    if result.is_err() {
+        let _ = tokio::fs::remove_file(&temp_path).await;
+    }
+    result
+}
+
+/// File extension for an image content type served by the CDN
+fn extension_for_content_type(content_type: &str) -> Option<&'static str> {
+    let mime = content_type.split(';').next()?.trim().to_ascii_lowercase();
+    match mime.as_str() {
+        "image/jpeg" | "image/jpg" | "image/pjpeg" => Some("jpg"),
+        "image/png" => Some("png"),
+        "image/webp" => Some("webp"),
+        "image/avif" => Some("avif"),
+        "image/gif" => Some("gif"),
+        "image/bmp" => Some("bmp"),
+        "image/tiff" => Some("tiff"),
+        "image/heic" | "image/heif" => Some("heic"),
+        _ => None,
+    }
+}
+
+pub fn is_image_extension(ext: &str) -> bool {
+    matches!(
+        ext.to_ascii_lowercase().as_str(),
+        "jpg" | "jpeg" | "png" | "webp" | "avif" | "gif" | "bmp" | "tiff" | "heic"
+    )
+}
+
+/// Whether the current platform's wallpaper backend can display this format directly
+fn platform_supports_format(ext: &str) -> bool {
+    match ext {
+        "jpg" | "jpeg" | "png" | "bmp" => true,
+        "webp" => cfg!(target_os = "macos") || is_kde(),
+        "avif" | "heic" | "tiff" | "gif" => cfg!(target_os = "macos"),
+        _ => false,
+    }
+}
+
+/// Longest value substituted for a single filename template placeholder
+const MAX_PLACEHOLDER_LEN: usize = 60;
+
+/// Make a value safe to use in a filename on every platform
+fn sanitize_filename_part(value: &str) -> String {
+    let mut result = String::new();
+    for c in value.chars() {
+        if c.is_alphanumeric() || c == '-' || c == '.' {
+            result.push(c);
+        } else if !result.ends_with('_') {
+            result.push('_');
+        }
+    }
+    result.trim_matches(['_', '.']).to_string()
+}
+
+/// File name without extension for a photo, rendered from a template such as
+/// `{author}_{description}_{id}.{ext}`. The extension is always the format the
+/// server sends, so `{ext}` only marks where it goes. Templates without `{id}` get
+/// it appended, so two photos never share a file.
+pub fn filename_stem(template: &str, image: &UnsplashImage) -> String {
+    let value = |value: &str| {
+        sanitize_filename_part(value)
+            .chars()
+            .take(MAX_PLACEHOLDER_LEN)
+            .collect::<String>()
+    };
+    let description = image
+        .description
+        .as_deref()
+        .or(image.alt_description.as_deref())
+        .unwrap_or_default();
+
+    let template = template.trim().trim_end_matches(".{ext}");
+    let rendered = template
+        .replace("{ext}", "")
+        .replace("{id}", &value(&image.id))
+        .replace("{author}", &value(&image.user.name))
+        .replace("{username}", &value(&image.user.username))
+        .replace("{description}", &value(description));
+    let stem = sanitize_filename_part(&rendered);
+
+    if stem.is_empty() {
+        format!("wallpaper_{}", image.id)
+    } else if !template.contains("{id}") {
+        format!("{}_{}", stem, value(&image.id))
+    } else {
+        stem
+    }
+}
+
+/// Download a response into `dir` as `{stem}.{ext}`, keeping the format the server sent
+pub async fn download_image_file(
+    response: reqwest::Response,
+    dir: &Path,
+    stem: &str,
+    cancel: &CancellationToken,
+) -> Result<PathBuf, WallyError> {
+    let ext = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(extension_for_content_type)
+        .unwrap_or("jpg");
+
+    let file_path = dir.join(format!("{}.{}", stem, ext));
+    download_to_file(response, &file_path, cancel).await?;
+    Ok(file_path)
+}
+
+/// Transcode an image to JPEG if the wallpaper backend can't display its format
+fn ensure_platform_format(file_path: PathBuf) -> Result<PathBuf, String> {
+    let ext = file_path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default();
+    if platform_supports_format(&ext) {
+        return Ok(file_path);
+    }
+
+    info!("Transcoding {} image to JPEG", ext);
+    let image = image::open(&file_path).map_err(|e| format!("Unsupported image format: {}", e))?;
+    let jpeg_path = file_path.with_extension("jpg");
+    image
+        .to_rgb8()
+        .save_with_format(&jpeg_path, image::ImageFormat::Jpeg)
+        .map_err(|e| format!("Failed to transcode image: {}", e))?;
+    let _ = fs::remove_file(&file_path);
+
+    Ok(jpeg_path)
+}
+
+/// Copy a local image into `dir` so the wallpaper survives the original being moved,
+/// transcoding it if the wallpaper backend can't display its format
+pub fn import_local_image(source: &Path, dir: &Path) -> Result<PathBuf, WallyError> {
+    let ext = source
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default();
+    if !source.is_file() {
+        return Err(format!("{} is not a file", source.display()).into());
+    }
+    if !is_image_extension(&ext) {
+        return Err(format!("{} is not a supported image", source.display()).into());
+    }
+    // Formats we can decode are checked up front, so a broken file never replaces the wallpaper
+    if image::ImageFormat::from_extension(&ext).is_some_and(|format| format.reading_enabled()) {
+        image::image_dimensions(source)
+            .map_err(|e| format!("{} is not a valid image: {}", source.display(), e))?;
+    }
+
+    let source = source.canonicalize()?;
+    if dir
+        .canonicalize()
+        .is_ok_and(|dir| source.parent() == Some(dir.as_path()))
+    {
+        return Ok(source);
+    }
+    let stem = source
+        .file_stem()
+        .map(|stem| sanitize_filename_part(&stem.to_string_lossy()))
+        .unwrap_or_default();
+    // A hash of the contents keeps images with the same name from different folders
+    // apart. A file already under this name is an earlier import of the same image,
+    // maybe processed since, so it's refreshed from the original rather than reused.
+    let content = fs::read(&source)?;
+    let target = dir.join(format!(
+        "wallpaper_local_{}_{:016x}.{}",
+        stem,
+        stable_hash(&content),
+        ext
+    ));
+    fs::write(&target, &content)?;
+    Ok(ensure_platform_format(target)?)
+}
+
+/// Ask imgix to convert an Unsplash image to JPEG server-side
+fn jpeg_image_url(image_url: &str) -> Option<String> {
+    let (base, query) = image_url.split_once('?').unwrap_or((image_url, ""));
+    if !base.contains("images.unsplash.com") {
+        return None;
+    }
+
+    let mut params: Vec<&str> = query
+        .split('&')
+        .filter(|param| !param.is_empty() && !param.starts_with("fm="))
+        .collect();
+    params.push("fm=jpg");
+    Some(format!("{}?{}", base, params.join("&")))
+}
+
+/// Free up space in the wallpaper folder when a download would not fit, deleting
+/// every download that isn't pinned or on screen if the settings allow it
+fn make_room_for_download(
+    dir: &Path,
+    response: &reqwest::Response,
+    settings: &WallpaperSettings,
+) -> Result<(), WallyError> {
+    let Err(e) = check_disk_space(dir, response) else {
+        return Ok(());
+    };
+    if !settings.cleanup_on_low_space {
+        return Err(e);
+    }
+
+    warn!("{}, removing old wallpapers", e);
+    let aggressive = WallpaperSettings {
+        cleanup_keep_count: 0,
+        ..settings.clone()
+    };
+    cleanup_old_wallpapers(&dir.to_path_buf(), &aggressive, "")?;
+    check_disk_space(dir, response)
+}
+
+/// A previous download of the same photo in `dir`, so repeats from the random
+/// endpoint don't cost bandwidth. Files are processed in place, so they are only
+/// reused when processing them again leaves them unchanged.
+fn find_downloaded_file(dir: &Path, stem: &str, settings: &WallpaperSettings) -> Option<PathBuf> {
+    if processing::is_cumulative(settings) {
+        return None;
+    }
+
+    let path = fs::read_dir(dir)
+        .ok()?
+        .flatten()
+        .map(|e| e.path())
+        .find(|path| {
+            path.file_stem().is_some_and(|s| s == stem)
+                && path
+                    .extension()
+                    .is_some_and(|ext| is_image_extension(&ext.to_string_lossy()))
+        })?;
+
+    // Only trust files whose header still decodes
+    image::image_dimensions(&path).ok()?;
+
+    // Count the file as fresh so cleanup keeps it with the recent downloads
+    if let Ok(file) = fs::File::options().write(true).open(&path) {
+        let _ = file.set_modified(std::time::SystemTime::now());
+    }
+    Some(path)
+}
+
+/// Download a wallpaper image into `dir`, making sure it ends up in a format the
+/// platform can display
+pub async fn fetch_wallpaper_file(
+    client: &reqwest::Client,
+    settings: &WallpaperSettings,
+    image_url: &str,
+    dir: &Path,
+    stem: &str,
+    cancel: &CancellationToken,
+) -> Result<PathBuf, WallyError> {
+    if let Some(existing) = find_downloaded_file(dir, stem, settings) {
+        info!("Reusing {}", existing.display());
+        return Ok(existing);
+    }
+
+    let response = send_with_retry(client.get(image_url), settings)
+        .await
+        .map_err(|e| WallyError::DownloadFailed(e.to_string()))?;
+    make_room_for_download(dir, &response, settings)?;
+    let file_path = download_image_file(response, dir, stem, cancel).await?;
+
+    match ensure_platform_format(file_path.clone()) {
+        Ok(path) => Ok(path),
+        Err(e) => {
+            // Formats we can't decode locally (e.g. AVIF) can still be converted by the CDN
+            let Some(jpeg_url) = jpeg_image_url(image_url) else {
+                return Err(WallyError::DownloadFailed(e));
+            };
+            let _ = fs::remove_file(&file_path);
+            info!("{}, requesting JPEG from the CDN instead", e);
+            let response = send_with_retry(client.get(&jpeg_url), settings)
+                .await
+                .map_err(|e| WallyError::DownloadFailed(e.to_string()))?;
+            download_image_file(response, dir, stem, cancel).await
+        }
+    }
+}
+
+/// Run local post-processing off the async runtime, falling back to the untouched
+/// download if processing fails
+pub async fn process_downloaded_wallpaper(
+    file_path: PathBuf,
+    settings: &WallpaperSettings,
+    screen: Option<ScreenResolution>,
+    caption: Option<String>,
+) -> PathBuf {
+    let options = processing::ProcessOptions {
+        screen,
+        caption,
+        dark_mode: settings.dark_mode_adjust && is_dark_mode(),
+    };
+    let settings = settings.clone();
+    let path = file_path.clone();
+    let result = tokio::task::spawn_blocking(move || {
+        processing::process_wallpaper(path, &settings, &options)
+    })
+    .await
+    .map_err(|e| e.to_string())
+    .and_then(|result| result);
+
+    match result {
+        Ok(path) => path,
+        Err(e) => {
+            warn!("Post-processing failed, using original image: {}", e);
+            file_path
+        }
+    }
+}
+
+pub fn cleanup_old_wallpapers(
+    wallpaper_dir: &PathBuf,
+    settings: &WallpaperSettings,
+    in_use: &str,
+) -> Result<(), String> {
+    // Pinned files and the wallpaper on screen are never deleted
+    let mut exempt = load_pins();
+    exempt.push(in_use.to_string());
+    exempt.extend(load_current_wallpaper().local_path);
+
+    // Downloads are named from a user template, so every image in the folder counts
+    let mut entries: Vec<_> = fs::read_dir(wallpaper_dir)
+        .map_err(|e| e.to_string())?
+        .filter_map(|e| e.ok())
+        .filter(|e| {
+            e.path()
+                .extension()
+                .map(|ext| is_image_extension(&ext.to_string_lossy()))
+                .unwrap_or(false)
+        })
+        .collect();
+
+    // Sort by modification time (newest first)
+    entries.sort_by(|a, b| {
+        let a_time = a.metadata().and_then(|m| m.modified()).ok();
+        let b_time = b.metadata().and_then(|m| m.modified()).ok();
+        b_time.cmp(&a_time)
+    });
+
+    let files: Vec<(PathBuf, u64)> = entries
+        .iter()
+        .map(|entry| {
+            let size = entry.metadata().map(|m| m.len()).unwrap_or(0);
+            (entry.path(), size)
+        })
+        .collect();
+    let max_size = settings.cleanup_max_size_mb.saturating_mul(1024 * 1024);
+    for path in files_to_clean_up(
+        &files,
+        &exempt,
+        settings.cleanup_keep_count as usize,
+        max_size,
+    ) {
+        let _ = fs::remove_file(path);
+    }
+
+    Ok(())
+}
+
+/// Which of `files`, newest first with their sizes, to delete: all but the
+/// `keep_count` most recent, then the oldest remaining ones until the folder fits
+/// `max_size` bytes (0 for no cap). Exempt paths are never picked.
+fn files_to_clean_up<'a>(
+    files: &'a [(PathBuf, u64)],
+    exempt: &[String],
+    keep_count: usize,
+    max_size: u64,
+) -> Vec<&'a PathBuf> {
+    let mut total_size: u64 = files.iter().map(|(_, size)| size).sum();
+    let deletable: Vec<_> = files
+        .iter()
+        .filter(|(path, _)| !exempt.contains(&path.to_string_lossy().to_string()))
+        .collect();
+    let mut remove = Vec::new();
+    for (index, (path, size)) in deletable.into_iter().enumerate().rev() {
+        let over_count = index >= keep_count;
+        let over_size = max_size > 0 && total_size > max_size;
+        if over_count || over_size {
+            total_size = total_size.saturating_sub(*size);
+            remove.push(path);
+        }
+    }
+    remove
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{UnsplashLinks, UnsplashUrls, UnsplashUser};
+
+    fn image(description: Option<&str>) -> UnsplashImage {
+        let url = String::new;
+        UnsplashImage {
+            id: "abc123".to_string(),
+            description: description.map(str::to_string),
+            alt_description: None,
+            width: 0,
+            height: 0,
+            color: None,
+            urls: UnsplashUrls {
+                raw: url(),
+                full: url(),
+                regular: url(),
+                small: url(),
+                thumb: url(),
+            },
+            user: UnsplashUser {
+                name: "Jane Doe".to_string(),
+                username: "jane".to_string(),
+            },
+            links: UnsplashLinks {
+                html: url(),
+                download: url(),
+                download_location: url(),
+            },
+        }
+    }
+
+    #[test]
+    fn filename_stem_sanitizes_placeholders() {
+        let template = "{author}_{description}_{id}.{ext}";
+        assert_eq!(
+            filename_stem(template, &image(Some("A/B: sunset?"))),
+            "Jane_Doe_A_B_sunset_abc123"
+        );
+        assert_eq!(filename_stem("../{id}", &image(None)), "abc123");
+        assert_eq!(filename_stem("{username}", &image(None)), "jane_abc123");
+    }
+
+    #[test]
+    fn filename_stem_falls_back_to_the_id() {
+        assert_eq!(
+            filename_stem("{description}", &image(None)),
+            "wallpaper_abc123"
+        );
+        assert_eq!(filename_stem("  ", &image(None)), "wallpaper_abc123");
+    }
+
+    #[test]
+    fn filename_stem_shortens_long_values() {
+        let long = "a".repeat(200);
+        let stem = filename_stem("{description}_{id}", &image(Some(&long)));
+        assert_eq!(stem.len(), MAX_PLACEHOLDER_LEN + "_abc123".len());
+    }
+
+    fn files(sizes: &[u64]) -> Vec<(PathBuf, u64)> {
+        sizes
+            .iter()
+            .enumerate()
+            .map(|(index, size)| (PathBuf::from(format!("{}.jpg", index)), *size))
+            .collect()
+    }
+
+    fn names(paths: Vec<&PathBuf>) -> Vec<String> {
+        paths
+            .into_iter()
+            .map(|path| path.to_string_lossy().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn cleanup_keeps_the_newest_files() {
+        let files = files(&[1, 1, 1, 1, 1]);
+        assert_eq!(
+            names(files_to_clean_up(&files, &[], 3, 0)),
+            ["4.jpg", "3.jpg"]
+        );
+        assert!(files_to_clean_up(&files, &[], 10, 0).is_empty());
+    }
+
+    #[test]
+    fn cleanup_removes_the_oldest_until_under_the_size_cap() {
+        let files = files(&[40, 30, 20, 10]);
+        assert_eq!(
+            names(files_to_clean_up(&files, &[], 10, 75)),
+            ["3.jpg", "2.jpg"]
+        );
+    }
+
+    #[test]
+    fn cleanup_skips_exempt_files() {
+        let files = files(&[1, 1, 1, 1]);
+        let exempt = ["3.jpg".to_string()];
+        // The exempt file doesn't use up a place among the newest
+        assert_eq!(names(files_to_clean_up(&files, &exempt, 2, 0)), ["2.jpg"]);
+    }
+}
@@ -0,0 +1,117 @@
+//! Error type returned by commands, serialized with a stable code the frontend can match on
+
+use chrono::{DateTime, Local, Utc};
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
+
+#[derive(Debug, thiserror::Error)]
+pub enum WallyError {
+    #[error("API key not configured")]
+    ApiKeyMissing,
+    #[error("Invalid Unsplash API key, check the access key in settings")]
+    InvalidApiKey,
+    #[error("Unsplash rate limit reached, {}", retry_hint(.reset))]
+    RateLimited {
+        /// When requests are expected to be accepted again
+        reset: Option<DateTime<Utc>>,
+    },
+    #[error("{resource} not found")]
+    NotFound { resource: String },
+    #[error("Unsplash refused the request: {0}")]
+    Forbidden(String),
+    #[error("API error: {status} - {message}")]
+    Api { status: u16, message: String },
+    #[error("{0}")]
+    UnsupportedDesktop(String),
+    #[error("Failed to download image: {0}")]
+    DownloadFailed(String),
+    #[error("Download cancelled")]
+    Cancelled,
+    #[error(
+        "Not enough disk space: {} MB needed, {} MB available",
+        .required.div_ceil(1024 * 1024),
+        .available / (1024 * 1024)
+    )]
+    InsufficientSpace { required: u64, available: u64 },
+    #[error("{backend} did not respond within {timeout_secs}s")]
+    BackendTimeout {
+        /// The wallpaper backend that hung, e.g. "qdbus6" or "gsettings"
+        backend: String,
+        timeout_secs: u64,
+    },
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error("{0}")]
+    Other(String),
+}
+
+impl WallyError {
+    /// Stable identifier for the kind of error
+    pub fn code(&self) -> &'static str {
+        match self {
+            WallyError::ApiKeyMissing => "api_key_missing",
+            WallyError::InvalidApiKey => "invalid_api_key",
+            WallyError::RateLimited { .. } => "rate_limited",
+            WallyError::NotFound { .. } => "not_found",
+            WallyError::Forbidden(_) => "forbidden",
+            WallyError::Api { .. } => "api_error",
+            WallyError::UnsupportedDesktop(_) => "unsupported_desktop",
+            WallyError::DownloadFailed(_) => "download_failed",
+            WallyError::Cancelled => "cancelled",
+            WallyError::InsufficientSpace { .. } => "insufficient_space",
+            WallyError::BackendTimeout { .. } => "backend_timeout",
+            WallyError::Io(_) => "io",
+            WallyError::Http(_) => "network",
+            WallyError::Json(_) => "invalid_response",
+            WallyError::Other(_) => "other",
+        }
+    }
+}
+
+fn retry_hint(reset: &Option<DateTime<Utc>>) -> String {
+    match reset {
+        Some(reset) => format!(
+            "try again after {}",
+            reset.with_timezone(&Local).format("%H:%M")
+        ),
+        None => "try again later".to_string(),
+    }
+}
+
+impl From<String> for WallyError {
+    fn from(message: String) -> Self {
+        WallyError::Other(message)
+    }
+}
+
+impl From<&str> for WallyError {
+    fn from(message: &str) -> Self {
+        WallyError::Other(message.to_string())
+    }
+}
+
+impl<T> From<std::sync::PoisonError<T>> for WallyError {
+    fn from(e: std::sync::PoisonError<T>) -> Self {
+        WallyError::Other(e.to_string())
+    }
+}
+
+/// Serialized as `{ code, message, reset }` so the frontend gets both a code to
+/// branch on and a readable message
+impl Serialize for WallyError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let reset = match self {
+            WallyError::RateLimited { reset } => reset.map(|reset| reset.to_rfc3339()),
+            _ => None,
+        };
+        let mut state = serializer.serialize_struct("WallyError", 3)?;
+        state.serialize_field("code", self.code())?;
+        state.serialize_field("message", &self.to_string())?;
+        state.serialize_field("reset", &reset)?;
+        state.end()
+    }
+}
@@ -0,0 +1,124 @@
+//! The wallpapers already on disk, with cached thumbnails so a gallery doesn't
+//! have to load full-resolution files
+
+use crate::download::is_image_extension;
+use crate::paths::{get_thumbnail_dir, get_wallpaper_dir};
+use crate::storage::{load_current_wallpaper, load_history, load_pins};
+use crate::{UnsplashImage, WallyError};
+use image::codecs::webp::WebPEncoder;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use tracing::warn;
+
+/// Longest side of a thumbnail in pixels
+pub const THUMBNAIL_SIZE: u32 = 320;
+
+/// A downloaded or imported wallpaper in the wallpaper folder
+#[derive(Debug, Clone, Serialize)]
+pub struct CachedWallpaper {
+    pub local_path: String,
+    /// WebP preview in the thumbnail folder, if the image could be decoded
+    pub thumbnail_path: Option<String>,
+    pub size_bytes: u64,
+    pub modified_at: Option<String>,
+    pub pinned: bool,
+    pub current: bool,
+    /// Photo details from the history, for wallpapers set recently enough
+    pub image: Option<UnsplashImage>,
+    pub set_at: Option<String>,
+    pub palette: Vec<String>,
+}
+
+/// Thumbnails are named after the wallpaper's file name, which is unique in its folder
+fn thumbnail_path(thumbnail_dir: &Path, wallpaper: &Path) -> Option<PathBuf> {
+    let name = wallpaper.file_name()?.to_string_lossy();
+    Some(thumbnail_dir.join(format!("{}.webp", name)))
+}
+
+/// The thumbnail for `wallpaper`, generated if it is missing or older than the file
+pub fn ensure_thumbnail(
+    thumbnail_dir: &Path,
+    wallpaper: &Path,
+    modified: Option<SystemTime>,
+) -> Result<PathBuf, WallyError> {
+    let path = thumbnail_path(thumbnail_dir, wallpaper).ok_or("Wallpaper has no file name")?;
+    let thumbnail_modified = fs::metadata(&path).and_then(|m| m.modified()).ok();
+    // Files are processed in place, so a newer wallpaper means a stale thumbnail
+    if thumbnail_modified.is_some_and(|thumbnail| modified.is_none_or(|file| thumbnail >= file)) {
+        return Ok(path);
+    }
+
+    let image = image::open(wallpaper)
+        .map_err(|e| format!("Failed to open {}: {}", wallpaper.display(), e))?;
+    let thumbnail = image.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE).to_rgba8();
+    let file = fs::File::create(&path)?;
+    thumbnail
+        .write_with_encoder(WebPEncoder::new_lossless(std::io::BufWriter::new(file)))
+        .map_err(|e| format!("Failed to write thumbnail: {}", e))?;
+    Ok(path)
+}
+
+/// Every image in the wallpaper folder, newest first, generating missing thumbnails
+/// and removing those of deleted files. Blocking.
+pub fn list_cached_wallpapers() -> Result<Vec<CachedWallpaper>, WallyError> {
+    let thumbnail_dir = get_thumbnail_dir();
+    let pins = load_pins();
+    let history = load_history();
+    let current_path = load_current_wallpaper().local_path;
+
+    let mut files: Vec<(PathBuf, fs::Metadata)> = fs::read_dir(get_wallpaper_dir())?
+        .flatten()
+        .filter(|entry| {
+            entry
+                .path()
+                .extension()
+                .is_some_and(|ext| is_image_extension(&ext.to_string_lossy()))
+        })
+        .filter_map(|entry| Some((entry.path(), entry.metadata().ok()?)))
+        .filter(|(_, metadata)| metadata.is_file())
+        .collect();
+    files.sort_by_key(|(_, metadata)| std::cmp::Reverse(metadata.modified().ok()));
+
+    let mut thumbnails = HashSet::new();
+    let wallpapers = files
+        .into_iter()
+        .map(|(path, metadata)| {
+            let local_path = path.to_string_lossy().to_string();
+            let modified = metadata.modified().ok();
+            let thumbnail = ensure_thumbnail(&thumbnail_dir, &path, modified)
+                .inspect_err(|e| warn!("No thumbnail for {}: {}", local_path, e))
+                .ok();
+            thumbnails.extend(thumbnail.clone());
+            let entry = history
+                .iter()
+                .find(|entry| entry.local_path.as_deref() == Some(local_path.as_str()));
+
+            CachedWallpaper {
+                thumbnail_path: thumbnail.map(|path| path.to_string_lossy().to_string()),
+                size_bytes: metadata.len(),
+                modified_at: modified
+                    .map(|modified| chrono::DateTime::<chrono::Utc>::from(modified).to_rfc3339()),
+                pinned: pins.contains(&local_path),
+                current: current_path.as_deref() == Some(local_path.as_str()),
+                image: entry.and_then(|entry| entry.image.clone()),
+                set_at: entry.and_then(|entry| entry.set_at.clone()),
+                palette: entry.map(|entry| entry.palette.clone()).unwrap_or_default(),
+                local_path,
+            }
+        })
+        .collect();
+
+    // Drop thumbnails of wallpapers that cleanup or the user deleted
+    if let Ok(entries) = fs::read_dir(&thumbnail_dir) {
+        for entry in entries.flatten() {
+            if !thumbnails.contains(&entry.path()) {
+                let _ = fs::remove_file(entry.path());
+            }
+        }
+    }
+
+    Ok(wallpapers)
+}
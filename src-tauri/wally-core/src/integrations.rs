@@ -0,0 +1,260 @@
+//! Integrations run after each successful wallpaper change
+
+use crate::palette::accent_color;
+use crate::{CurrentWallpaper, WallpaperSettings};
+use serde::Serialize;
+use std::process::Command;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+/// Run every enabled post-change integration for the new wallpaper
+pub fn run_post_change_integrations(settings: &WallpaperSettings, current: &CurrentWallpaper) {
+    let Some(local_path) = current.local_path.as_deref() else {
+        return;
+    };
+
+    if settings.pywal_integration {
+        if let Err(e) = run_pywal(local_path) {
+            warn!("pywal integration failed: {}", e);
+        }
+    }
+
+    if settings.sync_accent_color {
+        match accent_color(&current.palette) {
+            Some(color) => {
+                if let Err(e) = set_accent_color(color) {
+                    warn!("Failed to set accent color: {}", e);
+                }
+            }
+            None => info!("No suitable accent color in palette"),
+        }
+    }
+
+    for hook in settings.hooks.iter().filter(|hook| !hook.trim().is_empty()) {
+        let timeout = Duration::from_secs(settings.hook_timeout_secs.max(1));
+        if let Err(e) = run_hook(hook, current, local_path, timeout) {
+            warn!("Hook `{}` failed: {}", hook, e);
+        }
+    }
+}
+
+/// Run a user hook through the shell with the new wallpaper's details in its environment
+fn run_hook(
+    hook: &str,
+    current: &CurrentWallpaper,
+    local_path: &str,
+    timeout: Duration,
+) -> Result<(), String> {
+    info!("Running hook: {}", hook);
+
+    #[cfg(target_os = "windows")]
+    let mut command = {
+        let mut command = Command::new("cmd");
+        command.args(["/C", hook]);
+        command
+    };
+    #[cfg(not(target_os = "windows"))]
+    let mut command = {
+        let mut command = Command::new("sh");
+        command.args(["-c", hook]);
+        command
+    };
+
+    command.env("WALLY_PATH", local_path);
+    if let Some(image) = &current.image {
+        command
+            .env("WALLY_ID", &image.id)
+            .env("WALLY_AUTHOR", &image.user.name)
+            .env("WALLY_AUTHOR_USERNAME", &image.user.username)
+            .env("WALLY_URL", &image.links.html)
+            .env(
+                "WALLY_DESCRIPTION",
+                image
+                    .description
+                    .as_deref()
+                    .or(image.alt_description.as_deref())
+                    .unwrap_or_default(),
+            );
+    }
+
+    let mut child = command.spawn().map_err(|e| e.to_string())?;
+    let started = Instant::now();
+    loop {
+        if let Some(status) = child.try_wait().map_err(|e| e.to_string())? {
+            if status.success() {
+                return Ok(());
+            }
+            return Err(format!("exited with {}", status));
+        }
+        if started.elapsed() >= timeout {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(format!("timed out after {}s", timeout.as_secs()));
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+}
+
+/// Generate a pywal color scheme from the wallpaper without letting wal set it again
+fn run_pywal(local_path: &str) -> Result<(), String> {
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    {
+        info!("Running pywal for {}", local_path);
+        let output = Command::new("wal")
+            .args(["-i", local_path, "-n", "-q"])
+            .output()
+            .map_err(|e| format!("wal not found: {}", e))?;
+
+        if !output.status.success() {
+            return Err(String::from_utf8_lossy(&output.stderr).to_string());
+        }
+        Ok(())
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+    {
+        let _ = local_path;
+        Err("pywal is only supported on Linux and macOS".to_string())
+    }
+}
+
+/// Set the desktop accent color from an RGB color
+fn set_accent_color(color: [u8; 3]) -> Result<(), String> {
+    let hex = format!("#{:02x}{:02x}{:02x}", color[0], color[1], color[2]);
+    info!("Setting accent color to {}", hex);
+
+    #[cfg(target_os = "linux")]
+    {
+        if crate::platform::is_kde() {
+            let output = Command::new("plasma-apply-colorscheme")
+                .args(["--accent-color", &hex])
+                .output()
+                .map_err(|e| format!("plasma-apply-colorscheme not found: {}", e))?;
+            if !output.status.success() {
+                return Err(String::from_utf8_lossy(&output.stderr).to_string());
+            }
+            return Ok(());
+        }
+
+        if crate::platform::is_gnome() {
+            // GNOME only offers a fixed set of named accent colors
+            let output = Command::new("gsettings")
+                .args([
+                    "set",
+                    "org.gnome.desktop.interface",
+                    "accent-color",
+                    crate::palette::gnome_accent_name(color),
+                ])
+                .output()
+                .map_err(|e| e.to_string())?;
+            if !output.status.success() {
+                return Err(String::from_utf8_lossy(&output.stderr).to_string());
+            }
+            return Ok(());
+        }
+
+        Err("Accent colors are only supported on KDE Plasma and GNOME".to_string())
+    }
+
+    #[cfg(target_os = "windows")]
+    {
+        set_accent_color_windows(color)
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "windows")))]
+    {
+        Err("Setting the accent color is not supported on this platform".to_string())
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn set_accent_color_windows(color: [u8; 3]) -> Result<(), String> {
+    use windows::core::w;
+    use windows::Win32::Foundation::{ERROR_SUCCESS, LPARAM, WPARAM};
+    use windows::Win32::System::Registry::{RegSetKeyValueW, HKEY_CURRENT_USER, REG_DWORD};
+    use windows::Win32::UI::WindowsAndMessaging::{
+        SendMessageTimeoutW, HWND_BROADCAST, SMTO_ABORTIFHUNG, WM_SETTINGCHANGE,
+    };
+
+    let [r, g, b] = color.map(u32::from);
+    // DWM stores the accent as 0xAABBGGRR and the colorization color as 0xAARRGGBB
+    let accent: u32 = 0xff00_0000 | (b << 16) | (g << 8) | r;
+    let colorization: u32 = 0xc400_0000 | (r << 16) | (g << 8) | b;
+
+    for (name, value) in [
+        (w!("AccentColor"), accent),
+        (w!("ColorizationColor"), colorization),
+    ] {
+        let status = unsafe {
+            RegSetKeyValueW(
+                HKEY_CURRENT_USER,
+                w!("Software\\Microsoft\\Windows\\DWM"),
+                name,
+                REG_DWORD.0,
+                Some(&value as *const u32 as *const core::ffi::c_void),
+                std::mem::size_of::<u32>() as u32,
+            )
+        };
+        if status != ERROR_SUCCESS {
+            return Err(format!("Failed to write accent color: {:?}", status));
+        }
+    }
+
+    // Tell running applications to pick up the new colors
+    let setting = w!("ImmersiveColorSet");
+    unsafe {
+        SendMessageTimeoutW(
+            HWND_BROADCAST,
+            WM_SETTINGCHANGE,
+            WPARAM(0),
+            LPARAM(setting.as_ptr() as isize),
+            SMTO_ABORTIFHUNG,
+            1000,
+            None,
+        );
+    }
+
+    Ok(())
+}
+
+/// Payload POSTed to the webhook URL after each change
+#[derive(Debug, Serialize)]
+struct WebhookPayload<'a> {
+    event: &'static str,
+    image_id: Option<&'a str>,
+    author: Option<&'a str>,
+    author_username: Option<&'a str>,
+    photo_url: Option<&'a str>,
+    local_path: Option<&'a str>,
+    timestamp: Option<&'a str>,
+    palette: &'a [String],
+}
+
+/// Notify a user-configured URL about the new wallpaper
+pub async fn send_webhook(
+    client: &reqwest::Client,
+    url: &str,
+    current: &CurrentWallpaper,
+) -> Result<(), String> {
+    let image = current.image.as_ref();
+    let payload = WebhookPayload {
+        event: "wallpaper-changed",
+        image_id: image.map(|image| image.id.as_str()),
+        author: image.map(|image| image.user.name.as_str()),
+        author_username: image.map(|image| image.user.username.as_str()),
+        photo_url: image.map(|image| image.links.html.as_str()),
+        local_path: current.local_path.as_deref(),
+        timestamp: current.set_at.as_deref(),
+        palette: &current.palette,
+    };
+
+    client
+        .post(url)
+        .json(&payload)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| e.to_string())?;
+
+    Ok(())
+}
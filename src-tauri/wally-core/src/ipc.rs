@@ -0,0 +1,73 @@
+//! Local control socket used by `wallyctl`: a Unix socket in the config dir, or a
+//! named pipe on Windows. Each connection sends one JSON request line and gets one
+//! JSON response line back.
+
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Write};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "lowercase")]
+pub enum Request {
+    /// Change to a new wallpaper now
+    Next,
+    /// Go back to the previous wallpaper
+    Previous,
+    /// Pause rotation, or resume it when already paused
+    Pause,
+    Status,
+    /// Set a local image file as the wallpaper
+    Set {
+        path: String,
+    },
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Status {
+    pub local_path: Option<String>,
+    pub author: Option<String>,
+    pub auto_change: bool,
+    pub paused: bool,
+    /// RFC 3339 time of the next scheduled change
+    pub next_change_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Response {
+    pub ok: bool,
+    /// `WallyError::code` when the request failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<Status>,
+}
+
+#[cfg(windows)]
+pub const PIPE_NAME: &str = r"\\.\pipe\unsplash-wally";
+
+#[cfg(unix)]
+pub fn socket_path() -> std::path::PathBuf {
+    crate::paths::get_config_dir().join("wally.sock")
+}
+
+/// Send a request to the running app and wait for its answer
+pub fn send(request: &Request) -> std::io::Result<Response> {
+    #[cfg(unix)]
+    let stream = std::os::unix::net::UnixStream::connect(socket_path())?;
+    #[cfg(windows)]
+    let stream = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(PIPE_NAME)?;
+
+    let mut writer = &stream;
+    let mut line = serde_json::to_string(request)?;
+    line.push('\n');
+    writer.write_all(line.as_bytes())?;
+    writer.flush()?;
+
+    let mut response = String::new();
+    BufReader::new(&stream).read_line(&mut response)?;
+    Ok(serde_json::from_str(&response)?)
+}
@@ -0,0 +1,33 @@
+//! Wallpaper logic shared by the wally app and its command line tools: settings and
+//! state persistence, the Unsplash client, downloads, scheduling and the desktop
+//! backends. Nothing here depends on Tauri.
+
+pub mod api_cache;
+pub mod collage;
+pub mod crash;
+pub mod download;
+pub mod error;
+pub mod gallery;
+pub mod integrations;
+pub mod ipc;
+pub mod metrics;
+pub mod palette;
+pub mod paths;
+pub mod platform;
+pub mod processing;
+pub mod rotation;
+pub mod schedule;
+pub mod settings;
+pub mod settings_store;
+pub mod similarity;
+pub mod slideshow;
+pub mod storage;
+pub mod system;
+pub mod unsplash;
+pub mod upscale;
+pub mod weather;
+
+pub use error::WallyError;
+pub use settings::WallpaperSettings;
+pub use storage::CurrentWallpaper;
+pub use unsplash::{ScreenResolution, UnsplashImage, UnsplashLinks, UnsplashUrls, UnsplashUser};
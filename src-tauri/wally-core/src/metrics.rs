@@ -0,0 +1,181 @@
+//! Opt-in usage counts kept on this machine: wallpaper changes per day, the desktop
+//! backend that set them and failures by error code. Nothing identifying is stored,
+//! and the counts only leave the machine when a submission URL is configured.
+
+use crate::paths::get_config_dir;
+use crate::platform::get_platform;
+use crate::settings_store::write_atomic;
+use crate::WallpaperSettings;
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use tracing::{info, warn};
+
+/// Days of counts kept before the oldest is dropped
+const RETENTION_DAYS: i64 = 90;
+
+/// Counts for a single day
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DayUsage {
+    pub changes: u32,
+    /// Successful changes per backend, e.g. "linux-kde"
+    #[serde(default)]
+    pub backends: BTreeMap<String, u32>,
+    /// Failed changes per `WallyError::code`
+    #[serde(default)]
+    pub failures: BTreeMap<String, u32>,
+}
+
+/// The counts on disk
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct UsageLog {
+    /// Keyed by local date, "YYYY-MM-DD"
+    #[serde(default)]
+    days: BTreeMap<String, DayUsage>,
+    /// Last day already sent to the submission URL
+    #[serde(default)]
+    submitted_through: Option<String>,
+}
+
+/// Totals and per-day counts, for `get_usage_summary`
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct UsageSummary {
+    pub changes: u32,
+    pub backends: BTreeMap<String, u32>,
+    pub failures: BTreeMap<String, u32>,
+    pub days: BTreeMap<String, DayUsage>,
+}
+
+impl UsageSummary {
+    fn from_days(days: BTreeMap<String, DayUsage>) -> Self {
+        let mut summary = Self::default();
+        for day in days.values() {
+            summary.changes += day.changes;
+            for (backend, count) in &day.backends {
+                *summary.backends.entry(backend.clone()).or_default() += count;
+            }
+            for (code, count) in &day.failures {
+                *summary.failures.entry(code.clone()).or_default() += count;
+            }
+        }
+        summary.days = days;
+        summary
+    }
+}
+
+/// What is sent to the submission URL, completed days only
+#[derive(Debug, Serialize)]
+struct Submission<'a> {
+    os: &'static str,
+    days: &'a BTreeMap<String, DayUsage>,
+}
+
+fn usage_path() -> PathBuf {
+    get_config_dir().join("usage.json")
+}
+
+fn load() -> UsageLog {
+    fs::read_to_string(usage_path())
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+fn save(log: &UsageLog) {
+    if let Ok(content) = serde_json::to_string_pretty(log) {
+        let _ = write_atomic(&usage_path(), content.as_bytes());
+    }
+}
+
+fn today() -> String {
+    Local::now().date_naive().format("%Y-%m-%d").to_string()
+}
+
+/// Add to today's counts when metrics are on, dropping days past the retention
+fn record(settings: &WallpaperSettings, update: impl FnOnce(&mut DayUsage)) {
+    if !settings.usage_metrics {
+        return;
+    }
+    let mut log = load();
+    update(log.days.entry(today()).or_default());
+    let oldest = (Local::now().date_naive() - chrono::Duration::days(RETENTION_DAYS))
+        .format("%Y-%m-%d")
+        .to_string();
+    log.days.retain(|day, _| *day >= oldest);
+    save(&log);
+}
+
+/// Count a successful wallpaper change
+pub fn record_change(settings: &WallpaperSettings) {
+    let backend = get_platform();
+    record(settings, |day| {
+        day.changes += 1;
+        *day.backends.entry(backend).or_default() += 1;
+    });
+}
+
+/// Count a failed wallpaper change by its `WallyError::code`
+pub fn record_failure(settings: &WallpaperSettings, code: &str) {
+    record(settings, |day| {
+        *day.failures.entry(code.to_string()).or_default() += 1;
+    });
+}
+
+/// Everything counted so far
+pub fn summary() -> UsageSummary {
+    UsageSummary::from_days(load().days)
+}
+
+/// Delete all counts
+pub fn clear() {
+    let _ = fs::remove_file(usage_path());
+}
+
+/// Send the days completed since the last submission to `usage_metrics_url`, when
+/// metrics are on and a URL is set
+pub async fn submit_due(client: &reqwest::Client, settings: &WallpaperSettings) {
+    let url = settings.usage_metrics_url.trim();
+    if !settings.usage_metrics || url.is_empty() {
+        return;
+    }
+    let log = load();
+    let today = today();
+    let days: BTreeMap<String, DayUsage> = log
+        .days
+        .iter()
+        .filter(|(day, _)| {
+            **day < today
+                && log
+                    .submitted_through
+                    .as_ref()
+                    .is_none_or(|last| *day > last)
+        })
+        .map(|(day, usage)| (day.clone(), usage.clone()))
+        .collect();
+    let Some(last) = days.keys().next_back().cloned() else {
+        return;
+    };
+
+    let submission = Submission {
+        os: std::env::consts::OS,
+        days: &days,
+    };
+    let result = client
+        .post(url)
+        .json(&submission)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status());
+    match result {
+        Ok(_) => {
+            info!("Submitted usage counts for {} day(s)", days.len());
+            // Reloaded, changes may have been counted while sending
+            let mut log = load();
+            log.submitted_through = Some(last);
+            save(&log);
+        }
+        Err(e) => warn!("Failed to submit usage counts: {}", e),
+    }
+}
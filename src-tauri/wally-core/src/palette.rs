@@ -0,0 +1,186 @@
+//! Dominant color palette extraction using median cut
+
+use crate::processing::parse_hex_color;
+use std::path::Path;
+
+/// Number of colors extracted for each wallpaper
+pub const PALETTE_SIZE: usize = 6;
+
+/// A box of pixels in RGB space that median cut keeps splitting
+struct ColorBox {
+    pixels: Vec<[u8; 3]>,
+}
+
+impl ColorBox {
+    /// The channel with the widest spread and the size of that spread
+    fn widest_channel(&self) -> (usize, u8) {
+        (0..3)
+            .map(|channel| {
+                let (min, max) = self
+                    .pixels
+                    .iter()
+                    .fold((u8::MAX, u8::MIN), |(min, max), pixel| {
+                        (min.min(pixel[channel]), max.max(pixel[channel]))
+                    });
+                (channel, max.saturating_sub(min))
+            })
+            .max_by_key(|&(_, range)| range)
+            .unwrap_or((0, 0))
+    }
+
+    fn split(mut self) -> (ColorBox, ColorBox) {
+        let (channel, _) = self.widest_channel();
+        self.pixels.sort_unstable_by_key(|pixel| pixel[channel]);
+        let upper = self.pixels.split_off(self.pixels.len() / 2);
+        (self, ColorBox { pixels: upper })
+    }
+
+    fn average(&self) -> [u8; 3] {
+        let count = self.pixels.len().max(1) as u64;
+        let sums = self.pixels.iter().fold([0u64; 3], |mut sums, pixel| {
+            for (sum, value) in sums.iter_mut().zip(pixel) {
+                *sum += *value as u64;
+            }
+            sums
+        });
+        [
+            (sums[0] / count) as u8,
+            (sums[1] / count) as u8,
+            (sums[2] / count) as u8,
+        ]
+    }
+}
+
+/// Extract up to `count` dominant colors as "#rrggbb", most common first
+pub fn extract_palette(path: &Path, count: usize) -> Result<Vec<String>, String> {
+    let image = image::open(path).map_err(|e| format!("Failed to open image: {}", e))?;
+    let pixels: Vec<[u8; 3]> = image
+        .thumbnail(128, 128)
+        .to_rgb8()
+        .pixels()
+        .map(|pixel| pixel.0)
+        .collect();
+
+    if pixels.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut boxes = vec![ColorBox { pixels }];
+    while boxes.len() < count {
+        // Split the box with the widest color range next
+        let Some((index, _)) = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, color_box)| color_box.pixels.len() > 1)
+            .max_by_key(|(_, color_box)| color_box.widest_channel().1)
+        else {
+            break;
+        };
+        let (lower, upper) = boxes.swap_remove(index).split();
+        boxes.push(lower);
+        boxes.push(upper);
+    }
+
+    boxes.sort_by_key(|color_box| std::cmp::Reverse(color_box.pixels.len()));
+    Ok(boxes
+        .iter()
+        .map(|color_box| {
+            let [r, g, b] = color_box.average();
+            format!("#{:02x}{:02x}{:02x}", r, g, b)
+        })
+        .collect())
+}
+
+/// Hue in degrees, saturation and value of an RGB color
+fn to_hsv([r, g, b]: [u8; 3]) -> (f32, f32, f32) {
+    let (r, g, b) = (r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0);
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let delta = max - min;
+
+    let hue = if delta == 0.0 {
+        0.0
+    } else if max == r {
+        60.0 * ((g - b) / delta).rem_euclid(6.0)
+    } else if max == g {
+        60.0 * ((b - r) / delta + 2.0)
+    } else {
+        60.0 * ((r - g) / delta + 4.0)
+    };
+    let saturation = if max == 0.0 { 0.0 } else { delta / max };
+
+    (hue, saturation, max)
+}
+
+/// Pick the most vivid palette color that is usable as a desktop accent color
+pub fn accent_color(palette: &[String]) -> Option<[u8; 3]> {
+    palette
+        .iter()
+        .filter_map(|color| parse_hex_color(color))
+        .filter(|&color| {
+            let (_, saturation, value) = to_hsv(color);
+            saturation >= 0.2 && value >= 0.25
+        })
+        .max_by(|&a, &b| {
+            let score = |color| {
+                let (_, saturation, value) = to_hsv(color);
+                saturation * value
+            };
+            score(a).total_cmp(&score(b))
+        })
+}
+
+/// Closest of GNOME's named accent colors
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+pub fn gnome_accent_name(color: [u8; 3]) -> &'static str {
+    let (hue, saturation, _) = to_hsv(color);
+    if saturation < 0.25 {
+        return "slate";
+    }
+    match hue {
+        h if h < 15.0 => "red",
+        h if h < 45.0 => "orange",
+        h if h < 70.0 => "yellow",
+        h if h < 160.0 => "green",
+        h if h < 195.0 => "teal",
+        h if h < 250.0 => "blue",
+        h if h < 290.0 => "purple",
+        h if h < 345.0 => "pink",
+        _ => "red",
+    }
+}
+
+/// Whether a color counts as one of Unsplash's color filters: "black_and_white",
+/// "black", "white", "yellow", "orange", "red", "purple", "magenta", "green", "teal"
+/// or "blue"
+pub fn matches_color_filter(color: &str, filter: &str) -> bool {
+    let Some(color) = parse_hex_color(color) else {
+        return true;
+    };
+    let (hue, saturation, value) = to_hsv(color);
+    let name = if value < 0.2 {
+        "black"
+    } else if saturation < 0.15 {
+        if value > 0.85 {
+            "white"
+        } else {
+            "gray"
+        }
+    } else {
+        match hue {
+            h if h < 15.0 => "red",
+            h if h < 45.0 => "orange",
+            h if h < 70.0 => "yellow",
+            h if h < 160.0 => "green",
+            h if h < 195.0 => "teal",
+            h if h < 255.0 => "blue",
+            h if h < 290.0 => "purple",
+            h if h < 345.0 => "magenta",
+            _ => "red",
+        }
+    };
+    match filter {
+        "black_and_white" => matches!(name, "black" | "white" | "gray"),
+        _ => name == filter,
+    }
+}
@@ -0,0 +1,73 @@
+//! Where config and downloaded wallpapers live
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Data folder next to the executable when a `portable` marker file sits beside it,
+/// for installs on a USB stick or without access to the user's profile
+pub fn portable_dir() -> Option<&'static Path> {
+    static PORTABLE_DIR: OnceLock<Option<PathBuf>> = OnceLock::new();
+    PORTABLE_DIR
+        .get_or_init(|| {
+            let exe_dir = std::env::current_exe().ok()?.parent()?.to_path_buf();
+            exe_dir
+                .join("portable")
+                .exists()
+                .then(|| exe_dir.join("wally-data"))
+        })
+        .as_deref()
+}
+
+pub fn get_config_dir() -> PathBuf {
+    let config_dir = match portable_dir() {
+        Some(dir) => dir.join("config"),
+        None => dirs::config_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join("unsplash-wally"),
+    };
+    fs::create_dir_all(&config_dir).ok();
+    config_dir
+}
+
+pub fn get_settings_path() -> PathBuf {
+    get_config_dir().join("settings.json")
+}
+
+pub fn get_log_dir() -> PathBuf {
+    let log_dir = get_config_dir().join("logs");
+    fs::create_dir_all(&log_dir).ok();
+    log_dir
+}
+
+/// Small previews of the downloaded wallpapers for the gallery
+pub fn get_thumbnail_dir() -> PathBuf {
+    let thumbnail_dir = get_config_dir().join("thumbnails");
+    fs::create_dir_all(&thumbnail_dir).ok();
+    thumbnail_dir
+}
+
+/// Crash reports written when a panic is recorded
+pub fn get_crash_dir() -> PathBuf {
+    let crash_dir = get_config_dir().join("crashes");
+    fs::create_dir_all(&crash_dir).ok();
+    crash_dir
+}
+
+/// Upscaled copies of photos smaller than the screen
+pub fn get_upscale_dir() -> PathBuf {
+    let upscale_dir = get_config_dir().join("upscaled");
+    fs::create_dir_all(&upscale_dir).ok();
+    upscale_dir
+}
+
+pub fn get_wallpaper_dir() -> PathBuf {
+    let wallpaper_dir = match portable_dir() {
+        Some(dir) => dir.join("wallpapers"),
+        None => dirs::picture_dir()
+            .unwrap_or_else(|| dirs::home_dir().unwrap_or_else(|| PathBuf::from(".")))
+            .join("unsplash_wallpapers"),
+    };
+    fs::create_dir_all(&wallpaper_dir).ok();
+    wallpaper_dir
+}
@@ -0,0 +1,918 @@
+//! Setting and reading the desktop wallpaper on each supported desktop
+
+use crate::WallyError;
+use serde::{Deserialize, Serialize};
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+use std::io::Read;
+#[cfg(target_os = "macos")]
+use std::path::PathBuf;
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+use std::process::{Command, Output, Stdio};
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+use std::sync::Mutex;
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+use std::thread::JoinHandle;
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+use std::time::{Duration, Instant};
+#[cfg(any(target_os = "linux", target_os = "windows"))]
+use tracing::debug;
+use tracing::info;
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+use tracing::warn;
+
+/// How long an external wallpaper tool gets before it's considered hung
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Backends that timed out, tried last until one of them works again
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+static TIMED_OUT_BACKENDS: Mutex<Vec<&'static str>> = Mutex::new(Vec::new());
+
+/// How long a command that only reads system state gets, they're run often and
+/// should answer right away
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Run a command, killing it with a `BackendTimeout` error if it hasn't finished
+/// within `timeout`
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn run_with_timeout(
+    backend: &str,
+    command: &mut Command,
+    timeout: Duration,
+) -> Result<Output, WallyError> {
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    // Drain the pipes on their own threads so a chatty tool can't block on a full pipe
+    let stdout = child.stdout.take().map(read_pipe);
+    let stderr = child.stderr.take().map(read_pipe);
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            warn!(
+                "{} did not finish within {}s, killed it",
+                backend,
+                timeout.as_secs()
+            );
+            return Err(WallyError::BackendTimeout {
+                backend: backend.to_string(),
+                timeout_secs: timeout.as_secs(),
+            });
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    };
+
+    let join = |pipe: Option<JoinHandle<Vec<u8>>>| {
+        pipe.and_then(|pipe| pipe.join().ok()).unwrap_or_default()
+    };
+    Ok(Output {
+        status,
+        stdout: join(stdout),
+        stderr: join(stderr),
+    })
+}
+
+/// Run a backend's command, killing it with a `BackendTimeout` error if it hasn't
+/// finished within `COMMAND_TIMEOUT`
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn run_backend(backend: &'static str, command: &mut Command) -> Result<Output, WallyError> {
+    let result = run_with_timeout(backend, command, COMMAND_TIMEOUT);
+    if let Ok(mut timed_out) = TIMED_OUT_BACKENDS.lock() {
+        match &result {
+            Err(WallyError::BackendTimeout { .. }) if !timed_out.contains(&backend) => {
+                timed_out.push(backend);
+            }
+            Ok(output) if output.status.success() => {
+                timed_out.retain(|timed_out| *timed_out != backend);
+            }
+            _ => {}
+        }
+    }
+    result
+}
+
+/// Run a command that reads system state, `None` when it can't be started or hangs.
+/// Unlike `run_backend` a hung probe doesn't change the order setters are tried in.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+pub(crate) fn run_probe(command: &mut Command) -> Option<Output> {
+    let program = command.get_program().to_string_lossy().to_string();
+    run_with_timeout(&program, command, PROBE_TIMEOUT).ok()
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn read_pipe(mut pipe: impl Read + Send + 'static) -> JoinHandle<Vec<u8>> {
+    std::thread::spawn(move || {
+        let mut buf = Vec::new();
+        let _ = pipe.read_to_end(&mut buf);
+        buf
+    })
+}
+
+/// Backends in the order to try them, with any that timed out moved to the end
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn backend_order<const N: usize>(mut backends: [&'static str; N]) -> [&'static str; N] {
+    if let Ok(timed_out) = TIMED_OUT_BACKENDS.lock() {
+        backends.sort_by_key(|backend| timed_out.contains(backend));
+    }
+    backends
+}
+
+/// Set the wallpaper with the desktop's backend. `all_spaces` is the
+/// `apply_to_all_spaces` setting, only used on macOS.
+pub fn set_wallpaper_platform(file_path: &str, all_spaces: bool) -> Result<(), WallyError> {
+    #[cfg(target_os = "macos")]
+    {
+        set_wallpaper_macos(file_path)?;
+        if all_spaces {
+            if let Err(e) = set_wallpaper_all_spaces_macos(file_path) {
+                warn!("Failed to apply wallpaper to all Spaces: {}", e);
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    let _ = all_spaces;
+
+    #[cfg(target_os = "linux")]
+    {
+        set_wallpaper_linux(file_path)?;
+        // A still wallpaper replaces a live one
+        stop_live_wallpaper_linux();
+        Ok(())
+    }
+
+    #[cfg(target_os = "windows")]
+    {
+        Ok(set_wallpaper_windows(file_path)?)
+    }
+
+    #[cfg(not(any(target_os = "macos", target_os = "linux", target_os = "windows")))]
+    {
+        Err(WallyError::UnsupportedDesktop(
+            "Unsupported platform".to_string(),
+        ))
+    }
+}
+
+#[cfg(target_os = "macos")]
+pub fn set_wallpaper_macos(file_path: &str) -> Result<(), WallyError> {
+    info!("Setting macOS wallpaper: {}", file_path);
+
+    // Use NSWorkspace via AppleScript - this is the most reliable method
+    let workspace_script = format!(
+        r#"
+        use framework "AppKit"
+        use scripting additions
+
+        set imageURL to current application's NSURL's fileURLWithPath:"{}"
+        set sharedWorkspace to current application's NSWorkspace's sharedWorkspace()
+        set allScreens to current application's NSScreen's screens()
+
+
+        repeat with aScreen in allScreens
+            set theOptions to current application's NSDictionary's dictionary()
+            sharedWorkspace's setDesktopImageURL:imageURL forScreen:aScreen options:theOptions |error|:(missing value)
+        end repeat
+        "#,
+        file_path
+    );
+
+    // Fallback to System Events
+    let system_events_script = format!(
+        r#"
+        tell application "System Events"
+            tell every desktop
+                set picture to "{}"
+            end tell
+        end tell
+        "#,
+        file_path
+    );
+
+    let mut timeout = None;
+    let mut last_error = String::from("No AppleScript method succeeded");
+    for backend in backend_order(["NSWorkspace", "System Events"]) {
+        let script = match backend {
+            "NSWorkspace" => &workspace_script,
+            _ => &system_events_script,
+        };
+        match run_backend(backend, Command::new("osascript").arg("-e").arg(script)) {
+            Ok(output) if output.status.success() => return Ok(()),
+            Ok(output) => {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                warn!("{} AppleScript error: {}", backend, stderr);
+                last_error = stderr.to_string();
+            }
+            Err(e @ WallyError::BackendTimeout { .. }) => {
+                timeout.get_or_insert(e);
+            }
+            Err(e) => last_error = format!("AppleScript failed: {}", e),
+        }
+    }
+
+    Err(timeout.unwrap_or_else(|| format!("All methods failed: {}", last_error).into()))
+}
+
+/// Location of the Dock's desktop picture database, which holds one entry per Space
+#[cfg(target_os = "macos")]
+fn get_desktop_picture_db() -> Option<PathBuf> {
+    let db = dirs::home_dir()?
+        .join("Library")
+        .join("Application Support")
+        .join("Dock")
+        .join("desktoppicture.db");
+    db.exists().then_some(db)
+}
+
+/// Point every Space's picture at the wallpaper in the desktop picture database.
+/// Only the active Space changes right away: the Dock reads the database when it
+/// starts, so other Spaces show it after a Dock restart or the next login, and until
+/// then the space watcher re-applies it when switching to them.
+#[cfg(target_os = "macos")]
+fn set_wallpaper_all_spaces_macos(file_path: &str) -> Result<(), WallyError> {
+    let db = get_desktop_picture_db().ok_or("Desktop picture database not found")?;
+
+    info!("Writing wallpaper to all Spaces: {}", db.display());
+
+    // `data` holds every preference value, shared between rows, so the path is added
+    // as a value of its own and only the picture path preferences (key 1) of the
+    // Spaces' pictures are pointed at it
+    let path = file_path.replace('\'', "''");
+    let query = format!(
+        "BEGIN;\
+         INSERT INTO data (value) SELECT '{path}' \
+         WHERE NOT EXISTS (SELECT 1 FROM data WHERE value = '{path}');\
+         UPDATE preferences SET data_id = (SELECT MAX(rowid) FROM data WHERE value = '{path}') \
+         WHERE key = 1 AND picture_id IN (SELECT rowid FROM pictures);\
+         COMMIT;"
+    );
+    let output = run_backend("sqlite3", Command::new("sqlite3").arg(&db).arg(&query))?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "Failed to update desktop picture database: {}",
+            String::from_utf8_lossy(&output.stderr)
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Write the wallpaper to every Space and restart the Dock so it shows right away,
+/// for when `apply_to_all_spaces` is turned on
+#[cfg(target_os = "macos")]
+pub fn apply_to_all_spaces_now(file_path: &str) -> Result<(), WallyError> {
+    set_wallpaper_all_spaces_macos(file_path)?;
+    // The Dock caches the database
+    let _ = run_backend("killall", Command::new("killall").arg("Dock"));
+    Ok(())
+}
+
+/// Get the current desktop picture path on macOS
+#[cfg(target_os = "macos")]
+pub fn get_current_desktop_picture() -> Option<String> {
+    let output = run_probe(
+        Command::new("osascript")
+            .arg("-e")
+            .arg(r#"tell application "System Events" to get picture of current desktop"#),
+    )?;
+
+    if output.status.success() {
+        let path = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        if !path.is_empty() {
+            return Some(path);
+        }
+    }
+    None
+}
+
+#[cfg(target_os = "windows")]
+pub fn set_wallpaper_windows(file_path: &str) -> Result<(), String> {
+    use std::path::Path;
+    use windows::core::{HSTRING, PCWSTR};
+    use windows::Win32::System::Com::{
+        CoCreateInstance, CoInitializeEx, CLSCTX_ALL, COINIT_APARTMENTTHREADED,
+    };
+    use windows::Win32::UI::Shell::{DesktopWallpaper, IDesktopWallpaper, DWPOS_FILL};
+
+    info!("Setting Windows wallpaper: {}", file_path);
+
+    // Verify file exists
+    if !Path::new(file_path).exists() {
+        return Err(format!("Wallpaper file does not exist: {}", file_path));
+    }
+    debug!("File exists, proceeding with IDesktopWallpaper");
+
+    unsafe {
+        // Initialize COM
+        let _ = CoInitializeEx(None, COINIT_APARTMENTTHREADED);
+
+        // Create IDesktopWallpaper instance
+        let wallpaper: IDesktopWallpaper = CoCreateInstance(&DesktopWallpaper, None, CLSCTX_ALL)
+            .map_err(|e| format!("Failed to create IDesktopWallpaper: {}", e))?;
+
+        // Convert path to HSTRING
+        let path = HSTRING::from(file_path);
+
+        // Set wallpaper position to Fill
+        wallpaper
+            .SetPosition(DWPOS_FILL)
+            .map_err(|e| format!("Failed to set wallpaper position: {}", e))?;
+
+        // Set the wallpaper (pass None for monitor ID to set on all monitors)
+        wallpaper
+            .SetWallpaper(PCWSTR::null(), &path)
+            .map_err(|e| format!("Failed to set wallpaper: {}", e))?;
+
+        info!("Windows wallpaper set successfully via IDesktopWallpaper");
+        Ok(())
+    }
+}
+
+/// Get the GUID of the active virtual desktop on Windows 10/11
+#[cfg(target_os = "windows")]
+pub fn get_current_virtual_desktop() -> Option<Vec<u8>> {
+    use windows::core::w;
+    use windows::Win32::Foundation::ERROR_SUCCESS;
+    use windows::Win32::System::Registry::{RegGetValueW, HKEY_CURRENT_USER, RRF_RT_REG_BINARY};
+
+    let mut buf = [0u8; 16];
+    let mut size = buf.len() as u32;
+
+    let status = unsafe {
+        RegGetValueW(
+            HKEY_CURRENT_USER,
+            w!("Software\\Microsoft\\Windows\\CurrentVersion\\Explorer\\VirtualDesktops"),
+            w!("CurrentVirtualDesktop"),
+            RRF_RT_REG_BINARY,
+            None,
+            Some(buf.as_mut_ptr() as *mut core::ffi::c_void),
+            Some(&mut size),
+        )
+    };
+
+    if status != ERROR_SUCCESS {
+        return None;
+    }
+    Some(buf[..size as usize].to_vec())
+}
+
+/// Number of virtual desktops on Windows 10/11
+#[cfg(target_os = "windows")]
+pub fn get_virtual_desktop_count() -> Option<usize> {
+    use windows::core::w;
+    use windows::Win32::Foundation::ERROR_SUCCESS;
+    use windows::Win32::System::Registry::{RegGetValueW, HKEY_CURRENT_USER, RRF_RT_REG_BINARY};
+
+    // The IDs are stored back to back, one 16 byte GUID per desktop
+    let mut size = 0u32;
+    let status = unsafe {
+        RegGetValueW(
+            HKEY_CURRENT_USER,
+            w!("Software\\Microsoft\\Windows\\CurrentVersion\\Explorer\\VirtualDesktops"),
+            w!("VirtualDesktopIDs"),
+            RRF_RT_REG_BINARY,
+            None,
+            None,
+            Some(&mut size),
+        )
+    };
+
+    if status != ERROR_SUCCESS {
+        return None;
+    }
+    Some(size as usize / 16)
+}
+
+/// Get the current desktop wallpaper path on Windows
+#[cfg(target_os = "windows")]
+pub fn get_current_desktop_picture() -> Option<String> {
+    use windows::Win32::UI::WindowsAndMessaging::{
+        SystemParametersInfoW, SPI_GETDESKWALLPAPER, SYSTEM_PARAMETERS_INFO_UPDATE_FLAGS,
+    };
+
+    let mut buf = [0u16; 260];
+    unsafe {
+        SystemParametersInfoW(
+            SPI_GETDESKWALLPAPER,
+            buf.len() as u32,
+            Some(buf.as_mut_ptr() as *mut core::ffi::c_void),
+            SYSTEM_PARAMETERS_INFO_UPDATE_FLAGS(0),
+        )
+        .ok()?;
+    }
+
+    let len = buf.iter().position(|&c| c == 0).unwrap_or(buf.len());
+    let path = String::from_utf16_lossy(&buf[..len]);
+    (!path.is_empty()).then_some(path)
+}
+
+#[cfg(target_os = "linux")]
+fn set_wallpaper_linux(file_path: &str) -> Result<(), WallyError> {
+    info!("Setting wallpaper for Linux");
+    debug!("File path: {}", file_path);
+
+    // Log environment for debugging
+    debug!(
+        "XDG_CURRENT_DESKTOP: {:?}",
+        std::env::var("XDG_CURRENT_DESKTOP")
+    );
+    debug!("KDE_FULL_SESSION: {:?}", std::env::var("KDE_FULL_SESSION"));
+    debug!("XDG_SESSION_TYPE: {:?}", std::env::var("XDG_SESSION_TYPE"));
+
+    // Check if file exists
+    if !std::path::Path::new(file_path).exists() {
+        return Err(format!("Wallpaper file does not exist: {}", file_path).into());
+    }
+    debug!("File exists: true");
+
+    // Try KDE Plasma first
+    if is_kde() {
+        info!("Detected KDE Plasma");
+        return set_wallpaper_kde(file_path);
+    }
+
+    // Try GNOME
+    if is_gnome() {
+        info!("Detected GNOME");
+        return set_wallpaper_gnome(file_path);
+    }
+
+    Err(WallyError::UnsupportedDesktop(
+        "Unsupported Linux desktop environment. Currently supports KDE Plasma and GNOME."
+            .to_string(),
+    ))
+}
+
+#[cfg(target_os = "linux")]
+pub fn is_kde() -> bool {
+    std::env::var("KDE_FULL_SESSION").is_ok()
+        || std::env::var("XDG_CURRENT_DESKTOP")
+            .map(|d| d.to_lowercase().contains("kde"))
+            .unwrap_or(false)
+}
+
+#[cfg(target_os = "linux")]
+pub fn is_gnome() -> bool {
+    std::env::var("GNOME_DESKTOP_SESSION_ID").is_ok()
+        || std::env::var("XDG_CURRENT_DESKTOP")
+            .map(|d| d.to_lowercase().contains("gnome"))
+            .unwrap_or(false)
+}
+
+/// Whether this is a wlroots-based Wayland compositor such as Sway or Hyprland
+#[cfg(target_os = "linux")]
+pub fn is_wlroots() -> bool {
+    const COMPOSITORS: [&str; 6] = ["sway", "hyprland", "river", "wayfire", "labwc", "niri"];
+    std::env::var("SWAYSOCK").is_ok()
+        || std::env::var("HYPRLAND_INSTANCE_SIGNATURE").is_ok()
+        || std::env::var("XDG_CURRENT_DESKTOP").is_ok_and(|desktop| {
+            let desktop = desktop.to_lowercase();
+            COMPOSITORS.iter().any(|name| desktop.contains(name))
+        })
+}
+
+/// Build a Plasma script that sets the wallpaper on every desktop containment,
+/// optionally restricted to the containments of a single KDE Activity
+#[cfg(target_os = "linux")]
+fn kde_wallpaper_script(file_path: &str, activity_id: Option<&str>) -> String {
+    let filter = activity_id
+        .map(|id| format!("if (desktop.activity !== '{}') continue;", id))
+        .unwrap_or_default();
+
+    // Plasma 6 script for setting wallpaper
+    format!(
+        r#"
+        const allDesktops = desktops();
+        for (const desktop of allDesktops) {{
+            {}
+            desktop.currentConfigGroup = ['Wallpaper', 'org.kde.image', 'General'];
+            desktop.writeConfig('Image', 'file://{}');
+        }}
+        "#,
+        filter, file_path
+    )
+}
+
+/// Run a qdbus call, trying qdbus6 (Plasma 6 / Qt6) first and falling back to qdbus
+#[cfg(target_os = "linux")]
+fn run_qdbus(args: &[&str]) -> Result<String, WallyError> {
+    let mut timeout = None;
+    let mut last_error = String::from("No qdbus command succeeded");
+
+    for qdbus_cmd in backend_order(["qdbus6", "qdbus"]) {
+        match run_backend(qdbus_cmd, Command::new(qdbus_cmd).args(args)) {
+            Ok(output) if output.status.success() => {
+                return Ok(String::from_utf8_lossy(&output.stdout).trim().to_string());
+            }
+            Ok(output) => {
+                last_error = format!(
+                    "{} failed: {}",
+                    qdbus_cmd,
+                    String::from_utf8_lossy(&output.stderr)
+                );
+            }
+            Err(e @ WallyError::BackendTimeout { .. }) => {
+                timeout.get_or_insert(e);
+            }
+            Err(e) => {
+                last_error = format!("{} error: {}", qdbus_cmd, e);
+            }
+        }
+    }
+
+    Err(timeout.unwrap_or_else(|| last_error.into()))
+}
+
+#[cfg(target_os = "linux")]
+fn set_wallpaper_kde(file_path: &str) -> Result<(), WallyError> {
+    let script = kde_wallpaper_script(file_path, None);
+
+    debug!("KDE script:\n{}", script);
+
+    // Try qdbus6 first (Plasma 6 / Qt6), then qdbus, then plasma-apply-wallpaperimage (Plasma 6)
+    let mut timeout = None;
+    let mut last_error = String::from("No qdbus command succeeded");
+
+    for backend in backend_order(["qdbus6", "qdbus", "plasma-apply-wallpaperimage"]) {
+        debug!("Trying {}...", backend);
+
+        let mut command = Command::new(backend);
+        if backend == "plasma-apply-wallpaperimage" {
+            command.arg(file_path);
+        } else {
+            command.args([
+                "org.kde.plasmashell",
+                "/PlasmaShell",
+                "org.kde.PlasmaShell.evaluateScript",
+                &script,
+            ]);
+        }
+
+        match run_backend(backend, &mut command) {
+            Ok(output) => {
+                let stdout = String::from_utf8_lossy(&output.stdout);
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                debug!("{} exit status: {}", backend, output.status);
+                debug!("{} stdout: {}", backend, stdout);
+                debug!("{} stderr: {}", backend, stderr);
+
+                if output.status.success() {
+                    info!("Successfully set wallpaper via {}", backend);
+                    return Ok(());
+                }
+
+                last_error = format!("{} failed: {}", backend, stderr);
+            }
+            // A hung plasmashell doesn't stop the next backend from being tried
+            Err(e @ WallyError::BackendTimeout { .. }) => {
+                timeout.get_or_insert(e);
+            }
+            Err(e) => {
+                warn!("{} not found or failed to execute: {}", backend, e);
+                last_error = format!("{} error: {}", backend, e);
+            }
+        }
+    }
+
+    Err(timeout.unwrap_or_else(|| {
+        format!("Failed to set KDE wallpaper. Last error: {}", last_error).into()
+    }))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KdeActivity {
+    pub id: String,
+    pub name: String,
+    pub current: bool,
+}
+
+#[cfg(target_os = "linux")]
+pub fn list_kde_activities_linux() -> Result<Vec<KdeActivity>, WallyError> {
+    const SERVICE: &str = "org.kde.ActivityManager";
+    const PATH: &str = "/ActivityManager/Activities";
+
+    let current = run_qdbus(&[
+        SERVICE,
+        PATH,
+        "org.kde.ActivityManager.Activities.CurrentActivity",
+    ])
+    .unwrap_or_default();
+
+    let ids = run_qdbus(&[
+        SERVICE,
+        PATH,
+        "org.kde.ActivityManager.Activities.ListActivities",
+    ])?;
+
+    Ok(ids
+        .lines()
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(|id| {
+            let name = run_qdbus(&[
+                SERVICE,
+                PATH,
+                "org.kde.ActivityManager.Activities.ActivityName",
+                id,
+            ])
+            .unwrap_or_else(|_| id.to_string());
+            KdeActivity {
+                id: id.to_string(),
+                name,
+                current: id == current,
+            }
+        })
+        .collect())
+}
+
+#[cfg(target_os = "linux")]
+pub fn set_wallpaper_kde_activity(file_path: &str, activity_id: &str) -> Result<(), WallyError> {
+    // Activity IDs are UUIDs; reject anything else so it can't break out of the script
+    if activity_id.is_empty()
+        || !activity_id
+            .chars()
+            .all(|c| c.is_ascii_hexdigit() || c == '-')
+    {
+        return Err(format!("Invalid activity ID: {}", activity_id).into());
+    }
+
+    let script = kde_wallpaper_script(file_path, Some(activity_id));
+    run_qdbus(&[
+        "org.kde.plasmashell",
+        "/PlasmaShell",
+        "org.kde.PlasmaShell.evaluateScript",
+        &script,
+    ])
+    .map(|_| ())
+    .map_err(|e| match e {
+        WallyError::BackendTimeout { .. } => e,
+        e => format!("Failed to set wallpaper for activity: {}", e).into(),
+    })
+}
+
+/// Get the current GNOME wallpaper path. KDE has no simple way to read it back.
+#[cfg(target_os = "linux")]
+pub fn get_current_desktop_picture() -> Option<String> {
+    if !is_gnome() {
+        return None;
+    }
+    let output = run_probe(Command::new("gsettings").args([
+        "get",
+        "org.gnome.desktop.background",
+        "picture-uri",
+    ]))?;
+    let uri = String::from_utf8_lossy(&output.stdout);
+    let path = uri.trim().trim_matches('\'').strip_prefix("file://")?;
+    Some(path.to_string())
+}
+
+#[cfg(target_os = "linux")]
+fn set_wallpaper_gnome(file_path: &str) -> Result<(), WallyError> {
+    let file_uri = format!("file://{}", file_path);
+
+    let output = run_backend(
+        "gsettings",
+        Command::new("gsettings").args([
+            "set",
+            "org.gnome.desktop.background",
+            "picture-uri",
+            &file_uri,
+        ]),
+    )?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("Failed to set GNOME wallpaper: {}", stderr).into());
+    }
+
+    // Also set for dark mode
+    let _ = run_backend(
+        "gsettings",
+        Command::new("gsettings").args([
+            "set",
+            "org.gnome.desktop.background",
+            "picture-uri-dark",
+            &file_uri,
+        ]),
+    );
+
+    Ok(())
+}
+
+/// Extensions `set_live_wallpaper_linux` plays as video rather than as an animated image
+const VIDEO_EXTENSIONS: [&str; 5] = ["mp4", "webm", "mkv", "mov", "avi"];
+
+/// Whether a file is a video, going by its extension
+pub fn is_video(path: &str) -> bool {
+    std::path::Path::new(path).extension().is_some_and(|ext| {
+        VIDEO_EXTENSIONS.contains(&ext.to_ascii_lowercase().to_string_lossy().as_ref())
+    })
+}
+
+/// The mpvpaper process playing the live wallpaper, it runs until replaced
+#[cfg(target_os = "linux")]
+static MPVPAPER: Mutex<Option<std::process::Child>> = Mutex::new(None);
+
+/// Program that can play a live wallpaper here: mpvpaper for videos and animated
+/// images, or swww for animated images only. wlroots compositors only.
+pub fn live_wallpaper_backend() -> Option<&'static str> {
+    #[cfg(target_os = "linux")]
+    {
+        if !is_wlroots() {
+            return None;
+        }
+        ["mpvpaper", "swww"]
+            .into_iter()
+            .find(|tool| crate::system::find_in_path(tool).is_some())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        None
+    }
+}
+
+/// Play a video or animated image as the wallpaper on every output
+#[cfg(target_os = "linux")]
+pub fn set_live_wallpaper_linux(file_path: &str) -> Result<(), WallyError> {
+    if !std::path::Path::new(file_path).exists() {
+        return Err(format!("Wallpaper file does not exist: {}", file_path).into());
+    }
+    let backend = live_wallpaper_backend().ok_or_else(|| {
+        WallyError::UnsupportedDesktop(
+            "Live wallpapers need mpvpaper or swww on a wlroots compositor".to_string(),
+        )
+    })?;
+    info!("Setting live wallpaper via {}: {}", backend, file_path);
+    stop_live_wallpaper_linux();
+
+    if backend == "swww" {
+        if is_video(file_path) {
+            return Err(WallyError::UnsupportedDesktop(
+                "swww only plays animated images, install mpvpaper for videos".to_string(),
+            ));
+        }
+        let output = run_backend("swww", Command::new("swww").args(["img", file_path]))?;
+        if !output.status.success() {
+            return Err(format!(
+                "Failed to set live wallpaper: {}",
+                String::from_utf8_lossy(&output.stderr)
+            )
+            .into());
+        }
+        return Ok(());
+    }
+
+    let mut child = Command::new("mpvpaper")
+        .args(["-o", "no-audio loop", "ALL", file_path])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    // mpvpaper exits right away when it can't attach to the outputs
+    std::thread::sleep(Duration::from_millis(500));
+    if let Some(status) = child.try_wait()? {
+        return Err(format!("mpvpaper exited immediately ({})", status).into());
+    }
+    if let Ok(mut mpvpaper) = MPVPAPER.lock() {
+        *mpvpaper = Some(child);
+    }
+    Ok(())
+}
+
+/// Stop the mpvpaper process started by `set_live_wallpaper_linux`, if any. An
+/// animated image shown by swww stays until the next wallpaper replaces it.
+#[cfg(target_os = "linux")]
+pub fn stop_live_wallpaper_linux() {
+    let child = MPVPAPER
+        .lock()
+        .ok()
+        .and_then(|mut mpvpaper| mpvpaper.take());
+    if let Some(mut child) = child {
+        info!("Stopping live wallpaper");
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+}
+
+/// Whether the operating system is currently using a dark appearance
+pub fn is_dark_mode() -> bool {
+    #[cfg(target_os = "macos")]
+    {
+        run_probe(Command::new("defaults").args(["read", "-g", "AppleInterfaceStyle"]))
+            .map(|output| String::from_utf8_lossy(&output.stdout).trim() == "Dark")
+            .unwrap_or(false)
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        if is_kde() {
+            for kreadconfig in ["kreadconfig6", "kreadconfig5"] {
+                if let Some(output) = run_probe(Command::new(kreadconfig).args([
+                    "--file",
+                    "kdeglobals",
+                    "--group",
+                    "General",
+                    "--key",
+                    "ColorScheme",
+                ])) {
+                    if output.status.success() {
+                        return String::from_utf8_lossy(&output.stdout)
+                            .to_lowercase()
+                            .contains("dark");
+                    }
+                }
+            }
+        }
+
+        run_probe(Command::new("gsettings").args([
+            "get",
+            "org.gnome.desktop.interface",
+            "color-scheme",
+        ]))
+        .map(|output| String::from_utf8_lossy(&output.stdout).contains("prefer-dark"))
+        .unwrap_or(false)
+    }
+
+    #[cfg(target_os = "windows")]
+    {
+        use windows::core::w;
+        use windows::Win32::Foundation::ERROR_SUCCESS;
+        use windows::Win32::System::Registry::{RegGetValueW, HKEY_CURRENT_USER, RRF_RT_REG_DWORD};
+
+        let mut value: u32 = 1;
+        let mut size = std::mem::size_of::<u32>() as u32;
+        let status = unsafe {
+            RegGetValueW(
+                HKEY_CURRENT_USER,
+                w!("Software\\Microsoft\\Windows\\CurrentVersion\\Themes\\Personalize"),
+                w!("AppsUseLightTheme"),
+                RRF_RT_REG_DWORD,
+                None,
+                Some(&mut value as *mut u32 as *mut core::ffi::c_void),
+                Some(&mut size),
+            )
+        };
+        status == ERROR_SUCCESS && value == 0
+    }
+
+    #[cfg(not(any(target_os = "macos", target_os = "linux", target_os = "windows")))]
+    {
+        false
+    }
+}
+
+/// Wallpaper backend in use: "macos", "windows", "linux-kde", "linux-gnome" or "linux"
+pub fn get_platform() -> String {
+    #[cfg(target_os = "macos")]
+    {
+        "macos".to_string()
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        if is_kde() {
+            "linux-kde".to_string()
+        } else if is_gnome() {
+            "linux-gnome".to_string()
+        } else {
+            "linux".to_string()
+        }
+    }
+
+    #[cfg(target_os = "windows")]
+    {
+        "windows".to_string()
+    }
+
+    #[cfg(not(any(target_os = "macos", target_os = "linux", target_os = "windows")))]
+    {
+        "unknown".to_string()
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn is_kde() -> bool {
+    false
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn is_gnome() -> bool {
+    false
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux", target_os = "windows")))]
+pub fn get_current_desktop_picture() -> Option<String> {
+    None
+}
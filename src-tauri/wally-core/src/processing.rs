@@ -0,0 +1,390 @@
+//! Local post-processing applied to downloaded wallpapers before they are set
+
+use crate::upscale;
+use crate::{ScreenResolution, WallpaperSettings};
+use ab_glyph::{point, Font, FontVec, PxScale, ScaleFont};
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageFormat, Rgba, RgbaImage};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+/// Context for a single processing run, alongside the user's settings
+pub struct ProcessOptions {
+    /// Screen to resize to, if known
+    pub screen: Option<ScreenResolution>,
+    /// Attribution caption for the photo, if known
+    pub caption: Option<String>,
+    /// Whether the system is currently in dark mode
+    pub dark_mode: bool,
+}
+
+/// Downscale and crop an image so it exactly fills the screen.
+/// Images smaller than the screen are left alone rather than upscaled.
+pub fn resize_to_screen(
+    image: DynamicImage,
+    screen: ScreenResolution,
+    smart_crop: bool,
+) -> DynamicImage {
+    if screen.width == 0 || screen.height == 0 {
+        return image;
+    }
+    if image.width() <= screen.width || image.height() <= screen.height {
+        return image;
+    }
+    fill(image, screen.width, screen.height, smart_crop)
+}
+
+/// Resize and crop an image to exactly `width` x `height`, cropping around the most
+/// detailed area with `smart_crop` and at the center otherwise
+pub fn fill(image: DynamicImage, width: u32, height: u32, smart_crop: bool) -> DynamicImage {
+    if !smart_crop {
+        return image.resize_to_fill(width, height, FilterType::Lanczos3);
+    }
+    let (x, y, crop_width, crop_height) = salient_crop(&image, width, height);
+    image
+        .crop_imm(x, y, crop_width, crop_height)
+        .resize_exact(width, height, FilterType::Lanczos3)
+}
+
+/// Width of the copy edge detail is measured on, enough to find the subject
+const SALIENCY_SIZE: u32 = 128;
+
+/// The region with the aspect ratio of `target_width` x `target_height` holding the
+/// most edge detail, which is where subjects tend to be. Returns `(x, y, width, height)`.
+fn salient_crop(
+    image: &DynamicImage,
+    target_width: u32,
+    target_height: u32,
+) -> (u32, u32, u32, u32) {
+    let (width, height) = (image.width(), image.height());
+    let screen_ratio = f64::from(target_width) / f64::from(target_height.max(1));
+    let crop_sideways = f64::from(width) / f64::from(height) > screen_ratio;
+    let (crop_width, crop_height) = if crop_sideways {
+        (
+            ((f64::from(height) * screen_ratio).round() as u32).min(width),
+            height,
+        )
+    } else {
+        (
+            width,
+            ((f64::from(width) / screen_ratio).round() as u32).min(height),
+        )
+    };
+
+    let small = image.thumbnail(SALIENCY_SIZE, SALIENCY_SIZE).to_luma8();
+    let (small_width, small_height) = small.dimensions();
+    let axis_len = if crop_sideways {
+        small_width
+    } else {
+        small_height
+    };
+    let mut energy = vec![0u64; axis_len as usize];
+    for y in 1..small_height.saturating_sub(1) {
+        for x in 1..small_width.saturating_sub(1) {
+            let at = |x: u32, y: u32| small.get_pixel(x, y)[0];
+            let gradient = at(x + 1, y).abs_diff(at(x - 1, y)) as u64
+                + at(x, y + 1).abs_diff(at(x, y - 1)) as u64;
+            energy[if crop_sideways { x } else { y } as usize] += gradient;
+        }
+    }
+
+    let (full_len, crop_len) = if crop_sideways {
+        (width, crop_width)
+    } else {
+        (height, crop_height)
+    };
+    let window =
+        ((f64::from(crop_len) / f64::from(full_len)) * f64::from(axis_len)).round() as usize;
+    let offset = best_window(&energy, window.clamp(1, axis_len.max(1) as usize));
+    let offset =
+        ((offset as f64 / f64::from(axis_len.max(1))) * f64::from(full_len)).round() as u32;
+    let offset = offset.min(full_len - crop_len);
+
+    if crop_sideways {
+        (offset, 0, crop_width, crop_height)
+    } else {
+        (0, offset, crop_width, crop_height)
+    }
+}
+
+/// Start of the `window` consecutive cells with the most energy, preferring the
+/// one nearest the center on ties so plain images still crop in the middle
+fn best_window(energy: &[u64], window: usize) -> usize {
+    if window >= energy.len() {
+        return 0;
+    }
+    let center = (energy.len() - window) / 2;
+    let mut sum: u64 = energy[..window].iter().sum();
+    let (mut best, mut best_sum) = (0usize, sum);
+    for start in 1..=energy.len() - window {
+        sum = sum + energy[start + window - 1] - energy[start - 1];
+        let nearer = start.abs_diff(center) < best.abs_diff(center);
+        if sum > best_sum || (sum == best_sum && nearer) {
+            best = start;
+            best_sum = sum;
+        }
+    }
+    best
+}
+
+/// Parse a "#rrggbb" color
+pub fn parse_hex_color(color: &str) -> Option<[u8; 3]> {
+    let hex = color.trim().trim_start_matches('#');
+    if hex.len() != 6 {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+    Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+fn has_filters(settings: &WallpaperSettings) -> bool {
+    settings.blur_radius > 0.0
+        || settings.dim_percent > 0
+        || settings.vignette > 0.0
+        || settings.grayscale
+        || parse_hex_color(&settings.tint_color).is_some()
+}
+
+/// Whether processing with these settings would change an image again when run on
+/// its own output. Resizing is safe to repeat, filters and the caption are not.
+pub fn is_cumulative(settings: &WallpaperSettings) -> bool {
+    has_filters(settings) || settings.dark_mode_adjust || settings.attribution_overlay
+}
+
+/// Apply the blur, grayscale, tint, dim and vignette filters so busy photos can be
+/// toned down behind desktop icons
+pub fn apply_filters(image: DynamicImage, settings: &WallpaperSettings) -> DynamicImage {
+    let mut image = image;
+
+    if settings.blur_radius > 0.0 {
+        image = image.fast_blur(settings.blur_radius);
+    }
+    if settings.grayscale {
+        image = image.grayscale();
+    }
+
+    let mut rgba = image.to_rgba8();
+    let (width, height) = rgba.dimensions();
+
+    if let Some(tint) = parse_hex_color(&settings.tint_color) {
+        let strength = settings.tint_strength.clamp(0.0, 1.0);
+        for pixel in rgba.pixels_mut() {
+            blend(pixel, tint, strength);
+        }
+    }
+
+    let brightness = 1.0 - settings.dim_percent.min(100) as f32 / 100.0;
+    let vignette = settings.vignette.clamp(0.0, 1.0);
+    if brightness < 1.0 || vignette > 0.0 {
+        let center_x = width as f32 / 2.0;
+        let center_y = height as f32 / 2.0;
+        let max_distance = (center_x * center_x + center_y * center_y).sqrt().max(1.0);
+
+        for (x, y, pixel) in rgba.enumerate_pixels_mut() {
+            let mut factor = brightness;
+            if vignette > 0.0 {
+                let dx = x as f32 - center_x;
+                let dy = y as f32 - center_y;
+                let distance = (dx * dx + dy * dy).sqrt() / max_distance;
+                factor *= 1.0 - vignette * distance * distance;
+            }
+            for channel in pixel.0.iter_mut().take(3) {
+                *channel = (*channel as f32 * factor).round() as u8;
+            }
+        }
+    }
+
+    DynamicImage::ImageRgba8(rgba)
+}
+
+/// Reduce brightness and contrast so wallpapers aren't blinding next to a dark UI
+pub fn darken_for_dark_mode(image: DynamicImage, settings: &WallpaperSettings) -> DynamicImage {
+    let mut image = image;
+    if settings.dark_mode_contrast != 0.0 {
+        image = image.adjust_contrast(settings.dark_mode_contrast);
+    }
+
+    let factor = 1.0 - settings.dark_mode_dim_percent.min(100) as f32 / 100.0;
+    if factor < 1.0 {
+        let mut rgba = image.to_rgba8();
+        for pixel in rgba.pixels_mut() {
+            for channel in pixel.0.iter_mut().take(3) {
+                *channel = (*channel as f32 * factor).round() as u8;
+            }
+        }
+        image = DynamicImage::ImageRgba8(rgba);
+    }
+
+    image
+}
+
+/// Fonts tried, in order, for rendering the attribution caption
+const CAPTION_FONTS: &[&str] = &[
+    // Linux
+    "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/TTF/DejaVuSans.ttf",
+    "/usr/share/fonts/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/dejavu-sans-fonts/DejaVuSans.ttf",
+    "/usr/share/fonts/truetype/noto/NotoSans-Regular.ttf",
+    "/usr/share/fonts/noto/NotoSans-Regular.ttf",
+    "/usr/share/fonts/google-noto/NotoSans-Regular.ttf",
+    // macOS
+    "/System/Library/Fonts/Supplemental/Arial.ttf",
+    "/Library/Fonts/Arial.ttf",
+    "/System/Library/Fonts/Helvetica.ttc",
+    // Windows
+    "C:\\Windows\\Fonts\\segoeui.ttf",
+    "C:\\Windows\\Fonts\\arial.ttf",
+];
+
+fn load_caption_font() -> Option<FontVec> {
+    CAPTION_FONTS.iter().find_map(|path| {
+        let data = fs::read(path).ok()?;
+        FontVec::try_from_vec_and_index(data, 0).ok()
+    })
+}
+
+/// Alpha-blend a color onto a pixel
+fn blend(pixel: &mut Rgba<u8>, color: [u8; 3], alpha: f32) {
+    let alpha = alpha.clamp(0.0, 1.0);
+    for (channel, value) in pixel.0.iter_mut().zip(color) {
+        *channel = (value as f32 * alpha + *channel as f32 * (1.0 - alpha)).round() as u8;
+    }
+}
+
+/// Composite a caption onto a corner of the image on top of a translucent backdrop
+pub fn draw_attribution(
+    image: &mut RgbaImage,
+    text: &str,
+    settings: &WallpaperSettings,
+) -> Result<(), String> {
+    let font = load_caption_font().ok_or("No usable font found for the attribution caption")?;
+
+    let size = settings.attribution_font_size.max(6.0) * (image.height() as f32 / 1080.0).max(1.0);
+    let opacity = settings.attribution_opacity.clamp(0.0, 1.0);
+    let scaled = font.as_scaled(PxScale::from(size));
+
+    // Lay out the glyphs on a single line
+    let mut glyphs = Vec::new();
+    let mut caret = 0.0;
+    let mut previous = None;
+    for c in text.chars() {
+        let id = scaled.glyph_id(c);
+        if let Some(previous) = previous {
+            caret += scaled.kern(previous, id);
+        }
+        glyphs.push(id.with_scale_and_position(size, point(caret, scaled.ascent())));
+        caret += scaled.h_advance(id);
+        previous = Some(id);
+    }
+
+    let padding = (size * 0.5).round() as u32;
+    let margin = size.round() as u32;
+    let box_width = caret.ceil() as u32 + padding * 2;
+    let box_height = scaled.height().ceil() as u32 + padding * 2;
+    if box_width + margin * 2 > image.width() || box_height + margin * 2 > image.height() {
+        return Err("Image is too small for the attribution caption".to_string());
+    }
+
+    let left = match settings.attribution_corner.as_str() {
+        "top-left" | "bottom-left" => margin,
+        _ => image.width() - box_width - margin,
+    };
+    let top = match settings.attribution_corner.as_str() {
+        "top-left" | "top-right" => margin,
+        _ => image.height() - box_height - margin,
+    };
+
+    for y in top..top + box_height {
+        for x in left..left + box_width {
+            blend(image.get_pixel_mut(x, y), [0, 0, 0], opacity * 0.5);
+        }
+    }
+
+    for glyph in glyphs {
+        let Some(outline) = font.outline_glyph(glyph) else {
+            continue;
+        };
+        let bounds = outline.px_bounds();
+        outline.draw(|gx, gy, coverage| {
+            let x = left as i64 + padding as i64 + bounds.min.x as i64 + gx as i64;
+            let y = top as i64 + padding as i64 + bounds.min.y as i64 + gy as i64;
+            if x >= 0 && y >= 0 && (x as u32) < image.width() && (y as u32) < image.height() {
+                blend(
+                    image.get_pixel_mut(x as u32, y as u32),
+                    [255, 255, 255],
+                    coverage * opacity,
+                );
+            }
+        });
+    }
+
+    Ok(())
+}
+
+/// Write an image back to disk in the format implied by its extension
+pub(crate) fn save_image(image: &DynamicImage, path: &Path) -> Result<(), String> {
+    let format = ImageFormat::from_path(path).map_err(|e| e.to_string())?;
+
+    if format == ImageFormat::Jpeg {
+        let file = fs::File::create(path).map_err(|e| e.to_string())?;
+        let mut encoder = JpegEncoder::new_with_quality(std::io::BufWriter::new(file), 92);
+        return encoder
+            .encode_image(&image.to_rgb8())
+            .map_err(|e| e.to_string());
+    }
+
+    image
+        .save_with_format(path, format)
+        .map_err(|e| e.to_string())
+}
+
+/// Apply the enabled post-processing steps to a downloaded wallpaper in place
+pub fn process_wallpaper(
+    path: PathBuf,
+    settings: &WallpaperSettings,
+    options: &ProcessOptions,
+) -> Result<PathBuf, String> {
+    let resize_screen = options.screen.filter(|_| settings.resize_to_screen);
+    let caption = options
+        .caption
+        .as_deref()
+        .filter(|_| settings.attribution_overlay);
+    let filters = has_filters(settings);
+    let darken = options.dark_mode && settings.dark_mode_adjust;
+    let upscaled = options
+        .screen
+        .filter(|_| settings.upscale_low_resolution)
+        .and_then(|screen| upscale::upscale_for_screen(&path, screen));
+    if upscaled.is_none() && resize_screen.is_none() && !filters && !darken && caption.is_none() {
+        return Ok(path);
+    }
+
+    let source = upscaled.as_deref().unwrap_or(&path);
+    let mut image = image::open(source).map_err(|e| format!("Failed to open image: {}", e))?;
+
+    if let Some(screen) = resize_screen {
+        image = resize_to_screen(image, screen, settings.smart_crop);
+    }
+
+    if filters {
+        image = apply_filters(image, settings);
+    }
+
+    if darken {
+        image = darken_for_dark_mode(image, settings);
+    }
+
+    if let Some(caption) = caption {
+        let mut rgba = image.to_rgba8();
+        match draw_attribution(&mut rgba, caption, settings) {
+            Ok(()) => image = DynamicImage::ImageRgba8(rgba),
+            Err(e) => warn!("Skipping attribution overlay: {}", e),
+        }
+    }
+
+    save_image(&image, &path).map_err(|e| format!("Failed to save processed image: {}", e))?;
+    Ok(path)
+}
@@ -0,0 +1,882 @@
+//! One wallpaper change from start to finish: fetching a random photo, downloading
+//! and processing it, setting it and recording it as the current wallpaper. Shared
+//! by the app's daemon and the headless `wally-daemon`.
+
+use crate::download::{
+    cleanup_old_wallpapers, fetch_wallpaper_file, filename_stem, is_image_extension,
+    process_downloaded_wallpaper,
+};
+use crate::paths::get_wallpaper_dir;
+use crate::platform::set_wallpaper_platform;
+use crate::storage::{
+    advance_rotation_cursor, load_current_wallpaper, load_history, load_queue, save_queue,
+    write_current_wallpaper,
+};
+use crate::unsplash::{
+    api_error, random_photo_params, random_photo_resource, send_with_retry, sized_image_url,
+    SearchPage,
+};
+use crate::{
+    collage, palette, schedule, similarity, system, weather, CurrentWallpaper, ScreenResolution,
+    UnsplashImage, WallpaperSettings, WallyError,
+};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+/// A wallpaper downloaded and processed ahead of time, ready to be set
+pub struct PreparedWallpaper {
+    pub image: UnsplashImage,
+    pub local_path: PathBuf,
+    pub phash: Option<u64>,
+    /// Taken from the queue, so it leaves the queue once it's set
+    pub queued: bool,
+}
+
+/// Attempts at a queued photo before it's dropped from the queue
+const MAX_QUEUED_ATTEMPTS: u32 = 3;
+
+/// Failed attempts at each queued photo, by ID
+static QUEUED_FAILURES: Mutex<BTreeMap<String, u32>> = Mutex::new(BTreeMap::new());
+
+/// Extra random photos fetched when the candidate looks like a recent wallpaper
+const MAX_SIMILAR_REROLLS: usize = 3;
+
+/// Extra random photos fetched when the candidate is too small or badly shaped for
+/// the screen, or not in the filtered color
+const MAX_FILTER_REROLLS: usize = 5;
+
+/// Extract the dominant colors of a wallpaper off the async runtime
+pub async fn extract_wallpaper_palette(local_path: &str) -> Vec<String> {
+    let path = PathBuf::from(local_path);
+    let result =
+        tokio::task::spawn_blocking(move || palette::extract_palette(&path, palette::PALETTE_SIZE))
+            .await
+            .map_err(|e| e.to_string())
+            .and_then(|result| result);
+
+    result.unwrap_or_else(|e| {
+        warn!("Failed to extract palette: {}", e);
+        Vec::new()
+    })
+}
+
+pub async fn wallpaper_phash(path: PathBuf) -> Option<u64> {
+    let result = tokio::task::spawn_blocking(move || similarity::perceptual_hash(&path))
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|result| result);
+
+    result
+        .inspect_err(|e| warn!("Failed to hash wallpaper: {}", e))
+        .ok()
+}
+
+/// Perceptual hashes of the wallpapers in the history
+fn recent_phashes() -> Vec<u64> {
+    load_history()
+        .iter()
+        .filter_map(|entry| u64::from_str_radix(entry.phash.as_deref()?, 16).ok())
+        .collect()
+}
+
+#[derive(Debug, Deserialize)]
+struct IpLocation {
+    latitude: f64,
+    longitude: f64,
+}
+
+/// Approximate coordinates from the IP address, for the "sun" schedule
+pub async fn lookup_location(client: &reqwest::Client) -> Option<schedule::Location> {
+    let located: Result<IpLocation, reqwest::Error> = async {
+        client
+            .get("https://ipapi.co/json/")
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+    }
+    .await;
+
+    match located {
+        Ok(located) => {
+            let location = (located.latitude, located.longitude);
+            info!(
+                "Located at {:.2}, {:.2} for sunrise/sunset",
+                location.0, location.1
+            );
+            Some(location)
+        }
+        Err(e) => {
+            warn!("IP geolocation failed: {}", e);
+            None
+        }
+    }
+}
+
+/// Download and process the next wallpaper: the first queued photo, else one picked
+/// by the schedule and rotation order, else a random photo from Unsplash. Random
+/// photos near-identical to one shown recently are re-rolled.
+pub async fn prepare_wallpaper(
+    client: &reqwest::Client,
+    settings: &WallpaperSettings,
+    location: Option<schedule::Location>,
+    screen: Option<ScreenResolution>,
+    cancel: &CancellationToken,
+) -> Result<PreparedWallpaper, WallyError> {
+    if settings.api_key.is_empty() {
+        return Err(WallyError::ApiKeyMissing);
+    }
+    while let Some(image) = load_queue().into_iter().next() {
+        info!(target: "wally::daemon", "Next queued photo: {}", image.id);
+        let id = image.id.clone();
+        match prepare_photo(client, settings, image, screen, cancel).await {
+            // Dequeued once it's set, so a prefetch doesn't use up the entry
+            Ok(prepared) => {
+                return Ok(PreparedWallpaper {
+                    queued: true,
+                    ..prepared
+                })
+            }
+            Err(e) if give_up_on_queued(&id, &e) => {
+                warn!(target: "wally::daemon", "Dropped queued photo {}: {}", id, e);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    if settings.schedule_mode == "daily" {
+        let today = chrono::Local::now().date_naive();
+        let image = fetch_photo_of_the_day(client, settings, today).await?;
+        info!(target: "wally::daemon", "Photo of the day for {}: {}", today, image.id);
+        return prepare_photo(client, settings, image, screen, cancel).await;
+    }
+    weather::refresh(client, settings).await;
+    if settings.rotation_order != "random" {
+        let params = random_photo_params(settings, location);
+        if let Some((_, collections)) = params.iter().find(|(key, _)| *key == "collections") {
+            let image = fetch_ordered_photo(client, settings, collections).await?;
+            info!(target: "wally::daemon", "Next photo in order: {}", image.id);
+            return prepare_photo(client, settings, image, screen, cancel).await;
+        }
+    }
+
+    if let Some(screen) = screen.filter(|&screen| collage::wanted(settings, screen)) {
+        return prepare_collage(client, settings, location, screen, cancel).await;
+    }
+
+    let recent = if settings.skip_similar {
+        recent_phashes()
+    } else {
+        Vec::new()
+    };
+    let mut attempt = 0;
+    loop {
+        let prepared = prepare_random_wallpaper(client, settings, location, screen, cancel).await?;
+        let similar = prepared.phash.is_some_and(|hash| {
+            recent
+                .iter()
+                .any(|recent| similarity::is_similar(hash, *recent))
+        });
+        if !similar || attempt == MAX_SIMILAR_REROLLS {
+            return Ok(prepared);
+        }
+        attempt += 1;
+        info!(
+            target: "wally::daemon",
+            "{} looks like a recent wallpaper, fetching another",
+            prepared.image.id
+        );
+    }
+}
+
+/// Count a failed attempt at a queued photo and drop it from the queue when it can't
+/// succeed: Unsplash no longer has it, or it failed `MAX_QUEUED_ATTEMPTS` times.
+/// Network trouble and cancelled downloads don't count.
+fn give_up_on_queued(id: &str, error: &WallyError) -> bool {
+    if matches!(
+        error,
+        WallyError::Cancelled | WallyError::Http(_) | WallyError::RateLimited { .. }
+    ) {
+        return false;
+    }
+    let attempts = match QUEUED_FAILURES.lock() {
+        Ok(mut failures) => {
+            let attempts = failures.entry(id.to_string()).or_default();
+            *attempts += 1;
+            *attempts
+        }
+        Err(_) => MAX_QUEUED_ATTEMPTS,
+    };
+    let gone = matches!(
+        error,
+        WallyError::NotFound { .. } | WallyError::Forbidden(_)
+    );
+    if !gone && attempts < MAX_QUEUED_ATTEMPTS {
+        return false;
+    }
+    match dequeue(id) {
+        Ok(()) => true,
+        Err(e) => {
+            warn!("Failed to save the queue: {}", e);
+            false
+        }
+    }
+}
+
+/// Remove a photo from the queue
+fn dequeue(id: &str) -> Result<(), String> {
+    if let Ok(mut failures) = QUEUED_FAILURES.lock() {
+        failures.remove(id);
+    }
+    let mut queue = load_queue();
+    queue.retain(|queued| queued.id != id);
+    save_queue(&queue)
+}
+
+/// Why a photo's reported size or color doesn't suit, `None` when it does or the
+/// filters are off
+fn filter_mismatch(
+    settings: &WallpaperSettings,
+    image: &UnsplashImage,
+    screen: Option<ScreenResolution>,
+) -> Option<String> {
+    // Collections can't be filtered by color in the API, so check the average color
+    if let Some(color) = image.color.as_deref() {
+        if !settings.color_filter.is_empty()
+            && !palette::matches_color_filter(color, &settings.color_filter)
+        {
+            return Some(format!(
+                "is {}, not {}",
+                color,
+                settings.color_filter.replace('_', " ")
+            ));
+        }
+    }
+
+    let screen = screen.filter(|screen| screen.width > 0 && screen.height > 0)?;
+    if image.width == 0 || image.height == 0 {
+        return None;
+    }
+    if settings.min_resolution_filter
+        && (image.width < screen.width || image.height < screen.height)
+    {
+        return Some(format!(
+            "is {}x{}, smaller than the {}x{} screen",
+            image.width, image.height, screen.width, screen.height
+        ));
+    }
+
+    let max_deviation = f64::from(settings.max_aspect_deviation_percent) / 100.0;
+    let image_ratio = f64::from(image.width) / f64::from(image.height);
+    let screen_ratio = f64::from(screen.width) / f64::from(screen.height);
+    let deviation = (image_ratio / screen_ratio - 1.0).abs();
+    if max_deviation > 0.0 && deviation > max_deviation {
+        return Some(format!(
+            "has a {:.2}:1 aspect ratio, {:.0}% off the screen's",
+            image_ratio,
+            deviation * 100.0
+        ));
+    }
+    None
+}
+
+/// Search results a random pick is made from, deeper ones are rarely relevant
+const MAX_SEARCH_RESULTS: u64 = 300;
+
+/// One search result in the given color, by its index
+async fn search_photo_page(
+    client: &reqwest::Client,
+    settings: &WallpaperSettings,
+    query: &str,
+    index: u64,
+) -> Result<(Option<UnsplashImage>, u64), WallyError> {
+    let request = client
+        .get("https://api.unsplash.com/search/photos")
+        .query(&[
+            ("query", query.to_string()),
+            ("color", settings.color_filter.clone()),
+            ("orientation", "landscape".to_string()),
+            ("per_page", "1".to_string()),
+            ("page", (index + 1).to_string()),
+        ])
+        .header("Authorization", format!("Client-ID {}", settings.api_key));
+    let response = send_with_retry(request, settings).await?;
+    if !response.status().is_success() {
+        let resource = format!("Photos matching \"{}\"", query);
+        return Err(api_error(response, &resource).await);
+    }
+
+    let found: SearchPage<UnsplashImage> = response.json().await?;
+    Ok((found.results.into_iter().next(), found.total))
+}
+
+/// A random photo matching `query` in the configured color. The random endpoint
+/// can't filter by color, so this goes through search.
+async fn search_random_photo(
+    client: &reqwest::Client,
+    settings: &WallpaperSettings,
+    query: &str,
+) -> Result<UnsplashImage, WallyError> {
+    let (first, total) = search_photo_page(client, settings, query, 0).await?;
+    let index = rand::thread_rng().gen_range(0..total.clamp(1, MAX_SEARCH_RESULTS));
+    let photo = if index == 0 {
+        first
+    } else {
+        search_photo_page(client, settings, query, index).await?.0
+    };
+    photo.ok_or_else(|| {
+        format!(
+            "No {} photos matching \"{}\"",
+            settings.color_filter.replace('_', " "),
+            query
+        )
+        .into()
+    })
+}
+
+async fn fetch_random_photo(
+    client: &reqwest::Client,
+    settings: &WallpaperSettings,
+    location: Option<schedule::Location>,
+) -> Result<UnsplashImage, WallyError> {
+    let params = random_photo_params(settings, location);
+    if !settings.color_filter.is_empty() {
+        if let Some((_, query)) = params.iter().find(|(key, _)| *key == "query") {
+            return search_random_photo(client, settings, query).await;
+        }
+    }
+    let request = client
+        .get("https://api.unsplash.com/photos/random")
+        .query(&params)
+        .header("Authorization", format!("Client-ID {}", settings.api_key));
+    let response = send_with_retry(request, settings).await?;
+
+    if !response.status().is_success() {
+        return Err(api_error(response, &random_photo_resource(&params)).await);
+    }
+
+    Ok(response.json().await?)
+}
+
+async fn prepare_random_wallpaper(
+    client: &reqwest::Client,
+    settings: &WallpaperSettings,
+    location: Option<schedule::Location>,
+    screen: Option<ScreenResolution>,
+    cancel: &CancellationToken,
+) -> Result<PreparedWallpaper, WallyError> {
+    info!(target: "wally::daemon", "Fetching new wallpaper...");
+
+    // Fetch random images from Unsplash until one fits the screen and color filter,
+    // before downloading it
+    let mut attempt = 0;
+    let image = loop {
+        let image = fetch_random_photo(client, settings, location).await?;
+        match filter_mismatch(settings, &image, screen) {
+            Some(reason) if attempt < MAX_FILTER_REROLLS => {
+                attempt += 1;
+                info!(target: "wally::daemon", "{} {}, fetching another", image.id, reason);
+            }
+            Some(reason) => {
+                warn!("{} {}, using it anyway", image.id, reason);
+                break image;
+            }
+            None => break image,
+        }
+    };
+
+    info!(target: "wally::daemon", "Got image: {}", image.id);
+    prepare_photo(client, settings, image, screen, cancel).await
+}
+
+/// Download random portrait photos and compose them into one wallpaper the size of the
+/// screen. The first photo stands for the collage in the history.
+async fn prepare_collage(
+    client: &reqwest::Client,
+    settings: &WallpaperSettings,
+    location: Option<schedule::Location>,
+    screen: ScreenResolution,
+    cancel: &CancellationToken,
+) -> Result<PreparedWallpaper, WallyError> {
+    let count = collage::photo_count(settings);
+    info!(target: "wally::daemon", "Fetching {} photos for a collage...", count);
+
+    let mut params = random_photo_params(settings, location);
+    for (key, value) in params.iter_mut() {
+        if *key == "orientation" {
+            *value = "portrait".to_string();
+        }
+    }
+    params.push(("count", count.to_string()));
+    let request = client
+        .get("https://api.unsplash.com/photos/random")
+        .query(&params)
+        .header("Authorization", format!("Client-ID {}", settings.api_key));
+    let response = send_with_retry(request, settings).await?;
+    if !response.status().is_success() {
+        return Err(api_error(response, &random_photo_resource(&params)).await);
+    }
+    let images: Vec<UnsplashImage> = response.json().await?;
+    if images.len() < 2 {
+        return Err("Not enough photos for a collage".into());
+    }
+
+    // Each photo only needs to fill its share of the screen
+    let slot = ScreenResolution {
+        width: screen.width / images.len() as u32,
+        ..screen
+    };
+    let dir = get_wallpaper_dir();
+    let mut parts = Vec::new();
+    for image in &images {
+        let image_url = sized_image_url(
+            image.urls.for_quality(&settings.quality),
+            Some(slot),
+            settings.smart_crop,
+        );
+        let stem = format!("{}_part", filename_stem(&settings.filename_template, image));
+        parts.push(fetch_wallpaper_file(client, settings, &image_url, &dir, &stem, cancel).await?);
+    }
+
+    let ids: Vec<&str> = images.iter().map(|image| image.id.as_str()).collect();
+    let out = dir.join(format!("collage_{}.jpg", ids.join("_")));
+    let compose_settings = settings.clone();
+    let compose_parts = parts.clone();
+    let compose_out = out.clone();
+    let composed = tokio::task::spawn_blocking(move || {
+        collage::compose(&compose_parts, &compose_out, screen, &compose_settings)
+    })
+    .await
+    .map_err(|e| e.to_string())
+    .and_then(|result| result);
+    // The parts are only ever used for this collage
+    for part in &parts {
+        let _ = fs::remove_file(part);
+    }
+    composed?;
+
+    let caption = collage::attribution(&images);
+    let local_path = process_downloaded_wallpaper(out, settings, Some(screen), Some(caption)).await;
+    info!(target: "wally::daemon", "Composed collage: {}", local_path.display());
+    let phash = wallpaper_phash(local_path.clone()).await;
+    let image = images
+        .into_iter()
+        .next()
+        .ok_or("Not enough photos for a collage")?;
+
+    Ok(PreparedWallpaper {
+        image,
+        local_path,
+        phash,
+        queued: false,
+    })
+}
+
+/// Where the photo of the day comes from when no collection is configured
+const DAILY_TOPIC: &str = "wallpapers";
+
+/// FNV-1a, which unlike `DefaultHasher` gives the same value on every build and machine
+pub(crate) fn stable_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Fetch one page of a paginated photo list, one photo per page, and the number
+/// of photos in the list
+async fn list_photo_page(
+    client: &reqwest::Client,
+    settings: &WallpaperSettings,
+    url: &str,
+    resource: &str,
+    index: u64,
+) -> Result<(Option<UnsplashImage>, u64), WallyError> {
+    let request = client
+        .get(url)
+        .query(&[
+            ("per_page", "1".to_string()),
+            ("page", (index + 1).to_string()),
+        ])
+        .header("Authorization", format!("Client-ID {}", settings.api_key));
+    let response = send_with_retry(request, settings).await?;
+    if !response.status().is_success() {
+        return Err(api_error(response, resource).await);
+    }
+
+    let total = response
+        .headers()
+        .get("x-total")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(0);
+    let photos: Vec<UnsplashImage> = response.json().await?;
+    Ok((photos.into_iter().next(), total))
+}
+
+/// The photo at the index `pick` chooses from the number of photos in a list
+async fn pick_listed_photo(
+    client: &reqwest::Client,
+    settings: &WallpaperSettings,
+    url: &str,
+    resource: &str,
+    pick: impl FnOnce(u64) -> u64,
+) -> Result<UnsplashImage, WallyError> {
+    // The first page tells how many photos there are to pick from
+    let (first, total) = list_photo_page(client, settings, url, resource, 0).await?;
+    let index = pick(total.max(1));
+    let photo = if index == 0 {
+        first
+    } else {
+        list_photo_page(client, settings, url, resource, index)
+            .await?
+            .0
+    };
+    photo.ok_or_else(|| format!("{} has no photos", resource).into())
+}
+
+/// Index `cursor` of the configured rotation order over `total` items
+fn ordered_index(settings: &WallpaperSettings, cursor: u64, total: u64) -> u64 {
+    let total = total.max(1);
+    let position = cursor % total;
+    if settings.rotation_order != "shuffle" {
+        return position;
+    }
+    let mut order: Vec<u64> = (0..total).collect();
+    order.shuffle(&mut StdRng::seed_from_u64(settings.rotation_seed));
+    order[position as usize]
+}
+
+/// The next photo of an ordered rotation through a comma-separated list of
+/// collections, which take turns
+async fn fetch_ordered_photo(
+    client: &reqwest::Client,
+    settings: &WallpaperSettings,
+    collections: &str,
+) -> Result<UnsplashImage, WallyError> {
+    let ids: Vec<&str> = collections
+        .split(',')
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .collect();
+    if ids.is_empty() {
+        return Err("No collection to rotate through".into());
+    }
+    let cursor = advance_rotation_cursor(&format!("collections:{}", ids.join(",")));
+    let id = ids[(cursor % ids.len() as u64) as usize];
+    let position = cursor / ids.len() as u64;
+
+    let url = format!("https://api.unsplash.com/collections/{}/photos", id);
+    let resource = format!("Collection {}", id);
+    pick_listed_photo(client, settings, &url, &resource, |total| {
+        ordered_index(settings, position, total)
+    })
+    .await
+}
+
+/// The photo for `date`, picked from the configured collections or the wallpapers
+/// topic. The same date and collections always give the same photo while the
+/// collection is unchanged.
+pub async fn fetch_photo_of_the_day(
+    client: &reqwest::Client,
+    settings: &WallpaperSettings,
+    date: chrono::NaiveDate,
+) -> Result<UnsplashImage, WallyError> {
+    let collections: Vec<&str> = settings
+        .collection_id
+        .split(',')
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .collect();
+    let seed = stable_hash(format!("{}|{}", date, collections.join(",")).as_bytes());
+    let (url, resource) = if collections.is_empty() {
+        (
+            format!("https://api.unsplash.com/topics/{}/photos", DAILY_TOPIC),
+            format!("Topic {}", DAILY_TOPIC),
+        )
+    } else {
+        let id = collections[(seed % collections.len() as u64) as usize];
+        (
+            format!("https://api.unsplash.com/collections/{}/photos", id),
+            format!("Collection {}", id),
+        )
+    };
+
+    pick_listed_photo(client, settings, &url, &resource, |total| {
+        seed.rotate_left(32) % total
+    })
+    .await
+}
+
+/// Download and process a specific photo at the configured quality
+pub async fn prepare_photo(
+    client: &reqwest::Client,
+    settings: &WallpaperSettings,
+    image: UnsplashImage,
+    screen: Option<ScreenResolution>,
+    cancel: &CancellationToken,
+) -> Result<PreparedWallpaper, WallyError> {
+    let image_url = sized_image_url(
+        image.urls.for_quality(&settings.quality),
+        screen,
+        settings.smart_crop,
+    );
+    let file_path = fetch_wallpaper_file(
+        client,
+        settings,
+        &image_url,
+        &get_wallpaper_dir(),
+        &filename_stem(&settings.filename_template, &image),
+        cancel,
+    )
+    .await?;
+    let local_path =
+        process_downloaded_wallpaper(file_path, settings, screen, Some(image.attribution())).await;
+    info!(target: "wally::daemon", "Downloaded to: {}", local_path.display());
+    let phash = wallpaper_phash(local_path.clone()).await;
+
+    Ok(PreparedWallpaper {
+        image,
+        local_path,
+        phash,
+        queued: false,
+    })
+}
+
+/// Set a prepared wallpaper and record it as the current one
+pub async fn apply_prepared_wallpaper(
+    client: &reqwest::Client,
+    settings: &WallpaperSettings,
+    prepared: PreparedWallpaper,
+) -> Result<CurrentWallpaper, WallyError> {
+    let PreparedWallpaper {
+        image,
+        local_path,
+        phash,
+        queued,
+    } = prepared;
+    let file_path_str = local_path.to_string_lossy().to_string();
+
+    // Set the wallpaper
+    set_wallpaper_file(file_path_str.clone(), settings.apply_to_all_spaces).await?;
+    info!(target: "wally::daemon", "Wallpaper set successfully");
+    if queued {
+        if let Err(e) = dequeue(&image.id) {
+            warn!("Failed to save the queue: {}", e);
+        }
+    }
+
+    // Trigger download tracking (per Unsplash guidelines)
+    let _ = client
+        .get(&image.links.download_location)
+        .header("Authorization", format!("Client-ID {}", settings.api_key))
+        .send()
+        .await;
+
+    // Save current wallpaper info
+    let palette = extract_wallpaper_palette(&file_path_str).await;
+    let current = CurrentWallpaper {
+        image: Some(image),
+        local_path: Some(file_path_str.clone()),
+        set_at: Some(chrono::Utc::now().to_rfc3339()),
+        palette,
+        phash: phash.map(|hash| format!("{:016x}", hash)),
+    };
+    let _ = write_current_wallpaper(&current);
+
+    // Clean up old wallpapers
+    let _ = cleanup_old_wallpapers(&get_wallpaper_dir(), settings, &file_path_str);
+
+    Ok(current)
+}
+
+/// Set the wallpaper, retrying once if the backend hung. A backend that timed out
+/// is tried last, so the retry goes to a different one where the desktop has several.
+fn set_wallpaper_retrying_timeout(file_path: &str, all_spaces: bool) -> Result<(), WallyError> {
+    match set_wallpaper_platform(file_path, all_spaces) {
+        Err(e @ WallyError::BackendTimeout { .. }) => {
+            warn!(target: "wally::daemon", "{}, retrying", e);
+            set_wallpaper_platform(file_path, all_spaces)
+        }
+        result => result,
+    }
+}
+
+/// Set the wallpaper from async code. Backends run external tools and may wait on
+/// them until they time out, so this happens on a blocking thread.
+pub async fn set_wallpaper_file(file_path: String, all_spaces: bool) -> Result<(), WallyError> {
+    tokio::task::spawn_blocking(move || set_wallpaper_retrying_timeout(&file_path, all_spaces))
+        .await
+        .map_err(|e| WallyError::Other(e.to_string()))?
+}
+
+/// Set a wallpaper that is already on disk and record it as the current one. Blocking.
+pub fn set_cached_wallpaper(
+    entry: CurrentWallpaper,
+    all_spaces: bool,
+) -> Result<CurrentWallpaper, WallyError> {
+    let local_path = entry
+        .local_path
+        .clone()
+        .filter(|path| Path::new(path).exists())
+        .ok_or("Wallpaper is no longer cached on disk")?;
+    info!("Restoring cached wallpaper: {}", local_path);
+
+    set_wallpaper_retrying_timeout(&local_path, all_spaces)?;
+
+    let current = CurrentWallpaper {
+        set_at: Some(chrono::Utc::now().to_rfc3339()),
+        ..entry
+    };
+    write_current_wallpaper(&current)?;
+    Ok(current)
+}
+
+/// Pick a previously downloaded wallpaper other than the current one, in the
+/// configured rotation order, for when Unsplash is unreachable
+pub fn next_cached_wallpaper(settings: &WallpaperSettings) -> Result<CurrentWallpaper, WallyError> {
+    let current_path = load_current_wallpaper().local_path;
+    let mut candidates: Vec<CurrentWallpaper> = load_history()
+        .into_iter()
+        .filter(|entry| {
+            entry
+                .local_path
+                .as_deref()
+                .is_some_and(|path| Path::new(path).exists())
+        })
+        .collect();
+
+    // Files downloaded before the history was kept have no photo details. Downloads
+    // are named from a user template, so every finished image in the folder counts.
+    if let Ok(entries) = fs::read_dir(get_wallpaper_dir()) {
+        for entry in entries.flatten() {
+            let path = entry.path().to_string_lossy().to_string();
+            let name = entry.file_name().to_string_lossy().to_string();
+            let file = Path::new(&name);
+            // In-progress downloads end in `.part`, collage parts in `_part`
+            let is_wallpaper = !name.starts_with('.')
+                && file
+                    .extension()
+                    .is_some_and(|ext| is_image_extension(&ext.to_string_lossy()))
+                && !file
+                    .file_stem()
+                    .is_some_and(|stem| stem.to_string_lossy().ends_with("_part"));
+            let known = candidates
+                .iter()
+                .any(|candidate| candidate.local_path.as_deref() == Some(path.as_str()));
+            if is_wallpaper && !known {
+                candidates.push(CurrentWallpaper {
+                    local_path: Some(path),
+                    ..Default::default()
+                });
+            }
+        }
+    }
+
+    // Ordered rotations index the whole folder, stepping past the wallpaper on screen
+    if settings.rotation_order != "random" {
+        candidates.sort_by(|a, b| a.local_path.cmp(&b.local_path));
+        let total = candidates.len() as u64;
+        for _ in 0..total.min(2) {
+            let index = ordered_index(settings, advance_rotation_cursor("cache"), total) as usize;
+            if candidates[index].local_path != current_path {
+                return Ok(candidates.swap_remove(index));
+            }
+        }
+        return Err("No cached wallpapers to rotate through".into());
+    }
+
+    candidates.retain(|entry| entry.local_path != current_path);
+    if candidates.is_empty() {
+        return Err("No cached wallpapers to rotate through".into());
+    }
+    let index = rand::thread_rng().gen_range(0..candidates.len());
+    Ok(candidates.swap_remove(index))
+}
+
+/// Whether Unsplash can be reached at all, as opposed to returning an error
+pub async fn is_online(client: &reqwest::Client) -> bool {
+    match client
+        .head("https://api.unsplash.com")
+        .timeout(Duration::from_secs(5))
+        .send()
+        .await
+    {
+        Ok(_) => true,
+        Err(e) => !(e.is_connect() || e.is_timeout()),
+    }
+}
+
+/// How a due change should save battery or mobile data
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangePolicy {
+    Normal,
+    /// Download a smaller image
+    SmallImage,
+    /// Skip this change, for the given reason ("battery" or "metered")
+    Skip(&'static str),
+}
+
+/// Check the battery and network against the power-saving settings. Blocking.
+pub fn change_policy(settings: &WallpaperSettings) -> ChangePolicy {
+    let mut policy = ChangePolicy::Normal;
+
+    // Save power on a draining battery by skipping the change or downloading less
+    if settings.battery_policy != "ignore" && system::is_low_battery(settings.battery_threshold) {
+        if settings.battery_policy == "skip" {
+            info!(target: "wally::daemon", "Battery low, skipping this change");
+            return ChangePolicy::Skip("battery");
+        }
+        info!(target: "wally::daemon", "Battery low, downloading a smaller image");
+        policy = ChangePolicy::SmallImage;
+    }
+
+    // Avoid large downloads on metered connections unless the user opted out
+    if !settings.ignore_metered && system::is_metered_connection() {
+        if settings.metered_policy == "pause" {
+            info!(target: "wally::daemon", "Metered connection, skipping this change");
+            return ChangePolicy::Skip("metered");
+        }
+        info!(target: "wally::daemon", "Metered connection, downloading a smaller image");
+        policy = ChangePolicy::SmallImage;
+    }
+
+    policy
+}
+
+/// Holds a due change until the user has been idle for `idle_minutes`, or in the
+/// "return" mode until they come back after being away that long
+#[derive(Debug, Default)]
+pub struct IdleGate {
+    was_idle: bool,
+}
+
+impl IdleGate {
+    /// Whether the change should keep waiting, given the current `idle` time.
+    /// Changes are never held when the idle time can't be read.
+    pub fn holds(&mut self, settings: &WallpaperSettings, idle: Option<Duration>) -> bool {
+        let Some(idle) = idle else {
+            return false;
+        };
+        let threshold = Duration::from_secs(u64::from(settings.idle_minutes) * 60);
+        match settings.idle_change_mode.as_str() {
+            "idle" => idle < threshold,
+            "return" => {
+                if idle >= threshold {
+                    self.was_idle = true;
+                    return true;
+                }
+                !self.was_idle
+            }
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,829 @@
+//! Fixed-time, cron and sunrise/sunset schedules and quiet hours, evaluated in the
+//! local timezone
+
+use crate::settings::{HolidayTheme, KeywordPeriod, Playlist};
+use crate::WallpaperSettings;
+use chrono::{DateTime, Datelike, Days, Local, NaiveDate, NaiveTime, TimeZone, Utc, Weekday};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+use tracing::warn;
+
+/// Latitude and longitude in degrees
+pub type Location = (f64, f64);
+
+/// Parse a "HH:MM" time of day
+fn parse_time_of_day(time: &str) -> Option<NaiveTime> {
+    NaiveTime::parse_from_str(time.trim(), "%H:%M").ok()
+}
+
+/// Next occurrence of any of the given times of day strictly after `after`.
+/// Times skipped by a DST jump are dropped for that day, and repeated times use
+/// their first occurrence.
+fn next_fixed_time(times: &[String], after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let times: Vec<NaiveTime> = times
+        .iter()
+        .filter_map(|time| parse_time_of_day(time))
+        .collect();
+    if times.is_empty() {
+        warn!("No valid times in schedule, expected HH:MM");
+        return None;
+    }
+
+    let start = after.with_timezone(&Local).date_naive();
+    start
+        .iter_days()
+        .take(3)
+        .flat_map(|day| times.iter().map(move |time| day.and_time(*time)))
+        .filter_map(|naive| Local.from_local_datetime(&naive).earliest())
+        .map(|local| local.with_timezone(&Utc))
+        .filter(|time| *time > after)
+        .min()
+}
+
+/// The first moment of the next local day after `after`, for the "daily" schedule.
+/// Days starting with a DST jump begin at the first time that exists.
+fn next_local_midnight(after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let tomorrow = after.with_timezone(&Local).date_naive().succ_opt()?;
+    (0..3)
+        .filter_map(|hour| tomorrow.and_hms_opt(hour, 0, 0))
+        .find_map(|naive| Local.from_local_datetime(&naive).earliest())
+        .map(|local| local.with_timezone(&Utc))
+}
+
+/// Parse a cron expression. Standard five-field expressions are accepted as well
+/// as the six and seven field forms with seconds and years.
+fn parse_cron(expression: &str) -> Result<cron::Schedule, cron::error::Error> {
+    let expression = expression.trim();
+    let expression = if expression.split_whitespace().count() == 5 {
+        format!("0 {}", expression)
+    } else {
+        expression.to_string()
+    };
+    cron::Schedule::from_str(&expression)
+}
+
+/// Next occurrence of a cron expression strictly after `after`
+fn next_cron_time(expression: &str, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let schedule = match parse_cron(expression) {
+        Ok(schedule) => schedule,
+        Err(e) => {
+            warn!("Invalid cron expression: {}", e);
+            return None;
+        }
+    };
+
+    schedule
+        .after(&after.with_timezone(&Local))
+        .next()
+        .map(|time| time.with_timezone(&Utc))
+}
+
+/// Sunrise and sunset on a given day
+enum SolarDay {
+    Normal {
+        sunrise: DateTime<Utc>,
+        sunset: DateTime<Utc>,
+    },
+    PolarDay,
+    PolarNight,
+}
+
+fn julian_to_utc(julian: f64) -> Option<DateTime<Utc>> {
+    let millis = ((julian - 2440587.5) * 86_400_000.0).round() as i64;
+    DateTime::from_timestamp_millis(millis)
+}
+
+/// Sunrise and sunset for a UTC calendar day using the NOAA sunrise equation
+fn solar_day(date: NaiveDate, (latitude, longitude): Location) -> Option<SolarDay> {
+    let midnight = date.and_hms_opt(0, 0, 0)?.and_utc().timestamp() as f64;
+    let julian_date = midnight / 86_400.0 + 2440587.5;
+    let day_number = (julian_date - 2451545.0 + 0.0008).ceil();
+
+    let mean_solar_noon = day_number - longitude / 360.0;
+    let anomaly = (357.5291 + 0.98560028 * mean_solar_noon).rem_euclid(360.0);
+    let m = anomaly.to_radians();
+    let center = 1.9148 * m.sin() + 0.02 * (2.0 * m).sin() + 0.0003 * (3.0 * m).sin();
+    let ecliptic_longitude = (anomaly + center + 180.0 + 102.9372).rem_euclid(360.0);
+    let lambda = ecliptic_longitude.to_radians();
+    let transit = 2451545.0 + mean_solar_noon + 0.0053 * m.sin() - 0.0069 * (2.0 * lambda).sin();
+
+    let declination = (lambda.sin() * 23.4397_f64.to_radians().sin()).asin();
+    let phi = latitude.to_radians();
+    let cos_hour_angle = ((-0.833_f64).to_radians().sin() - phi.sin() * declination.sin())
+        / (phi.cos() * declination.cos());
+
+    if cos_hour_angle < -1.0 {
+        return Some(SolarDay::PolarDay);
+    }
+    if cos_hour_angle > 1.0 {
+        return Some(SolarDay::PolarNight);
+    }
+
+    let hour_angle = cos_hour_angle.acos().to_degrees();
+    Some(SolarDay::Normal {
+        sunrise: julian_to_utc(transit - hour_angle / 360.0)?,
+        sunset: julian_to_utc(transit + hour_angle / 360.0)?,
+    })
+}
+
+/// Solar days around `time`, wide enough to cover any timezone offset
+fn solar_days_around(time: DateTime<Utc>, location: Location) -> Vec<SolarDay> {
+    let today = time.date_naive();
+    [
+        today.checked_sub_days(Days::new(1)),
+        Some(today),
+        today.checked_add_days(Days::new(1)),
+        today.checked_add_days(Days::new(2)),
+    ]
+    .into_iter()
+    .flatten()
+    .filter_map(|date| solar_day(date, location))
+    .collect()
+}
+
+/// Whether the sun is up at `time`, going by the solar day it falls in: the one
+/// running from local solar midnight to the next at the location's longitude
+pub fn is_daytime(time: DateTime<Utc>, location: Location) -> bool {
+    let solar_offset = chrono::Duration::seconds((location.1 * 240.0).round() as i64);
+    let date = (time + solar_offset).date_naive();
+    match solar_day(date, location) {
+        Some(SolarDay::Normal { sunrise, sunset }) => sunrise <= time && time < sunset,
+        Some(SolarDay::PolarDay) => true,
+        Some(SolarDay::PolarNight) | None => false,
+    }
+}
+
+/// Next sunrise or sunset strictly after `after`
+fn next_sun_event(after: DateTime<Utc>, location: Location) -> Option<DateTime<Utc>> {
+    solar_days_around(after, location)
+        .into_iter()
+        .filter_map(|day| match day {
+            SolarDay::Normal { sunrise, sunset } => Some([sunrise, sunset]),
+            _ => None,
+        })
+        .flatten()
+        .filter(|time| *time > after)
+        .min()
+}
+
+/// When the schedule next fires after `after`, or `None` when the plain interval applies
+pub fn next_scheduled_change(
+    settings: &WallpaperSettings,
+    after: DateTime<Utc>,
+    location: Option<Location>,
+) -> Option<DateTime<Utc>> {
+    match settings.schedule_mode.as_str() {
+        "times" => next_fixed_time(&settings.schedule_times, after),
+        "cron" => next_cron_time(&settings.schedule_cron, after),
+        "sun" => next_sun_event(after, location?),
+        "daily" => next_local_midnight(after),
+        _ => None,
+    }
+}
+
+/// A recurring window during which the daemon does not change wallpaper
+struct QuietWindow {
+    days: Vec<Weekday>,
+    start: NaiveTime,
+    end: NaiveTime,
+}
+
+fn parse_weekday(day: &str) -> Option<Weekday> {
+    day.trim().parse().ok()
+}
+
+/// Parse "Mon-Fri", "Sat,Sun", "weekdays" or "weekends" into a list of days
+fn parse_days(days: &str) -> Option<Vec<Weekday>> {
+    match days.trim().to_ascii_lowercase().as_str() {
+        "daily" | "everyday" => return Some(Vec::new()),
+        "weekdays" => return parse_days("Mon-Fri"),
+        "weekends" => return parse_days("Sat,Sun"),
+        _ => {}
+    }
+
+    let mut result = Vec::new();
+    for part in days.split(',') {
+        match part.split_once('-') {
+            Some((from, to)) => {
+                let (mut day, to) = (parse_weekday(from)?, parse_weekday(to)?);
+                result.push(day);
+                while day != to {
+                    day = day.succ();
+                    result.push(day);
+                }
+            }
+            None => result.push(parse_weekday(part)?),
+        }
+    }
+    Some(result)
+}
+
+/// Parse "22:00-07:00" or "Mon-Fri 09:00-17:00". Days apply to the day a window starts.
+fn parse_quiet_window(window: &str) -> Option<QuietWindow> {
+    let window = window.trim();
+    let (days, times) = match window.rsplit_once(char::is_whitespace) {
+        Some((days, times)) => (parse_days(days)?, times),
+        None => (Vec::new(), window),
+    };
+    let (start, end) = times.split_once('-')?;
+    Some(QuietWindow {
+        days,
+        start: parse_time_of_day(start)?,
+        end: parse_time_of_day(end)?,
+    })
+}
+
+/// When `window` ends if it contains `now`
+fn window_end(window: &QuietWindow, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let today = now.with_timezone(&Local).date_naive();
+    let local = |date: NaiveDate, time: NaiveTime| {
+        Local
+            .from_local_datetime(&date.and_time(time))
+            .earliest()
+            .map(|time| time.with_timezone(&Utc))
+    };
+
+    [today.checked_sub_days(Days::new(1)), Some(today)]
+        .into_iter()
+        .flatten()
+        .filter_map(|day| {
+            if !window.days.is_empty() && !window.days.contains(&day.weekday()) {
+                return None;
+            }
+            let end_day = if window.end <= window.start {
+                day.checked_add_days(Days::new(1))?
+            } else {
+                day
+            };
+            let start = local(day, window.start)?;
+            let end = local(end_day, window.end)?;
+            (start <= now && now < end).then_some(end)
+        })
+        .max()
+}
+
+/// When the quiet window containing `now` ends, or `None` outside quiet hours
+pub fn quiet_hours_end(windows: &[String], now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    windows
+        .iter()
+        .filter_map(|window| {
+            let parsed = parse_quiet_window(window);
+            if parsed.is_none() {
+                warn!("Ignoring invalid quiet hours `{}`", window);
+            }
+            parsed
+        })
+        .filter_map(|window| window_end(&window, now))
+        .max()
+}
+
+/// Parse a playlist schedule: days such as "Mon-Fri", a time range, or both as in
+/// "Sat,Sun 08:00-20:00". Days alone cover the whole day.
+fn parse_playlist_schedule(schedule: &str) -> Option<QuietWindow> {
+    if schedule.contains(':') {
+        return parse_quiet_window(schedule);
+    }
+    let midnight = NaiveTime::MIN;
+    Some(QuietWindow {
+        days: parse_days(schedule)?,
+        start: midnight,
+        end: midnight,
+    })
+}
+
+/// The first enabled playlist whose schedule contains `now`. Playlists without a
+/// schedule are always active.
+pub fn active_playlist(playlists: &[Playlist], now: DateTime<Utc>) -> Option<&Playlist> {
+    playlists
+        .iter()
+        .filter(|playlist| playlist.enabled)
+        .find(|playlist| {
+            if playlist.schedule.trim().is_empty() {
+                return true;
+            }
+            match parse_playlist_schedule(&playlist.schedule) {
+                Some(window) => window_end(&window, now).is_some(),
+                None => {
+                    warn!(
+                        "Ignoring playlist `{}` with an invalid schedule",
+                        playlist.name
+                    );
+                    false
+                }
+            }
+        })
+}
+
+/// Keywords of the period `now` falls in. Each period lasts until the next one
+/// starts, and the last period of the day continues past midnight.
+pub fn period_keywords(periods: &[KeywordPeriod], now: DateTime<Utc>) -> Option<&str> {
+    let time = now.with_timezone(&Local).time();
+    let mut starts: Vec<(NaiveTime, &str)> = periods
+        .iter()
+        .filter_map(|period| Some((parse_time_of_day(&period.start)?, period.keywords.as_str())))
+        .collect();
+    starts.sort_by_key(|(start, _)| *start);
+    starts
+        .iter()
+        .rev()
+        .find(|(start, _)| *start <= time)
+        .or(starts.last())
+        .map(|(_, keywords)| *keywords)
+}
+
+/// Parse a "MM-DD" day of the year as (month, day)
+fn parse_month_day(day: &str) -> Option<(u32, u32)> {
+    // A leap year, so "02-29" is accepted
+    let date = NaiveDate::parse_from_str(&format!("2000-{}", day.trim()), "%Y-%m-%d").ok()?;
+    Some((date.month(), date.day()))
+}
+
+/// The first enabled holiday theme whose date range contains the local date of `now`.
+/// Ranges include both ends and wrap around New Year when `end` comes before `start`.
+pub fn active_holiday(themes: &[HolidayTheme], now: DateTime<Utc>) -> Option<&HolidayTheme> {
+    let date = now.with_timezone(&Local).date_naive();
+    let today = (date.month(), date.day());
+    themes.iter().filter(|theme| theme.enabled).find(|theme| {
+        let (Some(start), Some(end)) = (parse_month_day(&theme.start), parse_month_day(&theme.end))
+        else {
+            warn!(
+                "Ignoring holiday theme `{}` with an invalid date",
+                theme.name
+            );
+            return false;
+        };
+        if start <= end {
+            start <= today && today <= end
+        } else {
+            today >= start || today <= end
+        }
+    })
+}
+
+/// Problems with the schedule and quiet hours settings, for diagnostics
+pub fn validate(settings: &WallpaperSettings) -> Vec<String> {
+    let mut problems = Vec::new();
+    match settings.schedule_mode.as_str() {
+        "interval" | "daily" => {}
+        "times" => {
+            for time in &settings.schedule_times {
+                if parse_time_of_day(time).is_none() {
+                    problems.push(format!("Schedule time `{}` is not HH:MM", time));
+                }
+            }
+            if settings.schedule_times.is_empty() {
+                problems.push("Fixed-time schedule has no times".to_string());
+            }
+        }
+        "cron" => {
+            if let Err(e) = parse_cron(&settings.schedule_cron) {
+                problems.push(format!("Invalid cron expression: {}", e));
+            }
+        }
+        "sun" => {
+            if settings.sun_latitude.is_some() != settings.sun_longitude.is_some() {
+                problems.push("Only one of latitude and longitude is set".to_string());
+            }
+        }
+        mode => problems.push(format!("Unknown schedule mode `{}`", mode)),
+    }
+    if settings.slideshow_enabled {
+        if !Path::new(&settings.slideshow_folder).is_dir() {
+            problems.push(format!(
+                "Slideshow folder `{}` does not exist",
+                settings.slideshow_folder
+            ));
+        }
+        if !matches!(
+            settings.slideshow_order.as_str(),
+            "alphabetical" | "shuffle" | "date"
+        ) {
+            problems.push(format!(
+                "Unknown slideshow order `{}`",
+                settings.slideshow_order
+            ));
+        }
+    }
+    if !matches!(settings.update_channel.as_str(), "stable" | "beta") {
+        problems.push(format!(
+            "Unknown update channel `{}`",
+            settings.update_channel
+        ));
+    }
+    for window in &settings.quiet_hours {
+        if parse_quiet_window(window).is_none() {
+            problems.push(format!("Invalid quiet hours `{}`", window));
+        }
+    }
+    for playlist in &settings.playlists {
+        if !playlist.schedule.trim().is_empty()
+            && parse_playlist_schedule(&playlist.schedule).is_none()
+        {
+            problems.push(format!(
+                "Playlist `{}` has an invalid schedule `{}`",
+                playlist.name, playlist.schedule
+            ));
+        }
+    }
+    for theme in settings.holiday_themes.iter().filter(|theme| theme.enabled) {
+        if parse_month_day(&theme.start).is_none() || parse_month_day(&theme.end).is_none() {
+            problems.push(format!(
+                "Holiday theme `{}` dates are not MM-DD",
+                theme.name
+            ));
+        }
+    }
+    if settings.time_of_day_theming {
+        for period in &settings.keyword_periods {
+            if parse_time_of_day(&period.start).is_none() {
+                problems.push(format!(
+                    "Keyword period start `{}` is not HH:MM",
+                    period.start
+                ));
+            }
+        }
+    }
+    problems
+}
+
+/// Convert interval settings to Duration
+pub fn get_interval_duration(value: u32, unit: &str) -> Duration {
+    match unit {
+        "minutes" => Duration::from_secs(value as u64 * 60),
+        "hours" => Duration::from_secs(value as u64 * 3600),
+        "days" => Duration::from_secs(value as u64 * 86400),
+        "weeks" => Duration::from_secs(value as u64 * 604800),
+        _ => Duration::from_secs(3600), // Default to 1 hour
+    }
+}
+
+/// A random offset within ±`schedule_jitter_minutes`, in seconds. It is seeded by the
+/// last change so every check agrees on it until the next change, and differs between
+/// machines that share an API key.
+fn jitter_secs(settings: &WallpaperSettings, last_change: Option<DateTime<Utc>>) -> i64 {
+    let max = settings.schedule_jitter_minutes as i64 * 60;
+    match last_change {
+        Some(last) if max > 0 => {
+            StdRng::seed_from_u64(last.timestamp_micros() as u64).gen_range(-max..=max)
+        }
+        _ => 0,
+    }
+}
+
+/// The total wait before the next change and how much of it has already passed.
+/// Fixed-time and cron schedules fire at their next occurrence after the last change,
+/// so occurrences missed while the app was closed are caught up right away.
+pub fn schedule_wait(
+    settings: &WallpaperSettings,
+    last_change: Option<chrono::DateTime<chrono::Utc>>,
+    location: Option<Location>,
+) -> (Duration, Duration) {
+    let now = chrono::Utc::now();
+    let elapsed = last_change
+        .and_then(|last| (now - last).to_std().ok())
+        .unwrap_or(Duration::ZERO);
+    // The slideshow keeps its own steady pace, without the schedule or jitter
+    if settings.slideshow_enabled {
+        let minutes = settings.slideshow_interval_minutes.max(1) as u64;
+        return (Duration::from_secs(minutes * 60), elapsed);
+    }
+
+    let jitter = jitter_secs(settings, last_change);
+    if let Some(next) = next_scheduled_change(settings, last_change.unwrap_or(now), location) {
+        // Scheduled changes are only delayed, never moved before the time they belong to
+        let next = next + chrono::Duration::seconds(jitter.abs());
+        return (
+            (next - now).to_std().unwrap_or(Duration::ZERO),
+            Duration::ZERO,
+        );
+    }
+
+    let interval = get_interval_duration(settings.interval_value, &settings.interval_unit);
+    // At most half the interval either way, so changes can't bunch up
+    let offset = Duration::from_secs(jitter.unsigned_abs()).min(interval / 2);
+    let interval = if jitter < 0 {
+        interval - offset
+    } else {
+        interval + offset
+    };
+    (interval, elapsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(date: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(date)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    /// A local "YYYY-MM-DD HH:MM", away from DST changes
+    fn local(time: &str) -> DateTime<Utc> {
+        let naive = chrono::NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M").unwrap();
+        Local
+            .from_local_datetime(&naive)
+            .single()
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    const LONDON: Location = (51.5, -0.13);
+    const TROMSO: Location = (69.65, 18.96);
+
+    #[test]
+    fn sun_times_follow_the_location() {
+        assert!(is_daytime(utc("2024-06-21T12:00:00Z"), LONDON));
+        assert!(!is_daytime(utc("2024-06-21T00:00:00Z"), LONDON));
+
+        // Sunrise in London on the solstice is at about 03:43 UTC
+        let sunrise = next_sun_event(utc("2024-06-21T00:00:00Z"), LONDON).unwrap();
+        let expected = utc("2024-06-21T03:43:00Z");
+        assert!((sunrise - expected).num_minutes().abs() <= 5, "{}", sunrise);
+    }
+
+    #[test]
+    fn polar_days_have_no_sun_events() {
+        assert!(is_daytime(utc("2024-06-21T00:00:00Z"), TROMSO));
+        assert!(!is_daytime(utc("2024-12-21T12:00:00Z"), TROMSO));
+        assert_eq!(next_sun_event(utc("2024-06-21T00:00:00Z"), TROMSO), None);
+    }
+
+    #[test]
+    fn last_night_before_the_midnight_sun_is_night() {
+        let normal = |date: NaiveDate| match solar_day(date, TROMSO) {
+            Some(SolarDay::Normal { sunrise, sunset }) => Some((sunrise, sunset)),
+            _ => None,
+        };
+        // The last day with a sunset, followed by one without
+        let (last, (sunrise, _)) = NaiveDate::from_ymd_opt(2024, 5, 1)
+            .unwrap()
+            .iter_days()
+            .take(40)
+            .find_map(|date| {
+                let next = matches!(
+                    solar_day(date.succ_opt()?, TROMSO),
+                    Some(SolarDay::PolarDay)
+                );
+                next.then_some((date, normal(date)?))
+            })
+            .unwrap();
+        let (_, sunset) = normal(last.pred_opt().unwrap()).unwrap();
+
+        let night = sunset + (sunrise - sunset) / 2;
+        assert!(!is_daytime(night, TROMSO), "{}", night);
+    }
+
+    #[test]
+    fn daytime_near_the_date_line() {
+        let samoa = (-13.8, -171.8);
+        // Local solar noon is around 23:30 UTC, midnight around 11:30 UTC
+        assert!(is_daytime(utc("2024-03-20T23:30:00Z"), samoa));
+        assert!(!is_daytime(utc("2024-03-20T11:30:00Z"), samoa));
+    }
+
+    #[test]
+    fn fixed_times_pick_the_next_one() {
+        let times = vec!["20:00".to_string(), "08:00".to_string()];
+        let next = next_fixed_time(&times, local("2024-06-21 08:30"));
+        assert_eq!(next, Some(local("2024-06-21 20:00")));
+        let next = next_fixed_time(&times, local("2024-06-21 20:00"));
+        assert_eq!(next, Some(local("2024-06-22 08:00")));
+    }
+
+    #[test]
+    fn fixed_times_skip_invalid_entries() {
+        let times = vec!["8am".to_string(), " 09:15 ".to_string()];
+        let next = next_fixed_time(&times, local("2024-06-21 08:30"));
+        assert_eq!(next, Some(local("2024-06-21 09:15")));
+        assert_eq!(
+            next_fixed_time(&["8am".to_string()], local("2024-06-21 08:30")),
+            None
+        );
+    }
+
+    #[test]
+    fn five_field_cron_counts_minutes() {
+        assert!(parse_cron("0 9 * * *").is_ok());
+        assert!(parse_cron("0 0 9 * * * 2030").is_ok());
+        assert!(parse_cron("every morning").is_err());
+        // Every minute, not every second, whatever the local offset
+        let next = next_cron_time("* * * * *", utc("2024-06-21T10:30:20Z"));
+        assert_eq!(next, Some(utc("2024-06-21T10:31:00Z")));
+    }
+
+    #[test]
+    fn cron_runs_in_local_time() {
+        let next = next_cron_time("0 9 * * *", local("2024-06-21 09:00"));
+        assert_eq!(next, Some(local("2024-06-22 09:00")));
+    }
+
+    #[test]
+    fn default_settings_are_valid() {
+        assert!(validate(&WallpaperSettings::default()).is_empty());
+    }
+
+    #[test]
+    fn validate_reports_bad_schedules() {
+        let mut settings = WallpaperSettings {
+            schedule_mode: "times".to_string(),
+            schedule_times: vec!["08:00".to_string(), "25:00".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(validate(&settings), ["Schedule time `25:00` is not HH:MM"]);
+
+        settings.schedule_times.clear();
+        assert_eq!(validate(&settings), ["Fixed-time schedule has no times"]);
+
+        settings.schedule_mode = "cron".to_string();
+        settings.schedule_cron = "every morning".to_string();
+        assert_eq!(validate(&settings).len(), 1);
+
+        settings.schedule_mode = "sun".to_string();
+        settings.sun_latitude = Some(LONDON.0);
+        assert_eq!(
+            validate(&settings),
+            ["Only one of latitude and longitude is set"]
+        );
+
+        settings.schedule_mode = "hourly".to_string();
+        assert_eq!(validate(&settings), ["Unknown schedule mode `hourly`"]);
+    }
+
+    #[test]
+    fn validate_reports_bad_quiet_hours_and_channels() {
+        let settings = WallpaperSettings {
+            quiet_hours: vec!["22:00-07:00".to_string(), "Someday 22:00".to_string()],
+            update_channel: "nightly".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            validate(&settings),
+            [
+                "Unknown update channel `nightly`",
+                "Invalid quiet hours `Someday 22:00`"
+            ]
+        );
+    }
+
+    #[test]
+    fn quiet_window_formats() {
+        let window = parse_quiet_window("Mon-Fri 09:00-17:00").unwrap();
+        assert_eq!(window.days.len(), 5);
+        assert_eq!(
+            parse_days("weekends"),
+            Some(vec![Weekday::Sat, Weekday::Sun])
+        );
+        assert_eq!(
+            parse_days("Fri-Mon"),
+            Some(vec![Weekday::Fri, Weekday::Sat, Weekday::Sun, Weekday::Mon])
+        );
+        assert!(parse_quiet_window("22:00-07:00").unwrap().days.is_empty());
+        assert!(parse_quiet_window("22:00").is_none());
+        assert!(parse_quiet_window("Someday 22:00-07:00").is_none());
+    }
+
+    #[test]
+    fn quiet_hours_span_midnight() {
+        let windows = ["22:00-07:00".to_string()];
+        assert_eq!(
+            quiet_hours_end(&windows, local("2024-06-21 23:00")),
+            Some(local("2024-06-22 07:00"))
+        );
+        assert_eq!(
+            quiet_hours_end(&windows, local("2024-06-22 06:59")),
+            Some(local("2024-06-22 07:00"))
+        );
+        assert_eq!(quiet_hours_end(&windows, local("2024-06-22 07:00")), None);
+    }
+
+    #[test]
+    fn quiet_hours_days_apply_to_the_start() {
+        // 2024-06-21 is a Friday
+        let windows = ["Fri 22:00-07:00".to_string()];
+        assert!(quiet_hours_end(&windows, local("2024-06-22 01:00")).is_some());
+        assert!(quiet_hours_end(&windows, local("2024-06-22 23:00")).is_none());
+    }
+
+    #[test]
+    fn overlapping_quiet_hours_end_with_the_last() {
+        let windows = ["09:00-12:00".to_string(), "11:00-13:00".to_string()];
+        assert_eq!(
+            quiet_hours_end(&windows, local("2024-06-21 11:30")),
+            Some(local("2024-06-21 13:00"))
+        );
+    }
+
+    fn playlist(name: &str, enabled: bool, schedule: &str) -> Playlist {
+        Playlist {
+            name: name.to_string(),
+            enabled,
+            schedule: schedule.to_string(),
+            collection_id: String::new(),
+            query: name.to_string(),
+        }
+    }
+
+    #[test]
+    fn first_matching_playlist_is_active() {
+        // 2024-06-21 is a Friday
+        let playlists = [
+            playlist("off", false, ""),
+            playlist("weekend", true, "weekends"),
+            playlist("work", true, "Mon-Fri 09:00-17:00"),
+            playlist("always", true, ""),
+        ];
+        let active = |time| active_playlist(&playlists, local(time)).map(|p| p.name.as_str());
+        assert_eq!(active("2024-06-21 10:00"), Some("work"));
+        assert_eq!(active("2024-06-21 18:00"), Some("always"));
+        assert_eq!(active("2024-06-22 18:00"), Some("weekend"));
+    }
+
+    #[test]
+    fn playlists_with_invalid_schedules_are_skipped() {
+        let playlists = [playlist("broken", true, "Someday")];
+        assert!(active_playlist(&playlists, local("2024-06-21 10:00")).is_none());
+        assert!(parse_playlist_schedule("Sat,Sun 08:00-20:00").is_some());
+    }
+
+    #[test]
+    fn keyword_periods_last_until_the_next_one() {
+        let period = |start: &str, keywords: &str| KeywordPeriod {
+            start: start.to_string(),
+            keywords: keywords.to_string(),
+        };
+        let periods = [
+            period("18:00", "sunset"),
+            period("06:00", "sunrise"),
+            period("late", "ignored"),
+            period("21:00", "night"),
+        ];
+        let keywords = |time| period_keywords(&periods, local(time));
+        assert_eq!(keywords("2024-06-21 06:00"), Some("sunrise"));
+        assert_eq!(keywords("2024-06-21 19:30"), Some("sunset"));
+        // The last period carries on past midnight
+        assert_eq!(keywords("2024-06-21 02:00"), Some("night"));
+        assert_eq!(period_keywords(&[], local("2024-06-21 02:00")), None);
+    }
+
+    fn holiday(name: &str, enabled: bool, start: &str, end: &str) -> HolidayTheme {
+        HolidayTheme {
+            name: name.to_string(),
+            enabled,
+            start: start.to_string(),
+            end: end.to_string(),
+            query: name.to_string(),
+            collection_id: String::new(),
+        }
+    }
+
+    fn holiday_name(themes: &[HolidayTheme], time: &str) -> Option<String> {
+        active_holiday(themes, local(time)).map(|theme| theme.name.clone())
+    }
+
+    #[test]
+    fn holidays_include_both_ends_of_their_range() {
+        let themes = [
+            holiday("disabled", false, "06-01", "06-30"),
+            holiday("broken", true, "June", "06-30"),
+            holiday("summer", true, "06-21", "06-22"),
+        ];
+        assert_eq!(holiday_name(&themes, "2024-06-20 12:00"), None);
+        assert_eq!(
+            holiday_name(&themes, "2024-06-21 00:00").as_deref(),
+            Some("summer")
+        );
+        assert_eq!(
+            holiday_name(&themes, "2024-06-22 23:59").as_deref(),
+            Some("summer")
+        );
+        assert_eq!(holiday_name(&themes, "2024-06-23 00:00"), None);
+    }
+
+    #[test]
+    fn holidays_wrap_around_new_year() {
+        let themes = [holiday("winter", true, "12-20", "01-05")];
+        assert_eq!(holiday_name(&themes, "2024-12-19 12:00"), None);
+        assert_eq!(
+            holiday_name(&themes, "2024-12-31 12:00").as_deref(),
+            Some("winter")
+        );
+        assert_eq!(
+            holiday_name(&themes, "2025-01-05 12:00").as_deref(),
+            Some("winter")
+        );
+        assert_eq!(holiday_name(&themes, "2025-01-06 12:00"), None);
+    }
+
+    #[test]
+    fn month_days_accept_leap_days() {
+        assert_eq!(parse_month_day("02-29"), Some((2, 29)));
+        assert_eq!(parse_month_day(" 12-31 "), Some((12, 31)));
+        assert_eq!(parse_month_day("02-30"), None);
+        assert_eq!(parse_month_day("13-01"), None);
+    }
+}
@@ -0,0 +1,402 @@
+//! User settings as stored in `settings.json`
+
+use crate::settings_store;
+use serde::{Deserialize, Serialize};
+
+/// Search keywords used from a local time of day until the next period starts
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeywordPeriod {
+    /// "HH:MM" the period starts at
+    pub start: String,
+    /// Comma-separated keywords, one of which is searched for on each change
+    pub keywords: String,
+}
+
+impl KeywordPeriod {
+    fn new(start: &str, keywords: &str) -> Self {
+        Self {
+            start: start.to_string(),
+            keywords: keywords.to_string(),
+        }
+    }
+}
+
+/// A yearly date range during which photos come from its own query or collection
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HolidayTheme {
+    pub name: String,
+    pub enabled: bool,
+    /// First day as "MM-DD"
+    pub start: String,
+    /// Last day as "MM-DD", before `start` for ranges spanning New Year
+    pub end: String,
+    /// Search query, takes precedence over the collection
+    pub query: String,
+    pub collection_id: String,
+}
+
+impl HolidayTheme {
+    fn new(name: &str, start: &str, end: &str, query: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            enabled: false,
+            start: start.to_string(),
+            end: end.to_string(),
+            query: query.to_string(),
+            collection_id: String::new(),
+        }
+    }
+}
+
+/// A named source of photos, used while its schedule is active
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Playlist {
+    pub name: String,
+    pub enabled: bool,
+    /// Days such as "Mon-Fri" or "weekends", a time range such as "09:00-17:00", or
+    /// both as in "Sat,Sun 08:00-20:00". Empty to play at any time
+    pub schedule: String,
+    /// Comma-separated collection IDs
+    pub collection_id: String,
+    /// Search query, takes precedence over the collections
+    pub query: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WallpaperSettings {
+    /// Format version of `settings.json`, see `settings_store::MIGRATIONS`
+    pub schema_version: u32,
+    pub api_key: String,
+    pub collection_id: String,
+    pub interval_value: u32,
+    pub interval_unit: String,
+    pub auto_change: bool,
+    /// Start wally when the user logs in
+    pub launch_at_login: bool,
+    /// Keep the window hidden on startup so wally only lives in the tray
+    pub start_minimized: bool,
+    /// Closing the window hides it to the tray instead of quitting
+    pub close_to_tray: bool,
+    /// Run without a Dock icon on macOS, showing only the menu bar item
+    pub hide_dock_icon: bool,
+    /// Look for new versions in the background once a day
+    pub check_for_updates: bool,
+    /// Release channel to update from: "stable", or "beta" for pre-releases too
+    pub update_channel: String,
+    /// Write a crash report with a backtrace when wally panics, for bug reports
+    pub crash_reports: bool,
+    /// Count changes, backends and failures locally, see `metrics`
+    pub usage_metrics: bool,
+    /// Where to send the daily counts, nothing is sent when empty
+    pub usage_metrics_url: String,
+    /// macOS only: also record the wallpaper for every Space in the Dock's database.
+    /// Other Spaces show it once the Dock restarts, which happens when this is turned
+    /// on, until then it's re-applied when switching to them.
+    pub apply_to_all_spaces: bool,
+    /// Windows only: play live wallpapers in a window behind the desktop icons
+    pub live_wallpaper_renderer: bool,
+    /// Which Unsplash size to download: "raw", "full", "regular" or "small"
+    pub quality: String,
+    /// How many times a network request is attempted before giving up
+    pub retry_attempts: u32,
+    /// Delay before the first retry, doubled on each further attempt
+    pub retry_base_delay_ms: u64,
+    /// Seconds to wait for a connection to be established
+    pub connect_timeout_secs: u64,
+    /// Seconds to wait between reads before a request is considered stalled
+    pub read_timeout_secs: u64,
+    /// HTTP or SOCKS proxy URL, e.g. "http://proxy:8080" or "socks5://proxy:1080"
+    pub proxy_url: String,
+    pub proxy_username: String,
+    pub proxy_password: String,
+    /// Honor the system proxy environment when no explicit proxy is configured
+    pub use_system_proxy: bool,
+    /// Downscale and crop downloads to the screen resolution before setting them
+    pub resize_to_screen: bool,
+    /// Crop around the most detailed part of a photo instead of its center when it
+    /// doesn't have the screen's shape, so subjects aren't cut off
+    pub smart_crop: bool,
+    /// Upscale photos smaller than the screen with realesrgan-ncnn-vulkan, if installed
+    pub upscale_low_resolution: bool,
+    /// Render a "Photo by … on Unsplash" caption into the wallpaper
+    pub attribution_overlay: bool,
+    /// "top-left", "top-right", "bottom-left" or "bottom-right"
+    pub attribution_corner: String,
+    /// Caption size in pixels at 1080p, scaled with the image height
+    pub attribution_font_size: f32,
+    /// Caption opacity from 0.0 to 1.0
+    pub attribution_opacity: f32,
+    /// Gaussian blur strength in pixels, 0 to disable
+    pub blur_radius: f32,
+    /// Darken the image by this percentage (0-100)
+    pub dim_percent: u8,
+    /// Vignette strength from 0.0 (off) to 1.0
+    pub vignette: f32,
+    pub grayscale: bool,
+    /// Tint color as "#rrggbb", empty to disable
+    pub tint_color: String,
+    /// How strongly the tint color is mixed in, from 0.0 to 1.0
+    pub tint_strength: f32,
+    /// Tone wallpapers down while the system is in dark mode
+    pub dark_mode_adjust: bool,
+    /// Extra darkening applied in dark mode (0-100)
+    pub dark_mode_dim_percent: u8,
+    /// Contrast change applied in dark mode, negative values reduce contrast
+    pub dark_mode_contrast: f32,
+    /// Generate a pywal color scheme from each new wallpaper
+    pub pywal_integration: bool,
+    /// Set the desktop accent color from the wallpaper's palette
+    pub sync_accent_color: bool,
+    /// Shell commands run after each change, with WALLY_PATH, WALLY_AUTHOR, WALLY_URL etc. set
+    pub hooks: Vec<String>,
+    /// Seconds a hook may run before it is killed
+    pub hook_timeout_secs: u64,
+    /// URL that receives a JSON POST after each change, empty to disable
+    pub webhook_url: String,
+    /// Serve a small JSON control API on 127.0.0.1
+    pub http_api_enabled: bool,
+    pub http_api_port: u16,
+    /// Bearer token every HTTP API request must carry, the API stays off while empty
+    pub http_api_token: String,
+    /// Show a system notification with the photo credit after automatic changes
+    pub notify_on_change: bool,
+    /// Global shortcut for changing to a new wallpaper, empty to disable
+    pub shortcut_next: String,
+    /// Global shortcut for going back to the previous wallpaper, empty to disable
+    pub shortcut_previous: String,
+    /// Global shortcut for pausing and resuming rotation, empty to disable
+    pub shortcut_pause: String,
+    /// When the daemon changes wallpaper: "interval", "times", "cron", "sun", or
+    /// "daily" for one photo per calendar day changed at local midnight
+    pub schedule_mode: String,
+    /// Local times of day ("HH:MM") used by the "times" schedule
+    pub schedule_times: Vec<String>,
+    /// Cron expression used by the "cron" schedule, evaluated in local time
+    pub schedule_cron: String,
+    /// Latitude for the "sun" schedule and the weather, looked up from the IP address
+    /// for the schedule when unset
+    pub sun_latitude: Option<f64>,
+    /// Longitude for the "sun" schedule and the weather, looked up from the IP address
+    /// for the schedule when unset
+    pub sun_longitude: Option<f64>,
+    /// Collection used between sunrise and sunset in the "sun" schedule
+    pub day_collection_id: String,
+    /// Collection used between sunset and sunrise in the "sun" schedule
+    pub night_collection_id: String,
+    /// Search query used during the day, takes precedence over the day collection
+    pub day_query: String,
+    /// Search query used at night, takes precedence over the night collection
+    pub night_query: String,
+    /// Search for keywords matching the time of day instead of the collection,
+    /// outside the "sun" schedule
+    pub time_of_day_theming: bool,
+    /// Periods of the day and their keywords, used by `time_of_day_theming`
+    pub keyword_periods: Vec<KeywordPeriod>,
+    /// Named sources with their own schedules; the first active one replaces the
+    /// collection, the "sun" sources and time of day keywords
+    pub playlists: Vec<Playlist>,
+    /// Date ranges whose source replaces every other one while they last
+    pub holiday_themes: Vec<HolidayTheme>,
+    /// Add the current season to the photo search
+    pub season_aware: bool,
+    /// Add the current weather at the configured coordinates to the photo search
+    pub weather_aware: bool,
+    /// Change the wallpaper when the screen is unlocked, besides the schedule
+    pub change_on_unlock: bool,
+    /// Change the wallpaper when wally starts with the session or the user switches
+    /// back to it
+    pub change_on_login: bool,
+    /// Move each change by a random amount up to this many minutes, 0 to disable.
+    /// Fixed-time, cron, sun and daily schedules are only ever delayed.
+    pub schedule_jitter_minutes: u32,
+    /// Windows such as "22:00-07:00" or "Mon-Fri 09:00-17:00" during which changes
+    /// are deferred until the window ends
+    pub quiet_hours: Vec<String>,
+    /// Hold automatic changes while a fullscreen app, presentation or screen share is active
+    pub pause_when_presenting: bool,
+    /// When a due change may happen: "off" for right away, "idle" once the user has
+    /// been idle for `idle_minutes`, or "return" when they come back from that long away
+    pub idle_change_mode: String,
+    /// Minutes without keyboard or mouse input that count as idle
+    pub idle_minutes: u32,
+    /// What to do on battery below `battery_threshold`: "ignore", "skip" or "small"
+    pub battery_policy: String,
+    /// Battery percentage below which `battery_policy` applies
+    pub battery_threshold: u8,
+    /// What to do on a metered connection: "pause" or "small"
+    pub metered_policy: String,
+    /// Download normally even when the connection is metered
+    pub ignore_metered: bool,
+    /// Download the next wallpaper a few minutes before it is due
+    pub prefetch_next: bool,
+    /// Number of recent downloaded wallpapers kept on disk
+    pub cleanup_keep_count: u32,
+    /// Delete the oldest downloads once the folder exceeds this many MB, 0 for no cap
+    pub cleanup_max_size_mb: u64,
+    /// Delete all unpinned downloads when the disk is too full for a new wallpaper
+    pub cleanup_on_low_space: bool,
+    /// Name of downloaded files, with `{id}`, `{author}`, `{username}`,
+    /// `{description}` and `{ext}` placeholders. `{id}` is appended when left out.
+    pub filename_template: String,
+    /// Only show photos of one of Unsplash's colors, e.g. "teal" or "black_and_white",
+    /// empty to disable. Searches are filtered by Unsplash, collections by each
+    /// photo's average color
+    pub color_filter: String,
+    /// Fetch another random photo when one is smaller than the screen
+    pub min_resolution_filter: bool,
+    /// Fetch another random photo when its aspect ratio differs from the screen's by
+    /// more than this percentage, 0 to disable
+    pub max_aspect_deviation_percent: u8,
+    /// Fetch another photo when one looks nearly identical to a recent wallpaper
+    pub skip_similar: bool,
+    /// Stitch portrait photos side by side on screens at least 2.2 times wider than
+    /// tall, where a single photo rarely fits
+    pub collage_enabled: bool,
+    /// Photos in a collage, 2 or 3
+    pub collage_photos: u8,
+    /// Pixels between and around the photos of a collage
+    pub collage_gap: u32,
+    /// Color showing through the gaps of a collage, as "#rrggbb"
+    pub collage_background: String,
+    /// Order collection photos and offline cached wallpapers are shown in: "random",
+    /// "sequential", or "shuffle" to shuffle once with `rotation_seed` and then cycle
+    pub rotation_order: String,
+    /// Seed of the "shuffle" order, change it to get a different sequence
+    pub rotation_seed: u64,
+    /// Cycle the images of `slideshow_folder` instead of fetching photos, for photo
+    /// frames and demo booths
+    pub slideshow_enabled: bool,
+    /// Folder of images the slideshow shows
+    pub slideshow_folder: String,
+    /// Minutes each slideshow image stays up, replacing the schedule
+    pub slideshow_interval_minutes: u32,
+    /// Order of the slideshow: "alphabetical", "shuffle" to shuffle once with
+    /// `rotation_seed`, or "date" for oldest first
+    pub slideshow_order: String,
+}
+
+/// Key release builds are signed with, set by the release workflow. Builds without
+/// it can't verify updates, so they don't look for any.
+pub fn updater_pubkey() -> Option<&'static str> {
+    option_env!("WALLY_UPDATER_PUBKEY").filter(|pubkey| !pubkey.is_empty())
+}
+
+impl Default for WallpaperSettings {
+    fn default() -> Self {
+        Self {
+            schema_version: settings_store::SETTINGS_SCHEMA_VERSION,
+            api_key: String::new(),
+            collection_id: "880012".to_string(),
+            interval_value: 3,
+            interval_unit: "hours".to_string(),
+            auto_change: false,
+            launch_at_login: false,
+            start_minimized: false,
+            close_to_tray: true,
+            hide_dock_icon: false,
+            check_for_updates: updater_pubkey().is_some(),
+            update_channel: "stable".to_string(),
+            crash_reports: false,
+            usage_metrics: false,
+            usage_metrics_url: String::new(),
+            apply_to_all_spaces: false,
+            live_wallpaper_renderer: false,
+            quality: "full".to_string(),
+            retry_attempts: 3,
+            retry_base_delay_ms: 1000,
+            connect_timeout_secs: 10,
+            read_timeout_secs: 30,
+            proxy_url: String::new(),
+            proxy_username: String::new(),
+            proxy_password: String::new(),
+            use_system_proxy: true,
+            resize_to_screen: false,
+            smart_crop: true,
+            upscale_low_resolution: false,
+            attribution_overlay: false,
+            attribution_corner: "bottom-right".to_string(),
+            attribution_font_size: 18.0,
+            attribution_opacity: 0.8,
+            blur_radius: 0.0,
+            dim_percent: 0,
+            vignette: 0.0,
+            grayscale: false,
+            tint_color: String::new(),
+            tint_strength: 0.3,
+            dark_mode_adjust: false,
+            dark_mode_dim_percent: 25,
+            dark_mode_contrast: -10.0,
+            pywal_integration: false,
+            sync_accent_color: false,
+            hooks: Vec::new(),
+            hook_timeout_secs: 30,
+            webhook_url: String::new(),
+            http_api_enabled: false,
+            http_api_port: 7392,
+            http_api_token: String::new(),
+            notify_on_change: true,
+            shortcut_next: String::new(),
+            shortcut_previous: String::new(),
+            shortcut_pause: String::new(),
+            schedule_mode: "interval".to_string(),
+            schedule_times: vec![
+                "08:00".to_string(),
+                "13:00".to_string(),
+                "19:00".to_string(),
+            ],
+            schedule_cron: "0 9 * * *".to_string(),
+            sun_latitude: None,
+            sun_longitude: None,
+            day_collection_id: String::new(),
+            night_collection_id: String::new(),
+            day_query: "daylight landscape".to_string(),
+            night_query: "night sky".to_string(),
+            time_of_day_theming: false,
+            keyword_periods: vec![
+                KeywordPeriod::new("06:00", "sunrise, coffee, morning mist"),
+                KeywordPeriod::new("11:00", "landscape, mountains, beach"),
+                KeywordPeriod::new("17:00", "sunset, golden hour"),
+                KeywordPeriod::new("20:00", "city lights, night sky"),
+            ],
+            playlists: Vec::new(),
+            holiday_themes: vec![
+                HolidayTheme::new("Halloween", "10-25", "10-31", "halloween"),
+                HolidayTheme::new("December holidays", "12-01", "12-26", "christmas lights"),
+                HolidayTheme::new("New Year", "12-31", "01-01", "fireworks"),
+            ],
+            season_aware: false,
+            weather_aware: false,
+            change_on_unlock: false,
+            change_on_login: false,
+            schedule_jitter_minutes: 0,
+            quiet_hours: Vec::new(),
+            pause_when_presenting: true,
+            idle_change_mode: "off".to_string(),
+            idle_minutes: 5,
+            battery_policy: "ignore".to_string(),
+            battery_threshold: 20,
+            metered_policy: "small".to_string(),
+            ignore_metered: false,
+            prefetch_next: true,
+            cleanup_keep_count: 10,
+            cleanup_max_size_mb: 0,
+            cleanup_on_low_space: true,
+            filename_template: "wallpaper_{id}.{ext}".to_string(),
+            color_filter: String::new(),
+            min_resolution_filter: false,
+            max_aspect_deviation_percent: 0,
+            skip_similar: true,
+            collage_enabled: false,
+            collage_photos: 3,
+            collage_gap: 16,
+            collage_background: "#000000".to_string(),
+            rotation_order: "random".to_string(),
+            rotation_seed: 0,
+            slideshow_enabled: false,
+            slideshow_folder: String::new(),
+            slideshow_interval_minutes: 5,
+            slideshow_order: "alphabetical".to_string(),
+        }
+    }
+}
@@ -0,0 +1,221 @@
+//! Reading and writing `settings.json`: atomic saves, a backup of the last good
+//! file, and migrations from older schema versions
+
+use crate::WallpaperSettings;
+use serde_json::{Map, Value};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// Version written to `schema_version`. Bump it and append to `MIGRATIONS` when a
+/// setting is renamed or changes meaning.
+pub const SETTINGS_SCHEMA_VERSION: u32 = 1;
+
+/// `MIGRATIONS[n]` upgrades settings from version `n` to `n + 1`
+const MIGRATIONS: &[fn(&mut Map<String, Value>)] = &[migrate_v0_to_v1];
+
+/// Files written before versioning have the same fields, they only lack the
+/// version number
+fn migrate_v0_to_v1(_settings: &mut Map<String, Value>) {}
+
+pub(crate) fn backup_path(path: &Path) -> PathBuf {
+    path.with_extension("json.bak")
+}
+
+/// Write through a temp file in the same directory and rename it over `path`, so
+/// a crash mid-write never leaves a truncated file behind
+pub fn write_atomic(path: &Path, content: &[u8]) -> std::io::Result<()> {
+    let tmp = path.with_extension("json.tmp");
+    let result = fs::File::create(&tmp)
+        .and_then(|mut file| {
+            file.write_all(content)?;
+            file.sync_all()
+        })
+        .and_then(|_| fs::rename(&tmp, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result
+}
+
+/// Bring a parsed settings file up to the current schema
+fn migrate(mut value: Value) -> Result<Value, String> {
+    let settings = value
+        .as_object_mut()
+        .ok_or("Settings file is not a JSON object")?;
+    let version = settings
+        .get("schema_version")
+        .and_then(Value::as_u64)
+        .unwrap_or(0) as u32;
+
+    if version > SETTINGS_SCHEMA_VERSION {
+        warn!(
+            "Settings were written by a newer version (schema {}), unknown fields are ignored",
+            version
+        );
+    }
+    for (from, step) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        step(settings);
+        info!("Migrated settings from schema {} to {}", from, from + 1);
+    }
+    settings.insert(
+        "schema_version".to_string(),
+        SETTINGS_SCHEMA_VERSION.max(version).into(),
+    );
+    Ok(value)
+}
+
+/// Settings from JSON of any schema version
+pub fn from_value(value: Value) -> Result<WallpaperSettings, String> {
+    serde_json::from_value(migrate(value)?).map_err(|e| e.to_string())
+}
+
+/// Parse and migrate a settings file without any fallback
+pub fn read(path: &Path) -> Result<WallpaperSettings, String> {
+    let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    from_value(serde_json::from_str(&content).map_err(|e| e.to_string())?)
+}
+
+/// Load settings, falling back to the backup and then to defaults
+pub fn load(path: &Path) -> WallpaperSettings {
+    if !path.exists() {
+        return WallpaperSettings::default();
+    }
+    match read(path) {
+        Ok(settings) => settings,
+        Err(e) => {
+            warn!("Failed to load {}: {}", path.display(), e);
+            let backup = backup_path(path);
+            match read(&backup) {
+                Ok(settings) => {
+                    warn!("Restored settings from {}", backup.display());
+                    settings
+                }
+                Err(e) => {
+                    warn!("No usable settings backup ({}), using defaults", e);
+                    WallpaperSettings::default()
+                }
+            }
+        }
+    }
+}
+
+/// Save settings, keeping the previous file as `.bak`. A file that no longer loads
+/// is not backed up, so it can't replace the last good copy.
+///
+/// A file written by a newer version keeps its schema version and the fields this
+/// version doesn't know, so the newer version doesn't miss its migrations.
+pub fn save(path: &Path, settings: &WallpaperSettings) -> Result<(), String> {
+    let mut settings = settings.clone();
+    settings.schema_version = SETTINGS_SCHEMA_VERSION;
+    let mut value = serde_json::to_value(&settings).map_err(|e| e.to_string())?;
+    if let Some(mut newer) = read_newer_schema(path) {
+        let version = newer.get("schema_version").and_then(Value::as_u64);
+        warn!(
+            "Settings were written by a newer version (schema {}), keeping its fields",
+            version.unwrap_or_default()
+        );
+        if let Value::Object(ours) = value {
+            for (key, field) in ours {
+                if key != "schema_version" {
+                    newer.insert(key, field);
+                }
+            }
+        }
+        value = Value::Object(newer);
+    }
+    let content = serde_json::to_string_pretty(&value).map_err(|e| e.to_string())?;
+
+    if read(path).is_ok() {
+        if let Err(e) = fs::copy(path, backup_path(path)) {
+            warn!("Failed to back up settings: {}", e);
+        }
+    }
+    write_atomic(path, content.as_bytes())
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+/// The settings file as written, if its schema is newer than this version knows
+fn read_newer_schema(path: &Path) -> Option<Map<String, Value>> {
+    let content = fs::read_to_string(path).ok()?;
+    let Value::Object(settings) = serde_json::from_str(&content).ok()? else {
+        return None;
+    };
+    let version = settings.get("schema_version")?.as_u64()?;
+    (version > u64::from(SETTINGS_SCHEMA_VERSION)).then_some(settings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// An empty directory of its own under the system temp dir
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("wally-test-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn unversioned_settings_are_migrated() {
+        let settings = from_value(json!({ "interval_value": 7 })).unwrap();
+        assert_eq!(settings.schema_version, SETTINGS_SCHEMA_VERSION);
+        assert_eq!(settings.interval_value, 7);
+    }
+
+    #[test]
+    fn newer_schema_version_is_kept_on_load() {
+        let newer = SETTINGS_SCHEMA_VERSION + 1;
+        let settings = from_value(json!({ "schema_version": newer })).unwrap();
+        assert_eq!(settings.schema_version, newer);
+    }
+
+    #[test]
+    fn non_objects_are_rejected() {
+        assert!(from_value(json!([1, 2])).is_err());
+    }
+
+    #[test]
+    fn save_keeps_a_newer_schema_and_its_fields() {
+        let dir = temp_dir("newer-schema");
+        let path = dir.join("settings.json");
+        let newer = SETTINGS_SCHEMA_VERSION + 1;
+        let file = json!({ "schema_version": newer, "from_the_future": true });
+        fs::write(&path, file.to_string()).unwrap();
+
+        let settings = WallpaperSettings {
+            interval_value: 7,
+            ..Default::default()
+        };
+        save(&path, &settings).unwrap();
+
+        let saved: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(saved["schema_version"], newer);
+        assert_eq!(saved["from_the_future"], true);
+        assert_eq!(saved["interval_value"], 7);
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn save_backs_up_the_previous_file() {
+        let dir = temp_dir("backup");
+        let path = dir.join("settings.json");
+        let first = WallpaperSettings {
+            interval_value: 1,
+            ..Default::default()
+        };
+        save(&path, &first).unwrap();
+        let second = WallpaperSettings {
+            interval_value: 2,
+            ..Default::default()
+        };
+        save(&path, &second).unwrap();
+
+        assert_eq!(read(&path).unwrap().interval_value, 2);
+        assert_eq!(read(&backup_path(&path)).unwrap().interval_value, 1);
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,62 @@
+//! Perceptual hashing to recognise near-identical photos, such as several crops of
+//! the same shot
+
+use image::imageops::FilterType;
+use std::f64::consts::PI;
+use std::path::Path;
+
+/// Side of the grayscale thumbnail the DCT runs on
+const SAMPLE_SIZE: usize = 32;
+
+/// Side of the low-frequency block that makes up the 64-bit hash
+const HASH_SIZE: usize = 8;
+
+/// Hashes at most this many bits apart are treated as the same picture
+pub const SIMILARITY_THRESHOLD: u32 = 10;
+
+/// 64-bit pHash of an image: each bit tells whether a low-frequency DCT coefficient
+/// is above the median, which survives resizing, recompression and light filters
+pub fn perceptual_hash(path: &Path) -> Result<u64, String> {
+    let image = image::open(path).map_err(|e| format!("Failed to open image: {}", e))?;
+    let gray = image
+        .resize_exact(SAMPLE_SIZE as u32, SAMPLE_SIZE as u32, FilterType::Triangle)
+        .to_luma8();
+    let pixels: Vec<f64> = gray.pixels().map(|pixel| pixel[0] as f64).collect();
+
+    // Only the top-left block of the 2D DCT is needed
+    let cosines: Vec<Vec<f64>> = (0..HASH_SIZE)
+        .map(|u| {
+            (0..SAMPLE_SIZE)
+                .map(|x| ((2 * x + 1) as f64 * u as f64 * PI / (2 * SAMPLE_SIZE) as f64).cos())
+                .collect()
+        })
+        .collect();
+    let mut coefficients = Vec::with_capacity(HASH_SIZE * HASH_SIZE);
+    for v in 0..HASH_SIZE {
+        for u in 0..HASH_SIZE {
+            let mut sum = 0.0;
+            for y in 0..SAMPLE_SIZE {
+                for x in 0..SAMPLE_SIZE {
+                    sum += pixels[y * SAMPLE_SIZE + x] * cosines[u][x] * cosines[v][y];
+                }
+            }
+            coefficients.push(sum);
+        }
+    }
+
+    // The DC term only reflects overall brightness, so leave it out of the median
+    let mut sorted = coefficients[1..].to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let median = sorted[sorted.len() / 2];
+
+    Ok(coefficients
+        .iter()
+        .enumerate()
+        .filter(|(_, coefficient)| **coefficient > median)
+        .fold(0u64, |hash, (bit, _)| hash | 1 << bit))
+}
+
+/// Whether two hashes describe visually near-identical images
+pub fn is_similar(a: u64, b: u64) -> bool {
+    (a ^ b).count_ones() <= SIMILARITY_THRESHOLD
+}
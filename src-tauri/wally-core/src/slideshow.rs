@@ -0,0 +1,71 @@
+//! The slideshow: a fixed folder of local images shown one after another in a set
+//! order, separate from the online rotation
+
+use crate::download::is_image_extension;
+use crate::rotation::set_cached_wallpaper;
+use crate::storage::advance_rotation_cursor;
+use crate::{CurrentWallpaper, WallpaperSettings, WallyError};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use tracing::info;
+
+/// Images directly inside `folder`, in the slideshow's `order`
+pub fn slides(folder: &Path, order: &str, seed: u64) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(folder) else {
+        return Vec::new();
+    };
+    let mut slides: Vec<(PathBuf, SystemTime)> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.is_file()
+                && path
+                    .extension()
+                    .is_some_and(|ext| is_image_extension(&ext.to_string_lossy()))
+        })
+        .map(|path| {
+            let modified = fs::metadata(&path)
+                .and_then(|metadata| metadata.modified())
+                .unwrap_or(SystemTime::UNIX_EPOCH);
+            (path, modified)
+        })
+        .collect();
+
+    // Sorting by name first keeps the shuffle and equal dates stable
+    slides.sort_by(|a, b| a.0.file_name().cmp(&b.0.file_name()));
+    match order {
+        "shuffle" => slides.shuffle(&mut StdRng::seed_from_u64(seed)),
+        "date" => slides.sort_by_key(|(_, modified)| *modified),
+        _ => {}
+    }
+    slides.into_iter().map(|(path, _)| path).collect()
+}
+
+/// The next image of the slideshow, advancing it for next time
+pub fn next_slide(settings: &WallpaperSettings) -> Result<PathBuf, WallyError> {
+    let folder = Path::new(&settings.slideshow_folder);
+    let mut slides = slides(folder, &settings.slideshow_order, settings.rotation_seed);
+    if slides.is_empty() {
+        return Err(format!("No images in slideshow folder {}", folder.display()).into());
+    }
+    let cursor = advance_rotation_cursor(&format!("slideshow:{}", folder.display()));
+    let index = (cursor % slides.len() as u64) as usize;
+    Ok(slides.swap_remove(index))
+}
+
+/// Set the next image of the slideshow as the wallpaper. Blocking.
+pub fn show_next_slide(settings: &WallpaperSettings) -> Result<CurrentWallpaper, WallyError> {
+    let slide = next_slide(settings)?;
+    info!(target: "wally::daemon", "Showing slide {}", slide.display());
+    set_cached_wallpaper(
+        CurrentWallpaper {
+            local_path: Some(slide.to_string_lossy().to_string()),
+            ..Default::default()
+        },
+        settings.apply_to_all_spaces,
+    )
+}
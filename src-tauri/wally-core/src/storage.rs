@@ -0,0 +1,188 @@
+//! Wallpaper state persisted in the config dir: the current wallpaper, history,
+//! pins and the daemon schedule
+
+use crate::paths::{get_config_dir, get_settings_path};
+use crate::{settings_store, UnsplashImage, WallpaperSettings};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::warn;
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct CurrentWallpaper {
+    pub image: Option<UnsplashImage>,
+    pub local_path: Option<String>,
+    pub set_at: Option<String>,
+    /// Dominant colors of the wallpaper as "#rrggbb", most common first
+    #[serde(default)]
+    pub palette: Vec<String>,
+    /// Perceptual hash of the wallpaper as 16 hex digits, used to skip near-duplicates
+    #[serde(default)]
+    pub phash: Option<String>,
+}
+
+pub fn load_settings() -> WallpaperSettings {
+    settings_store::load(&get_settings_path())
+}
+
+fn current_wallpaper_path() -> PathBuf {
+    get_config_dir().join("current_wallpaper.json")
+}
+
+fn read_current_wallpaper(path: &Path) -> Result<CurrentWallpaper, String> {
+    let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    serde_json::from_str(&content).map_err(|e| e.to_string())
+}
+
+/// The current wallpaper, falling back to the backup when the file is corrupt
+pub fn load_current_wallpaper() -> CurrentWallpaper {
+    let path = current_wallpaper_path();
+    if !path.exists() {
+        return CurrentWallpaper::default();
+    }
+    read_current_wallpaper(&path).unwrap_or_else(|e| {
+        warn!("Failed to load {}: {}", path.display(), e);
+        let backup = settings_store::backup_path(&path);
+        match read_current_wallpaper(&backup) {
+            Ok(current) => {
+                warn!("Restored current wallpaper from {}", backup.display());
+                current
+            }
+            Err(e) => {
+                warn!("No usable current wallpaper backup ({})", e);
+                CurrentWallpaper::default()
+            }
+        }
+    })
+}
+
+/// Write `current_wallpaper.json` atomically, keeping the previous good file as `.bak`
+pub fn persist_current_wallpaper(current: &CurrentWallpaper) -> Result<(), String> {
+    let path = current_wallpaper_path();
+    let content = serde_json::to_string_pretty(current).map_err(|e| e.to_string())?;
+    if read_current_wallpaper(&path).is_ok() {
+        if let Err(e) = fs::copy(&path, settings_store::backup_path(&path)) {
+            warn!("Failed to back up current wallpaper: {}", e);
+        }
+    }
+    settings_store::write_atomic(&path, content.as_bytes())
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+/// Number of past wallpapers remembered, matching the files kept on disk by default
+const HISTORY_LIMIT: usize = 10;
+
+/// Paths of downloaded wallpapers that cleanup must never delete
+pub fn load_pins() -> Vec<String> {
+    let pins_path = get_config_dir().join("pins.json");
+    if let Ok(content) = fs::read_to_string(&pins_path) {
+        serde_json::from_str(&content).unwrap_or_default()
+    } else {
+        Vec::new()
+    }
+}
+
+pub fn save_pins(pins: &[String]) -> Result<(), String> {
+    let pins_path = get_config_dir().join("pins.json");
+    let content = serde_json::to_string_pretty(pins).map_err(|e| e.to_string())?;
+    settings_store::write_atomic(&pins_path, content.as_bytes()).map_err(|e| e.to_string())
+}
+
+/// Photos queued to be shown next, in order, before the daemon falls back to random ones
+pub fn load_queue() -> Vec<UnsplashImage> {
+    let queue_path = get_config_dir().join("queue.json");
+    if let Ok(content) = fs::read_to_string(&queue_path) {
+        serde_json::from_str(&content).unwrap_or_default()
+    } else {
+        Vec::new()
+    }
+}
+
+pub fn save_queue(queue: &[UnsplashImage]) -> Result<(), String> {
+    let queue_path = get_config_dir().join("queue.json");
+    let content = serde_json::to_string_pretty(queue).map_err(|e| e.to_string())?;
+    settings_store::write_atomic(&queue_path, content.as_bytes()).map_err(|e| e.to_string())
+}
+
+/// Previously set wallpapers, newest first
+pub fn load_history() -> Vec<CurrentWallpaper> {
+    let history_path = get_config_dir().join("history.json");
+    if let Ok(content) = fs::read_to_string(&history_path) {
+        serde_json::from_str(&content).unwrap_or_default()
+    } else {
+        Vec::new()
+    }
+}
+
+pub fn save_history(history: &[CurrentWallpaper]) -> Result<(), String> {
+    let history_path = get_config_dir().join("history.json");
+    let content = serde_json::to_string_pretty(history).map_err(|e| e.to_string())?;
+    settings_store::write_atomic(&history_path, content.as_bytes()).map_err(|e| e.to_string())
+}
+
+/// Persist the current wallpaper and record it at the front of the history
+pub fn write_current_wallpaper(current: &CurrentWallpaper) -> Result<(), String> {
+    persist_current_wallpaper(current)?;
+
+    let mut history = load_history();
+    history.retain(|entry| entry.local_path != current.local_path);
+    history.insert(0, current.clone());
+    history.truncate(HISTORY_LIMIT);
+    save_history(&history)
+}
+
+/// Daemon schedule persisted across restarts so long intervals don't drift
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct ScheduleState {
+    pub last_changed_at: Option<String>,
+    pub next_change_at: Option<String>,
+    /// Position in each ordered rotation, keyed by what is being rotated through
+    #[serde(default)]
+    pub rotation_cursors: HashMap<String, u64>,
+}
+
+impl ScheduleState {
+    pub fn last_changed_at(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        let last = chrono::DateTime::parse_from_rfc3339(self.last_changed_at.as_deref()?).ok()?;
+        Some(last.with_timezone(&chrono::Utc))
+    }
+}
+
+pub fn load_schedule_state() -> ScheduleState {
+    let schedule_path = get_config_dir().join("schedule.json");
+    if let Ok(content) = fs::read_to_string(&schedule_path) {
+        serde_json::from_str(&content).unwrap_or_default()
+    } else {
+        ScheduleState::default()
+    }
+}
+
+pub fn save_schedule_state(schedule: &ScheduleState) {
+    let schedule_path = get_config_dir().join("schedule.json");
+    if let Ok(content) = serde_json::to_string_pretty(schedule) {
+        let _ = settings_store::write_atomic(&schedule_path, content.as_bytes());
+    }
+}
+
+/// The current position of an ordered rotation, advancing it for next time
+pub fn advance_rotation_cursor(key: &str) -> u64 {
+    let mut schedule = load_schedule_state();
+    let cursor = schedule
+        .rotation_cursors
+        .entry(key.to_string())
+        .or_default();
+    let current = *cursor;
+    *cursor = cursor.wrapping_add(1);
+    save_schedule_state(&schedule);
+    current
+}
+
+pub fn save_next_change_at(remaining: Duration) {
+    let mut schedule = load_schedule_state();
+    schedule.next_change_at = chrono::Duration::from_std(remaining)
+        .ok()
+        .map(|remaining| (chrono::Utc::now() + remaining).to_rfc3339());
+    save_schedule_state(&schedule);
+}
@@ -0,0 +1,362 @@
+//! Detection of system conditions that hold back or adjust automatic wallpaper
+//! changes, and of requests to shut down
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+use crate::platform::run_probe;
+use std::path::{Path, PathBuf};
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+use std::process::Command;
+use std::time::Duration;
+
+/// Find a program on PATH the way the shell would
+pub fn find_in_path(name: &str) -> Option<PathBuf> {
+    let path = std::env::var_os("PATH")?;
+    std::env::split_paths(&path).find_map(|dir| {
+        let candidate = dir.join(name);
+        if candidate.is_file() {
+            return Some(candidate);
+        }
+        let candidate = dir.join(format!("{}.exe", name));
+        candidate.is_file().then_some(candidate)
+    })
+}
+
+/// Whether a fullscreen app, presentation or screen share is in progress
+pub fn is_presenting() -> bool {
+    #[cfg(target_os = "windows")]
+    {
+        use windows::Win32::UI::Shell::{
+            SHQueryUserNotificationState, QUNS_BUSY, QUNS_PRESENTATION_MODE,
+            QUNS_RUNNING_D3D_FULL_SCREEN,
+        };
+
+        // The shell reports fullscreen apps, games and presentation mode here
+        matches!(
+            unsafe { SHQueryUserNotificationState() },
+            Ok(QUNS_BUSY | QUNS_RUNNING_D3D_FULL_SCREEN | QUNS_PRESENTATION_MODE)
+        )
+    }
+
+    #[cfg(target_os = "macos")]
+    {
+        // Presentations, video calls and fullscreen video keep the display awake
+        run_probe(Command::new("pmset").args(["-g", "assertions"]))
+            .map(|output| {
+                String::from_utf8_lossy(&output.stdout).lines().any(|line| {
+                    line.trim().starts_with("PreventUserIdleDisplaySleep")
+                        && line.trim().ends_with(" 1")
+                })
+            })
+            .unwrap_or(false)
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        is_idle_inhibited() || is_active_window_fullscreen()
+    }
+
+    #[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+    {
+        false
+    }
+}
+
+/// Presentation, video call and screen sharing apps inhibit the screensaver while active
+#[cfg(target_os = "linux")]
+fn is_idle_inhibited() -> bool {
+    if crate::platform::is_kde() {
+        return ["qdbus6", "qdbus"].iter().any(|qdbus| {
+            run_probe(Command::new(qdbus).args([
+                "org.freedesktop.PowerManagement.Inhibit",
+                "/org/freedesktop/PowerManagement/Inhibit",
+                "org.freedesktop.PowerManagement.Inhibit.HasInhibit",
+            ]))
+            .map(|output| String::from_utf8_lossy(&output.stdout).trim() == "true")
+            .unwrap_or(false)
+        });
+    }
+
+    if crate::platform::is_gnome() {
+        // Flag 8 asks whether idle is inhibited
+        return run_probe(Command::new("gdbus").args([
+            "call",
+            "--session",
+            "--dest",
+            "org.gnome.SessionManager",
+            "--object-path",
+            "/org/gnome/SessionManager",
+            "--method",
+            "org.gnome.SessionManager.IsInhibited",
+            "8",
+        ]))
+        .map(|output| String::from_utf8_lossy(&output.stdout).contains("true"))
+        .unwrap_or(false);
+    }
+
+    false
+}
+
+/// Whether the focused X11 window is fullscreen; not detectable under Wayland
+#[cfg(target_os = "linux")]
+fn is_active_window_fullscreen() -> bool {
+    if std::env::var("WAYLAND_DISPLAY").is_ok() || std::env::var("DISPLAY").is_err() {
+        return false;
+    }
+
+    let Some(output) = run_probe(Command::new("xprop").args(["-root", "_NET_ACTIVE_WINDOW"]))
+    else {
+        return false;
+    };
+    let active = String::from_utf8_lossy(&output.stdout);
+    let Some(window_id) = active.split_whitespace().last() else {
+        return false;
+    };
+
+    run_probe(Command::new("xprop").args(["-id", window_id, "_NET_WM_STATE"]))
+        .map(|output| String::from_utf8_lossy(&output.stdout).contains("_NET_WM_STATE_FULLSCREEN"))
+        .unwrap_or(false)
+}
+
+/// How long since the last keyboard or mouse input, or `None` when it can't be told
+pub fn idle_time() -> Option<Duration> {
+    #[cfg(target_os = "windows")]
+    {
+        use windows::Win32::System::SystemInformation::GetTickCount;
+        use windows::Win32::UI::Input::KeyboardAndMouse::{GetLastInputInfo, LASTINPUTINFO};
+
+        let mut info = LASTINPUTINFO {
+            cbSize: std::mem::size_of::<LASTINPUTINFO>() as u32,
+            dwTime: 0,
+        };
+        if !unsafe { GetLastInputInfo(&mut info) }.as_bool() {
+            return None;
+        }
+        // Both are milliseconds since boot, wrapping after 49 days
+        let idle_ms = unsafe { GetTickCount() }.wrapping_sub(info.dwTime);
+        Some(Duration::from_millis(idle_ms.into()))
+    }
+
+    #[cfg(target_os = "macos")]
+    {
+        // The HID system reports nanoseconds since the last input event
+        let output = run_probe(Command::new("ioreg").args(["-c", "IOHIDSystem", "-d", "4"]))?;
+        let output = String::from_utf8_lossy(&output.stdout);
+        let nanos = output.lines().find_map(|line| {
+            let (_, value) = line.split_once("\"HIDIdleTime\" = ")?;
+            value.trim().parse::<u64>().ok()
+        })?;
+        Some(Duration::from_nanos(nanos))
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        if crate::platform::is_gnome() {
+            // Replies with "(uint64 <milliseconds>,)"
+            let output = run_probe(Command::new("gdbus").args([
+                "call",
+                "--session",
+                "--dest",
+                "org.gnome.Mutter.IdleMonitor",
+                "--object-path",
+                "/org/gnome/Mutter/IdleMonitor/Core",
+                "--method",
+                "org.gnome.Mutter.IdleMonitor.GetIdletime",
+            ]))?;
+            let output = String::from_utf8_lossy(&output.stdout);
+            let millis = output
+                .trim()
+                .trim_start_matches("(uint64 ")
+                .trim_end_matches(",)")
+                .parse::<u64>()
+                .ok()?;
+            return Some(Duration::from_millis(millis));
+        }
+
+        // Other X11 desktops, through the screensaver extension
+        if std::env::var("WAYLAND_DISPLAY").is_ok() || std::env::var("DISPLAY").is_err() {
+            return None;
+        }
+        let output = run_probe(&mut Command::new("xprintidle"))?;
+        let millis = String::from_utf8_lossy(&output.stdout)
+            .trim()
+            .parse::<u64>()
+            .ok()?;
+        Some(Duration::from_millis(millis))
+    }
+
+    #[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+    {
+        None
+    }
+}
+
+/// Charge level and power source
+#[derive(Debug, Clone, Copy)]
+pub struct BatteryStatus {
+    pub on_battery: bool,
+    pub percent: u8,
+}
+
+/// Battery status, or `None` on machines without a battery
+pub fn battery_status() -> Option<BatteryStatus> {
+    #[cfg(target_os = "linux")]
+    {
+        let mut percent = None;
+        let mut discharging = false;
+        let mut on_mains = false;
+        for entry in std::fs::read_dir("/sys/class/power_supply").ok()?.flatten() {
+            let path = entry.path();
+            let read = |name: &str| {
+                std::fs::read_to_string(path.join(name))
+                    .map(|value| value.trim().to_string())
+                    .unwrap_or_default()
+            };
+            match read("type").as_str() {
+                "Battery" if read("scope") != "Device" => {
+                    percent = read("capacity").parse::<u8>().ok().or(percent);
+                    discharging |= read("status") == "Discharging";
+                }
+                "Mains" | "USB" => on_mains |= read("online") == "1",
+                _ => {}
+            }
+        }
+        Some(BatteryStatus {
+            on_battery: discharging || !on_mains,
+            percent: percent?,
+        })
+    }
+
+    #[cfg(target_os = "macos")]
+    {
+        let output = run_probe(Command::new("pmset").args(["-g", "batt"]))?;
+        let output = String::from_utf8_lossy(&output.stdout);
+        let percent = output
+            .split(|c: char| c.is_whitespace() || c == ';')
+            .find_map(|word| word.strip_suffix('%')?.parse::<u8>().ok())?;
+        Some(BatteryStatus {
+            on_battery: output.contains("'Battery Power'"),
+            percent,
+        })
+    }
+
+    #[cfg(target_os = "windows")]
+    {
+        use windows::Win32::System::Power::{GetSystemPowerStatus, SYSTEM_POWER_STATUS};
+
+        let mut status = SYSTEM_POWER_STATUS::default();
+        unsafe { GetSystemPowerStatus(&mut status) }.ok()?;
+        // 128 means no system battery, 255 an unknown charge level
+        if status.BatteryFlag & 128 != 0 || status.BatteryLifePercent > 100 {
+            return None;
+        }
+        Some(BatteryStatus {
+            on_battery: status.ACLineStatus == 0,
+            percent: status.BatteryLifePercent,
+        })
+    }
+
+    #[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+    {
+        None
+    }
+}
+
+/// Whether the machine is running on battery below `threshold` percent
+pub fn is_low_battery(threshold: u8) -> bool {
+    battery_status().is_some_and(|status| status.on_battery && status.percent < threshold)
+}
+
+/// Whether the active network connection is metered (mobile data, capped plans, roaming)
+pub fn is_metered_connection() -> bool {
+    #[cfg(target_os = "windows")]
+    {
+        use windows::Networking::Connectivity::{NetworkCostType, NetworkInformation};
+
+        let cost = NetworkInformation::GetInternetConnectionProfile()
+            .and_then(|profile| profile.GetConnectionCost());
+        let Ok(cost) = cost else {
+            return false;
+        };
+        matches!(
+            cost.NetworkCostType(),
+            Ok(NetworkCostType::Fixed | NetworkCostType::Variable)
+        ) || cost.Roaming().unwrap_or(false)
+            || cost.OverDataLimit().unwrap_or(false)
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        // NetworkManager reports 1 (yes) or 3 (guessed yes) for metered connections
+        run_probe(Command::new("busctl").args([
+            "--system",
+            "get-property",
+            "org.freedesktop.NetworkManager",
+            "/org/freedesktop/NetworkManager",
+            "org.freedesktop.NetworkManager",
+            "Metered",
+        ]))
+        .map(|output| {
+            matches!(
+                String::from_utf8_lossy(&output.stdout).trim(),
+                "u 1" | "u 3"
+            )
+        })
+        .unwrap_or(false)
+    }
+
+    #[cfg(not(any(target_os = "windows", target_os = "linux")))]
+    {
+        false
+    }
+}
+
+/// Bytes available to the current user on the filesystem holding `path`
+pub fn available_space(path: &Path) -> Option<u64> {
+    #[cfg(target_os = "windows")]
+    {
+        use windows::core::HSTRING;
+        use windows::Win32::Storage::FileSystem::GetDiskFreeSpaceExW;
+
+        let mut available = 0u64;
+        unsafe { GetDiskFreeSpaceExW(&HSTRING::from(path), Some(&mut available), None, None) }
+            .ok()?;
+        Some(available)
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    {
+        // POSIX output is a header and one line whose fourth column is free 1K blocks
+        let output = run_probe(Command::new("df").arg("-Pk").arg(path))?;
+        let output = String::from_utf8_lossy(&output.stdout);
+        let blocks = output
+            .lines()
+            .last()?
+            .split_whitespace()
+            .nth(3)?
+            .parse::<u64>()
+            .ok()?;
+        Some(blocks * 1024)
+    }
+
+    #[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+    {
+        let _ = path;
+        None
+    }
+}
+
+/// Resolves on Ctrl+C, or SIGTERM from systemd or the session ending
+pub async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        if let Ok(mut terminate) = signal(SignalKind::terminate()) {
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {}
+                _ = terminate.recv() => {}
+            }
+            return;
+        }
+    }
+    let _ = tokio::signal::ctrl_c().await;
+}
@@ -0,0 +1,547 @@
+//! Unsplash API types and the HTTP client used to talk to it
+
+use crate::{schedule, weather, WallpaperSettings, WallyError};
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::time::Duration;
+use tracing::warn;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnsplashUrls {
+    pub raw: String,
+    pub full: String,
+    pub regular: String,
+    pub small: String,
+    pub thumb: String,
+}
+
+impl UnsplashUrls {
+    /// Pick the URL matching the configured download quality
+    pub fn for_quality(&self, quality: &str) -> &str {
+        match quality {
+            "raw" => &self.raw,
+            "regular" => &self.regular,
+            "small" => &self.small,
+            _ => &self.full,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnsplashUser {
+    pub name: String,
+    pub username: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnsplashLinks {
+    pub html: String,
+    pub download: String,
+    pub download_location: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnsplashImage {
+    pub id: String,
+    pub description: Option<String>,
+    pub alt_description: Option<String>,
+    /// Pixel size of the original, 0 for photos saved before it was recorded
+    #[serde(default)]
+    pub width: u32,
+    #[serde(default)]
+    pub height: u32,
+    /// Average color as "#rrggbb"
+    #[serde(default)]
+    pub color: Option<String>,
+    pub urls: UnsplashUrls,
+    pub user: UnsplashUser,
+    pub links: UnsplashLinks,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CollectionLinks {
+    pub html: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CollectionCover {
+    pub urls: UnsplashUrls,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnsplashCollection {
+    pub id: String,
+    pub title: String,
+    pub description: Option<String>,
+    pub total_photos: u64,
+    pub user: UnsplashUser,
+    pub cover_photo: Option<CollectionCover>,
+    pub links: CollectionLinks,
+}
+
+/// One page of a search, as returned by the `/search/*` endpoints
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchPage<T> {
+    pub total: u64,
+    pub total_pages: u64,
+    pub results: Vec<T>,
+}
+
+/// Referral parameters the Unsplash guidelines ask for on links back to the site
+const UTM_PARAMS: &str = "utm_source=unsplash_wally&utm_medium=referral";
+
+/// Markup for a credit line with links to the photographer and the photo
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CreditFormat {
+    Plain,
+    Markdown,
+    Html,
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+impl UnsplashImage {
+    /// Credit line required by the Unsplash guidelines
+    pub fn attribution(&self) -> String {
+        format!("Photo by {} on Unsplash", self.user.name)
+    }
+
+    /// The photo's page on unsplash.com, with referral parameters
+    pub fn photo_url(&self) -> String {
+        format!("{}?{}", self.links.html, UTM_PARAMS)
+    }
+
+    /// The credit line with referral links, e.g. for a blog post or stream overlay
+    pub fn credit(&self, format: CreditFormat) -> String {
+        let profile = format!(
+            "https://unsplash.com/@{}?{}",
+            self.user.username, UTM_PARAMS
+        );
+        let photo = self.photo_url();
+        match format {
+            CreditFormat::Plain => format!("{} ({})", self.attribution(), photo),
+            CreditFormat::Markdown => format!(
+                "Photo by [{}]({}) on [Unsplash]({})",
+                self.user.name.replace('[', "\\[").replace(']', "\\]"),
+                profile,
+                photo
+            ),
+            CreditFormat::Html => format!(
+                "Photo by <a href=\"{}\">{}</a> on <a href=\"{}\">Unsplash</a>",
+                escape_html(&profile),
+                escape_html(&self.user.name),
+                escape_html(&photo)
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ScreenResolution {
+    pub width: u32,
+    pub height: u32,
+    pub scale_factor: f64,
+}
+
+const USER_AGENT: &str = concat!("Wally/", env!("CARGO_PKG_VERSION"));
+
+/// Build the HTTP client shared by all network requests
+pub fn build_http_client(settings: &WallpaperSettings) -> Result<reqwest::Client, String> {
+    let mut builder = reqwest::Client::builder()
+        .user_agent(USER_AGENT)
+        .connect_timeout(Duration::from_secs(settings.connect_timeout_secs.max(1)))
+        .read_timeout(Duration::from_secs(settings.read_timeout_secs.max(1)));
+
+    let proxy_url = settings.proxy_url.trim();
+    if !proxy_url.is_empty() {
+        let mut proxy =
+            reqwest::Proxy::all(proxy_url).map_err(|e| format!("Invalid proxy URL: {}", e))?;
+        if !settings.proxy_username.is_empty() {
+            proxy = proxy.basic_auth(&settings.proxy_username, &settings.proxy_password);
+        }
+        builder = builder.proxy(proxy);
+    } else if !settings.use_system_proxy {
+        builder = builder.no_proxy();
+    }
+
+    builder
+        .build()
+        .map_err(|e| format!("Failed to build HTTP client: {}", e))
+}
+
+/// Delay before retry number `attempt` (starting at 1): exponential backoff with up to 50% jitter
+fn retry_delay(base_delay_ms: u64, attempt: u32) -> Duration {
+    let backoff = base_delay_ms
+        .saturating_mul(1 << attempt.saturating_sub(1).min(10))
+        .min(30_000);
+    let jitter = rand::thread_rng().gen_range(0..=backoff / 2);
+    Duration::from_millis(backoff + jitter)
+}
+
+/// Unsplash limits requests per hour, so without a Retry-After header the limit has
+/// reset at the latest an hour from now
+const RATE_LIMIT_WINDOW: chrono::Duration = chrono::Duration::hours(1);
+
+/// Turn an unsuccessful Unsplash API response into an error. `resource` names what
+/// was requested, for the not found message.
+pub async fn api_error(response: reqwest::Response, resource: &str) -> WallyError {
+    let status = response.status();
+    let header = |name: &str| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.trim().to_string())
+    };
+    let exhausted = header("x-ratelimit-remaining").is_some_and(|remaining| remaining == "0");
+    let retry_after = header("retry-after").and_then(|seconds| seconds.parse::<i64>().ok());
+
+    // Errors come as {"errors": ["..."]}, or plain text for rate limiting
+    let body = response.text().await.unwrap_or_default();
+    let message = serde_json::from_str::<serde_json::Value>(&body)
+        .ok()
+        .and_then(|json| {
+            let errors = json.get("errors")?.as_array()?;
+            let errors: Vec<&str> = errors.iter().filter_map(|e| e.as_str()).collect();
+            Some(errors.join(", "))
+        })
+        .unwrap_or_else(|| body.trim().to_string());
+
+    let rate_limited = status == reqwest::StatusCode::TOO_MANY_REQUESTS
+        || (status == reqwest::StatusCode::FORBIDDEN
+            && (exhausted || message.to_ascii_lowercase().contains("rate limit")));
+    if rate_limited {
+        let wait = retry_after
+            .map(chrono::Duration::seconds)
+            .unwrap_or(RATE_LIMIT_WINDOW);
+        return WallyError::RateLimited {
+            reset: Some(chrono::Utc::now() + wait),
+        };
+    }
+
+    match status {
+        reqwest::StatusCode::UNAUTHORIZED => WallyError::InvalidApiKey,
+        reqwest::StatusCode::FORBIDDEN => WallyError::Forbidden(message),
+        reqwest::StatusCode::NOT_FOUND => WallyError::NotFound {
+            resource: resource.to_string(),
+        },
+        _ => WallyError::Api {
+            status: status.as_u16(),
+            message,
+        },
+    }
+}
+
+/// `X-Ratelimit-Remaining` of the latest Unsplash response
+static RATE_LIMIT_REMAINING: Mutex<Option<u32>> = Mutex::new(None);
+
+/// How many Unsplash requests are left this hour, `None` before the first response
+pub fn rate_limit_remaining() -> Option<u32> {
+    RATE_LIMIT_REMAINING
+        .lock()
+        .ok()
+        .and_then(|remaining| *remaining)
+}
+
+fn record_rate_limit(response: &reqwest::Response) {
+    let remaining = response
+        .headers()
+        .get("x-ratelimit-remaining")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse().ok());
+    if let (Some(remaining), Ok(mut recorded)) = (remaining, RATE_LIMIT_REMAINING.lock()) {
+        *recorded = Some(remaining);
+    }
+}
+
+/// Send a request, retrying connection failures and server errors with jittered
+/// exponential backoff so a transient network blip doesn't fail the whole change
+pub async fn send_with_retry(
+    request: reqwest::RequestBuilder,
+    settings: &WallpaperSettings,
+) -> Result<reqwest::Response, reqwest::Error> {
+    let attempts = settings.retry_attempts.max(1);
+    let mut attempt = 1;
+
+    loop {
+        let result = match request.try_clone() {
+            Some(request) => request.send().await,
+            // Requests with streaming bodies can't be replayed
+            None => return request.send().await,
+        };
+
+        if let Ok(response) = &result {
+            record_rate_limit(response);
+        }
+        let transient = match &result {
+            Ok(response) => response.status().is_server_error(),
+            Err(e) => e.is_connect() || e.is_timeout() || e.is_request(),
+        };
+        if !transient || attempt >= attempts {
+            return result;
+        }
+
+        let delay = retry_delay(settings.retry_base_delay_ms, attempt);
+        warn!(
+            "Request failed (attempt {}/{}), retrying in {}ms",
+            attempt,
+            attempts,
+            delay.as_millis()
+        );
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+/// One keyword from the period of the day `now` falls in, picked at random
+fn time_of_day_query(settings: &WallpaperSettings, now: chrono::DateTime<chrono::Utc>) -> &str {
+    if !settings.time_of_day_theming {
+        return "";
+    }
+    let keywords: Vec<&str> = schedule::period_keywords(&settings.keyword_periods, now)
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|keyword| !keyword.is_empty())
+        .collect();
+    keywords
+        .choose(&mut rand::thread_rng())
+        .copied()
+        .unwrap_or_default()
+}
+
+/// Query parameters selecting where random photos come from.
+/// An active playlist comes first. Otherwise the "sun" schedule picks its day or
+/// night source depending on the current time, and other schedules search for the
+/// time of day's keywords when theming is on. Season and weather keywords are added
+/// to the search when enabled. An active holiday theme replaces all of them.
+pub fn random_photo_params(
+    settings: &WallpaperSettings,
+    location: Option<schedule::Location>,
+) -> Vec<(&'static str, String)> {
+    let now = chrono::Utc::now();
+    let playlist = schedule::active_playlist(&settings.playlists, now);
+    let sun = location.filter(|_| settings.schedule_mode == "sun");
+    let (collection_id, query) = match (playlist, sun) {
+        (Some(playlist), _) => (&playlist.collection_id, playlist.query.trim()),
+        (None, Some(location)) if schedule::is_daytime(now, location) => {
+            (&settings.day_collection_id, settings.day_query.trim())
+        }
+        (None, Some(_)) => (&settings.night_collection_id, settings.night_query.trim()),
+        (None, None) => (&settings.collection_id, time_of_day_query(settings, now)),
+    };
+    // Searching for the season or weather replaces the collection
+    let query = [query, &weather::conditions_query(settings, now)]
+        .into_iter()
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    let mut params = vec![("orientation", "landscape".to_string())];
+    if let Some(theme) = schedule::active_holiday(&settings.holiday_themes, now) {
+        if !theme.query.trim().is_empty() {
+            params.push(("query", theme.query.trim().to_string()));
+            return params;
+        }
+        if !theme.collection_id.trim().is_empty() {
+            params.push(("collections", theme.collection_id.trim().to_string()));
+            return params;
+        }
+    }
+    if !query.is_empty() {
+        params.push(("query", query));
+    } else if !collection_id.is_empty() {
+        params.push(("collections", collection_id.clone()));
+    } else if !settings.collection_id.is_empty() {
+        params.push(("collections", settings.collection_id.clone()));
+    }
+    params
+}
+
+/// What a random photo request asks for, for error messages
+pub fn random_photo_resource(params: &[(&str, String)]) -> String {
+    params
+        .iter()
+        .find_map(|(key, value)| match *key {
+            "query" => Some(format!("Photos matching \"{}\"", value)),
+            "collections" => Some(format!("Collection {}", value)),
+            _ => None,
+        })
+        .unwrap_or_else(|| "Random photo".to_string())
+}
+
+/// Length of an Unsplash photo ID, used to split it off the slug in photo page URLs
+const PHOTO_ID_LEN: usize = 11;
+
+/// The photo ID from a bare ID or an unsplash.com photo page link such as
+/// `https://unsplash.com/photos/foggy-mountain-lake-Ab3dE_f9gH1`
+pub fn parse_photo_id(input: &str) -> Option<String> {
+    let input = input.trim();
+    let segment = if input.contains('/') {
+        let path = input.split(['?', '#']).next()?;
+        let mut segments = path.split('/').filter(|segment| !segment.is_empty());
+        // Localized pages use e.g. /de/fotos/
+        segments.find(|segment| matches!(*segment, "photos" | "fotos"))?;
+        segments.next()?
+    } else {
+        input
+    };
+
+    let valid = !segment.is_empty()
+        && segment
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid {
+        return None;
+    }
+    // Page URLs put a description slug in front of the ID
+    if segment.len() > PHOTO_ID_LEN && segment.contains('-') {
+        return Some(segment[segment.len() - PHOTO_ID_LEN..].to_string());
+    }
+    Some(segment.to_string())
+}
+
+/// Append imgix sizing parameters so Unsplash serves an image matching the screen
+/// instead of the full-size original. With `smart_crop`, imgix crops around the
+/// most detailed area rather than the center.
+pub fn sized_image_url(
+    image_url: &str,
+    screen: Option<ScreenResolution>,
+    smart_crop: bool,
+) -> String {
+    let Some(screen) = screen else {
+        return image_url.to_string();
+    };
+
+    // Leave URLs that are already sized (e.g. urls.regular) untouched
+    let already_sized = image_url
+        .split_once('?')
+        .map(|(_, query)| query.split('&').any(|param| param.starts_with("w=")))
+        .unwrap_or(false);
+    if already_sized || screen.width == 0 || screen.height == 0 {
+        return image_url.to_string();
+    }
+
+    let dpr = if screen.scale_factor > 0.0 {
+        screen.scale_factor
+    } else {
+        1.0
+    };
+    let width = (screen.width as f64 / dpr).round() as u32;
+    let height = (screen.height as f64 / dpr).round() as u32;
+    let separator = if image_url.contains('?') { '&' } else { '?' };
+    let crop = if smart_crop { "&crop=entropy" } else { "" };
+
+    format!(
+        "{}{}w={}&h={}&fit=crop{}&dpr={}",
+        image_url, separator, width, height, crop, dpr
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn photo_ids_come_from_ids_and_page_links() {
+        let id = |input| parse_photo_id(input);
+        assert_eq!(id(" Ab3dE_f9gH1 ").as_deref(), Some("Ab3dE_f9gH1"));
+        assert_eq!(id("-Ab3dE_f9gH").as_deref(), Some("-Ab3dE_f9gH"));
+        assert_eq!(
+            id("https://unsplash.com/photos/foggy-mountain-lake-Ab3dE_f9gH1").as_deref(),
+            Some("Ab3dE_f9gH1")
+        );
+        assert_eq!(
+            id("https://unsplash.com/de/fotos/nebliger-see-Ab3dE_f9gH1?utm_source=x#top")
+                .as_deref(),
+            Some("Ab3dE_f9gH1")
+        );
+        assert_eq!(
+            id("unsplash.com/photos/Ab3dE_f9gH1/").as_deref(),
+            Some("Ab3dE_f9gH1")
+        );
+    }
+
+    #[test]
+    fn invalid_photo_ids_are_rejected() {
+        assert_eq!(parse_photo_id(""), None);
+        assert_eq!(parse_photo_id("not an id"), None);
+        assert_eq!(parse_photo_id("https://unsplash.com/@jane"), None);
+        assert_eq!(parse_photo_id("https://unsplash.com/photos/"), None);
+    }
+
+    #[test]
+    fn retry_delays_double_with_jitter_up_to_a_cap() {
+        for (attempt, backoff) in [(1, 500), (2, 1_000), (3, 2_000), (8, 30_000), (40, 30_000)] {
+            for _ in 0..20 {
+                let delay = retry_delay(500, attempt).as_millis() as u64;
+                assert!(
+                    (backoff..=backoff * 3 / 2).contains(&delay),
+                    "attempt {attempt}: {delay}ms"
+                );
+            }
+        }
+    }
+
+    fn response(status: u16, headers: &[(&str, &str)], body: &str) -> reqwest::Response {
+        let mut response = http::Response::builder().status(status);
+        for (name, value) in headers {
+            response = response.header(*name, *value);
+        }
+        response.body(body.to_string()).unwrap().into()
+    }
+
+    async fn error(status: u16, headers: &[(&str, &str)], body: &str) -> WallyError {
+        api_error(response(status, headers, body), "photo").await
+    }
+
+    #[tokio::test]
+    async fn api_errors_are_classified_by_status() {
+        assert!(matches!(
+            error(401, &[], "").await,
+            WallyError::InvalidApiKey
+        ));
+        assert!(matches!(
+            error(404, &[], "").await,
+            WallyError::NotFound { resource } if resource == "photo"
+        ));
+        let body = r#"{"errors": ["Access denied", "Try again"]}"#;
+        assert!(matches!(
+            error(403, &[], body).await,
+            WallyError::Forbidden(message) if message == "Access denied, Try again"
+        ));
+        assert!(matches!(
+            error(500, &[], " Oops ").await,
+            WallyError::Api { status: 500, message } if message == "Oops"
+        ));
+    }
+
+    #[tokio::test]
+    async fn rate_limits_are_recognised() {
+        let before = chrono::Utc::now();
+        let WallyError::RateLimited { reset: Some(reset) } = error(429, &[], "").await else {
+            panic!("429 should be rate limited");
+        };
+        assert!(reset >= before + RATE_LIMIT_WINDOW);
+
+        let exhausted = [("x-ratelimit-remaining", "0"), ("retry-after", "60")];
+        let WallyError::RateLimited { reset: Some(reset) } = error(403, &exhausted, "").await
+        else {
+            panic!("403 without requests left should be rate limited");
+        };
+        assert!(reset < before + chrono::Duration::minutes(2));
+
+        assert!(matches!(
+            error(403, &[], "Rate Limit Exceeded").await,
+            WallyError::RateLimited { .. }
+        ));
+    }
+}
@@ -0,0 +1,138 @@
+//! Upscaling of photos smaller than the screen with Real-ESRGAN, when its ncnn build
+//! is installed, keeping the results so the same photo is only upscaled once
+
+use crate::paths::get_upscale_dir;
+use crate::system::find_in_path;
+use crate::ScreenResolution;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+/// The Real-ESRGAN ncnn Vulkan executable
+pub const UPSCALER: &str = "realesrgan-ncnn-vulkan";
+
+/// How long an upscale gets before it's considered hung; large photos on integrated
+/// graphics take a while
+const UPSCALE_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// Upscaled photos kept around, oldest removed first
+const MAX_CACHED: usize = 20;
+
+/// Factor to upscale an image of `width` x `height` by so it covers the screen,
+/// `None` when it already does. Real-ESRGAN supports 2x to 4x.
+pub fn scale_for(width: u32, height: u32, screen: ScreenResolution) -> Option<u32> {
+    if width == 0 || height == 0 || (width >= screen.width && height >= screen.height) {
+        return None;
+    }
+    let ratio = (f64::from(screen.width) / f64::from(width))
+        .max(f64::from(screen.height) / f64::from(height));
+    Some((ratio.ceil() as u32).clamp(2, 4))
+}
+
+/// Upscale `path` if it's smaller than the screen, returning the upscaled copy.
+/// `None` when it's big enough, the upscaler isn't installed or it failed. Blocking.
+pub fn upscale_for_screen(path: &Path, screen: ScreenResolution) -> Option<PathBuf> {
+    let (width, height) = image::image_dimensions(path).ok()?;
+    let scale = scale_for(width, height, screen)?;
+    let Some(upscaler) = find_in_path(UPSCALER) else {
+        warn!("Upscaling is on but {} isn't installed", UPSCALER);
+        return None;
+    };
+
+    // Downloads are named after the photo, so the name identifies the source
+    let stem = path.file_stem()?.to_string_lossy();
+    let cached = get_upscale_dir().join(format!("{}@{}x.png", stem, scale));
+    if cached.exists() {
+        info!("Using cached {}x upscale of {}", scale, path.display());
+        return Some(cached);
+    }
+
+    info!(
+        "Upscaling {} ({}x{}) {}x for a {}x{} screen",
+        path.display(),
+        width,
+        height,
+        scale,
+        screen.width,
+        screen.height
+    );
+    match run_upscaler(&upscaler, path, &cached, scale) {
+        Ok(()) => {
+            prune_cache();
+            Some(cached)
+        }
+        Err(e) => {
+            warn!("Upscaling failed, using the original: {}", e);
+            let _ = fs::remove_file(&cached);
+            None
+        }
+    }
+}
+
+fn run_upscaler(upscaler: &Path, input: &Path, output: &Path, scale: u32) -> Result<(), String> {
+    let mut command = Command::new(upscaler);
+    command
+        .arg("-i")
+        .arg(input)
+        .arg("-o")
+        .arg(output)
+        .args(["-s", &scale.to_string(), "-f", "png"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    // The models are looked up next to the executable in release archives
+    if let Some(dir) = upscaler.parent() {
+        command.current_dir(dir);
+    }
+    let mut child = command
+        .spawn()
+        .map_err(|e| format!("Failed to start {}: {}", UPSCALER, e))?;
+
+    let deadline = Instant::now() + UPSCALE_TIMEOUT;
+    let status = loop {
+        if let Some(status) = child.try_wait().map_err(|e| e.to_string())? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(format!(
+                "{} did not finish within {}s",
+                UPSCALER,
+                UPSCALE_TIMEOUT.as_secs()
+            ));
+        }
+        std::thread::sleep(Duration::from_millis(200));
+    };
+
+    if !status.success() {
+        return Err(format!("{} exited with {}", UPSCALER, status));
+    }
+    if !output.exists() {
+        return Err(format!("{} wrote no output", UPSCALER));
+    }
+    Ok(())
+}
+
+/// Remove the oldest upscaled photos beyond `MAX_CACHED`
+fn prune_cache() {
+    let Ok(entries) = fs::read_dir(get_upscale_dir()) else {
+        return;
+    };
+    let mut files: Vec<(PathBuf, std::time::SystemTime)> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let modified = entry.metadata().ok()?.modified().ok()?;
+            Some((entry.path(), modified))
+        })
+        .collect();
+    if files.len() <= MAX_CACHED {
+        return;
+    }
+    files.sort_by_key(|(_, modified)| std::cmp::Reverse(*modified));
+    for (path, _) in files.into_iter().skip(MAX_CACHED) {
+        let _ = fs::remove_file(path);
+    }
+}
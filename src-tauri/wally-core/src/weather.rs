@@ -0,0 +1,137 @@
+//! Season and current weather keywords for the photo search, with the weather from
+//! Open-Meteo cached so every change doesn't query it
+
+use crate::unsplash::send_with_retry;
+use crate::WallpaperSettings;
+use chrono::{DateTime, Datelike, Local, Utc};
+use serde::Deserialize;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+/// How long a weather report is used before Open-Meteo is asked again
+const WEATHER_TTL: Duration = Duration::from_secs(30 * 60);
+
+struct CachedWeather {
+    location: (f64, f64),
+    keyword: Option<&'static str>,
+    fetched_at: Instant,
+}
+
+static WEATHER: Mutex<Option<CachedWeather>> = Mutex::new(None);
+
+#[derive(Debug, Deserialize)]
+struct Forecast {
+    current: CurrentWeather,
+}
+
+#[derive(Debug, Deserialize)]
+struct CurrentWeather {
+    weather_code: u8,
+}
+
+/// Search keyword for a WMO weather code, `None` for unremarkable weather
+fn weather_keyword(code: u8) -> Option<&'static str> {
+    match code {
+        0 | 1 => Some("sunny"),
+        45 | 48 => Some("fog"),
+        51..=67 | 80..=82 => Some("rain"),
+        71..=77 | 85 | 86 => Some("snow"),
+        95..=99 => Some("storm"),
+        _ => None,
+    }
+}
+
+/// Meteorological season at `now`, flipped on the southern hemisphere
+pub fn season(now: DateTime<Utc>, latitude: Option<f64>) -> &'static str {
+    const SEASONS: [&str; 4] = ["winter", "spring", "summer", "autumn"];
+    let month = now.with_timezone(&Local).month();
+    let index = (month % 12 / 3) as usize;
+    if latitude.is_some_and(|latitude| latitude < 0.0) {
+        SEASONS[(index + 2) % 4]
+    } else {
+        SEASONS[index]
+    }
+}
+
+/// The configured coordinates, weather needs them set explicitly
+fn location(settings: &WallpaperSettings) -> Option<(f64, f64)> {
+    Some((settings.sun_latitude?, settings.sun_longitude?))
+}
+
+/// Fetch the current weather when weather-aware selection is on and the cached
+/// report is missing, stale or for other coordinates
+pub async fn refresh(client: &reqwest::Client, settings: &WallpaperSettings) {
+    if !settings.weather_aware {
+        return;
+    }
+    let Some(location) = location(settings) else {
+        return;
+    };
+    let fresh = WEATHER.lock().ok().is_some_and(|cached| {
+        cached.as_ref().is_some_and(|cached| {
+            cached.location == location && cached.fetched_at.elapsed() < WEATHER_TTL
+        })
+    });
+    if fresh {
+        return;
+    }
+
+    let request = client
+        .get("https://api.open-meteo.com/v1/forecast")
+        .query(&[
+            ("latitude", location.0.to_string()),
+            ("longitude", location.1.to_string()),
+            ("current", "weather_code".to_string()),
+        ]);
+    let forecast: Result<Forecast, reqwest::Error> = async {
+        send_with_retry(request, settings)
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+    }
+    .await;
+
+    let keyword = match forecast {
+        Ok(forecast) => {
+            let keyword = weather_keyword(forecast.current.weather_code);
+            info!(
+                "Weather code {} ({})",
+                forecast.current.weather_code,
+                keyword.unwrap_or("no keyword")
+            );
+            keyword
+        }
+        Err(e) => {
+            warn!("Failed to fetch the weather: {}", e);
+            None
+        }
+    };
+    // Failures are cached too, so an outage doesn't add a request to every change
+    if let Ok(mut cached) = WEATHER.lock() {
+        *cached = Some(CachedWeather {
+            location,
+            keyword,
+            fetched_at: Instant::now(),
+        });
+    }
+}
+
+/// Season and weather keywords to add to the search, empty when both are off
+pub fn conditions_query(settings: &WallpaperSettings, now: DateTime<Utc>) -> String {
+    let mut keywords = Vec::new();
+    if settings.season_aware {
+        keywords.push(season(now, settings.sun_latitude));
+    }
+    if settings.weather_aware {
+        let keyword = WEATHER.lock().ok().and_then(|cached| {
+            cached
+                .as_ref()
+                .filter(|cached| Some(cached.location) == location(settings))
+                .and_then(|cached| cached.keyword)
+        });
+        keywords.extend(keyword);
+    }
+    keywords.join(" ")
+}
@@ -33,7 +33,7 @@ export function useAutoWallpaper(onWallpaperChanged?: () => void) {
       }
 
       const image = await fetchRandomImage();
-      const path = await setWallpaper(image.urls.full, image.id);
+      const path = await setWallpaper(image.urls.full, image.id, image);
       await saveCurrentWallpaper(image, path);
       await triggerDownload(image.links.download_location);
       onWallpaperChanged?.();
@@ -2,18 +2,209 @@ import { invoke } from "@tauri-apps/api/core";
 
 export type IntervalUnit = "minutes" | "hours" | "days" | "weeks";
 
+export type DownloadQuality = "raw" | "full" | "regular" | "small";
+
+export type ScheduleMode = "interval" | "times" | "cron" | "sun" | "daily";
+
+export type BatteryPolicy = "ignore" | "skip" | "small";
+
+export type MeteredPolicy = "pause" | "small";
+
+export type ColorFilter =
+  | ""
+  | "black_and_white"
+  | "black"
+  | "white"
+  | "yellow"
+  | "orange"
+  | "red"
+  | "purple"
+  | "magenta"
+  | "green"
+  | "teal"
+  | "blue";
+
+export type IdleChangeMode = "off" | "idle" | "return";
+
+export type RotationOrder = "random" | "sequential" | "shuffle";
+
+export type SlideshowOrder = "alphabetical" | "shuffle" | "date";
+
+export type UpdateChannel = "stable" | "beta";
+
+export type CreditFormat = "plain" | "markdown" | "html";
+
+export type ShareContent = "link" | "file";
+
+export type WallyErrorCode =
+  | "api_key_missing"
+  | "invalid_api_key"
+  | "rate_limited"
+  | "not_found"
+  | "forbidden"
+  | "api_error"
+  | "unsupported_desktop"
+  | "download_failed"
+  | "cancelled"
+  | "insufficient_space"
+  | "backend_timeout"
+  | "io"
+  | "network"
+  | "invalid_response"
+  | "other";
+
+export interface WallyError {
+  code: WallyErrorCode;
+  message: string;
+  reset: string | null;
+}
+
+export function isWallyError(err: unknown): err is WallyError {
+  return typeof err === "object" && err !== null && "code" in err && "message" in err;
+}
+
+/** Readable message for an error thrown by a command */
+export function errorMessage(err: unknown, fallback: string): string {
+  if (isWallyError(err)) return err.message;
+  if (err instanceof Error) return err.message;
+  if (typeof err === "string") return err;
+  return fallback;
+}
+
+export interface KeywordPeriod {
+  start: string;
+  keywords: string;
+}
+
+export interface Playlist {
+  name: string;
+  enabled: boolean;
+  /** e.g. "Mon-Fri", "09:00-17:00" or "Sat,Sun 08:00-20:00", empty for always */
+  schedule: string;
+  collection_id: string;
+  query: string;
+}
+
+export interface HolidayTheme {
+  name: string;
+  enabled: boolean;
+  /** "MM-DD" */
+  start: string;
+  /** "MM-DD", before start for ranges spanning New Year */
+  end: string;
+  query: string;
+  collection_id: string;
+}
+
 export interface WallpaperSettings {
+  schema_version: number;
   api_key: string;
   collection_id: string;
   interval_value: number;
   interval_unit: IntervalUnit;
   auto_change: boolean;
+  launch_at_login: boolean;
+  start_minimized: boolean;
+  close_to_tray: boolean;
+  hide_dock_icon: boolean;
+  check_for_updates: boolean;
+  update_channel: UpdateChannel;
+  crash_reports: boolean;
+  usage_metrics: boolean;
+  usage_metrics_url: string;
+  apply_to_all_spaces: boolean;
+  live_wallpaper_renderer: boolean;
+  quality: DownloadQuality;
+  retry_attempts: number;
+  retry_base_delay_ms: number;
+  connect_timeout_secs: number;
+  read_timeout_secs: number;
+  proxy_url: string;
+  proxy_username: string;
+  proxy_password: string;
+  use_system_proxy: boolean;
+  resize_to_screen: boolean;
+  smart_crop: boolean;
+  upscale_low_resolution: boolean;
+  attribution_overlay: boolean;
+  attribution_corner: "top-left" | "top-right" | "bottom-left" | "bottom-right";
+  attribution_font_size: number;
+  attribution_opacity: number;
+  blur_radius: number;
+  dim_percent: number;
+  vignette: number;
+  grayscale: boolean;
+  tint_color: string;
+  tint_strength: number;
+  dark_mode_adjust: boolean;
+  dark_mode_dim_percent: number;
+  dark_mode_contrast: number;
+  pywal_integration: boolean;
+  sync_accent_color: boolean;
+  hooks: string[];
+  hook_timeout_secs: number;
+  webhook_url: string;
+  http_api_enabled: boolean;
+  http_api_port: number;
+  http_api_token: string;
+  notify_on_change: boolean;
+  shortcut_next: string;
+  shortcut_previous: string;
+  shortcut_pause: string;
+  schedule_mode: ScheduleMode;
+  schedule_times: string[];
+  schedule_cron: string;
+  sun_latitude: number | null;
+  sun_longitude: number | null;
+  day_collection_id: string;
+  night_collection_id: string;
+  day_query: string;
+  night_query: string;
+  time_of_day_theming: boolean;
+  keyword_periods: KeywordPeriod[];
+  playlists: Playlist[];
+  holiday_themes: HolidayTheme[];
+  season_aware: boolean;
+  weather_aware: boolean;
+  change_on_unlock: boolean;
+  change_on_login: boolean;
+  schedule_jitter_minutes: number;
+  quiet_hours: string[];
+  pause_when_presenting: boolean;
+  idle_change_mode: IdleChangeMode;
+  idle_minutes: number;
+  battery_policy: BatteryPolicy;
+  battery_threshold: number;
+  metered_policy: MeteredPolicy;
+  ignore_metered: boolean;
+  prefetch_next: boolean;
+  cleanup_keep_count: number;
+  cleanup_max_size_mb: number;
+  cleanup_on_low_space: boolean;
+  filename_template: string;
+  color_filter: ColorFilter;
+  min_resolution_filter: boolean;
+  max_aspect_deviation_percent: number;
+  skip_similar: boolean;
+  collage_enabled: boolean;
+  collage_photos: number;
+  collage_gap: number;
+  collage_background: string;
+  rotation_order: RotationOrder;
+  rotation_seed: number;
+  slideshow_enabled: boolean;
+  slideshow_folder: string;
+  slideshow_interval_minutes: number;
+  slideshow_order: SlideshowOrder;
 }
 
 export interface UnsplashImage {
   id: string;
   description: string | null;
   alt_description: string | null;
+  width: number;
+  height: number;
+  color: string | null;
   urls: {
     raw: string;
     full: string;
@@ -32,22 +223,259 @@ export interface UnsplashImage {
   };
 }
 
+export interface UnsplashCollection {
+  id: string;
+  title: string;
+  description: string | null;
+  total_photos: number;
+  user: {
+    name: string;
+    username: string;
+  };
+  cover_photo: { urls: UnsplashImage["urls"] } | null;
+  links: {
+    html: string;
+  };
+}
+
 export interface CurrentWallpaper {
   image: UnsplashImage | null;
   local_path: string | null;
   set_at: string | null;
+  palette: string[];
+  phash: string | null;
+}
+
+export interface CachedWallpaper {
+  local_path: string;
+  /** Load with `convertFileSrc` */
+  thumbnail_path: string | null;
+  size_bytes: number;
+  modified_at: string | null;
+  pinned: boolean;
+  current: boolean;
+  image: UnsplashImage | null;
+  set_at: string | null;
+  palette: string[];
+}
+
+export interface PinStatus {
+  pinned: boolean;
+  until: string | null;
+}
+
+export type LogLevel = "error" | "warn" | "info" | "debug" | "trace";
+
+export interface LogEntry {
+  timestamp: string;
+  level: string;
+  target: string;
+  message: string;
+}
+
+/** A newer version found by `checkForUpdates` */
+export interface AvailableUpdate {
+  version: string;
+  current_version: string;
+  notes: string | null;
+  date: string | null;
+}
+
+/** Counts for one day of `UsageSummary` */
+export interface DayUsage {
+  changes: number;
+  backends: Record<string, number>;
+  failures: Record<string, number>;
+}
+
+/** Usage counted locally while usage metrics are on, keyed by "YYYY-MM-DD" */
+export interface UsageSummary {
+  changes: number;
+  backends: Record<string, number>;
+  failures: Record<string, number>;
+  days: Record<string, DayUsage>;
+}
+
+/** A panic recorded while crash reports were on */
+export interface CrashReport {
+  timestamp: string;
+  app_version: string;
+  os: string;
+  arch: string;
+  desktop: string | null;
+  thread: string | null;
+  message: string;
+  location: string | null;
+  backtrace: string;
+}
+
+export interface DiagnosticsReport {
+  app_version: string;
+  platform: string;
+  os: string;
+  arch: string;
+  desktop: {
+    xdg_current_desktop: string | null;
+    xdg_session_type: string | null;
+    kde: boolean;
+    gnome: boolean;
+  };
+  portable: boolean;
+  tools: { name: string; path: string | null }[];
+  config_dir: DirCheck;
+  wallpaper_dir: DirCheck;
+  network: {
+    reachable: boolean;
+    status: number | null;
+    latency_ms: number | null;
+    error: string | null;
+  };
+  settings_problems: string[];
+}
+
+export interface DirCheck {
+  path: string;
+  writable: boolean;
+  error: string | null;
+}
+
+export interface BackendTestResult {
+  backend: string;
+  set_ok: boolean;
+  error: string | null;
+  verified: boolean | null;
+  restored_path: string | null;
+  restore_error: string | null;
+}
+
+export interface KdeActivity {
+  id: string;
+  name: string;
+  current: boolean;
 }
 
 export async function fetchRandomImage(): Promise<UnsplashImage> {
   return invoke("fetch_random_image");
 }
 
-export async function setWallpaper(imageUrl: string, imageId: string): Promise<string> {
-  return invoke("set_wallpaper", { imageUrl, imageId });
+export async function getPhoto(photoId: string): Promise<UnsplashImage> {
+  return invoke("get_photo", { photoId });
+}
+
+/** Look up a collection, e.g. to validate an ID before saving it */
+export async function getCollectionInfo(id: string): Promise<UnsplashCollection> {
+  return invoke("get_collection_info", { id });
+}
+
+/** A page of a collection's photos, starting at 1 */
+export async function listCollectionPhotos(id: string, page = 1): Promise<UnsplashImage[]> {
+  return invoke("list_collection_photos", { id, page });
+}
+
+export async function searchCollections(query: string): Promise<UnsplashCollection[]> {
+  return invoke("search_collections", { query });
+}
+
+/** Add a playlist or replace the one with the same name */
+export async function savePlaylist(playlist: Playlist): Promise<Playlist[]> {
+  return invoke("save_playlist", { playlist });
+}
+
+export async function deletePlaylist(name: string): Promise<Playlist[]> {
+  return invoke("delete_playlist", { name });
+}
+
+/** Name of the playlist currently in use, if any */
+export async function getActivePlaylist(): Promise<string | null> {
+  return invoke("get_active_playlist");
+}
+
+export async function setWallpaper(
+  imageUrl: string,
+  imageId: string,
+  image?: UnsplashImage
+): Promise<string> {
+  return invoke("set_wallpaper", { imageUrl, imageId, image });
+}
+
+export async function setWallpaperById(photoId: string): Promise<CurrentWallpaper> {
+  return invoke("set_wallpaper_by_id", { photoId });
+}
+
+/** Set a photo from its ID or a link to its page on unsplash.com */
+export async function setWallpaperByPhotoId(idOrUrl: string): Promise<CurrentWallpaper> {
+  return invoke("set_wallpaper_by_photo_id", { idOrUrl });
+}
+
+/** Reverts after `revertAfterSecs` (30 by default) unless confirmed */
+export async function previewWallpaper(
+  image: UnsplashImage,
+  revertAfterSecs?: number,
+): Promise<CurrentWallpaper> {
+  return invoke("preview_wallpaper", { image, revertAfterSecs });
+}
+
+export async function confirmPreview(): Promise<void> {
+  return invoke("confirm_preview");
+}
+
+export async function revertPreview(): Promise<void> {
+  return invoke("revert_preview");
+}
+
+export interface MonitorInfo {
+  name: string | null;
+  width: number;
+  height: number;
+  x: number;
+  y: number;
+  scale_factor: number;
+  primary: boolean;
+}
+
+export async function getMonitors(): Promise<MonitorInfo[]> {
+  return invoke("get_monitors");
 }
 
-export async function downloadImage(imageUrl: string, filename: string): Promise<string> {
-  return invoke("download_image", { imageUrl, filename });
+export interface FullscreenPreview {
+  url: string;
+  image: UnsplashImage;
+}
+
+/** Show a photo full-screen on a monitor, by name, without setting it */
+export async function openFullscreenPreview(
+  image: UnsplashImage,
+  monitor?: string,
+): Promise<void> {
+  return invoke("open_fullscreen_preview", { image, monitor });
+}
+
+export async function getFullscreenPreview(): Promise<FullscreenPreview | null> {
+  return invoke("get_fullscreen_preview");
+}
+
+export async function closeFullscreenPreview(): Promise<void> {
+  return invoke("close_fullscreen_preview");
+}
+
+export async function setWallpaperFromPath(path: string): Promise<CurrentWallpaper> {
+  return invoke("set_wallpaper_from_path", { path });
+}
+
+export async function setWallpaperFromClipboard(): Promise<CurrentWallpaper> {
+  return invoke("set_wallpaper_from_clipboard");
+}
+
+export async function downloadImage(
+  imageUrl: string,
+  filename: string,
+  image?: UnsplashImage
+): Promise<string> {
+  return invoke("download_image", { imageUrl, filename, image });
+}
+
+export async function cancelDownload(): Promise<void> {
+  return invoke("cancel_download");
 }
 
 export async function getSettings(): Promise<WallpaperSettings> {
@@ -58,6 +486,27 @@ export async function saveSettings(settings: WallpaperSettings): Promise<void> {
   return invoke("save_settings", { settings });
 }
 
+export async function setAutostart(enabled: boolean): Promise<void> {
+  return invoke("set_autostart", { enabled });
+}
+
+export async function quitApp(): Promise<void> {
+  return invoke("quit_app");
+}
+
+/** Replace the HTTP API token, returning the new one */
+export async function regenerateHttpApiToken(): Promise<string> {
+  return invoke("regenerate_http_api_token");
+}
+
+export async function exportConfig(path: string): Promise<void> {
+  return invoke("export_config", { path });
+}
+
+export async function importConfig(path: string): Promise<WallpaperSettings> {
+  return invoke("import_config", { path });
+}
+
 export async function getCurrentWallpaper(): Promise<CurrentWallpaper> {
   return invoke("get_current_wallpaper");
 }
@@ -70,10 +519,43 @@ export async function triggerDownload(downloadLocation: string): Promise<void> {
   return invoke("trigger_download", { downloadLocation });
 }
 
-export async function getPlatform(): Promise<string> {
+export interface PlatformInfo {
+  /** Wallpaper backend: "macos", "windows", "linux-kde", "linux-gnome" or "linux" */
+  backend: string;
+  /** Whether videos and animated images can be set with `setLiveWallpaper` */
+  live_wallpaper: boolean;
+}
+
+export async function getPlatform(): Promise<PlatformInfo> {
   return invoke("get_platform");
 }
 
+export interface LiveWallpaper {
+  path: string;
+  video: boolean;
+}
+
+/** Play a video or animated image as the wallpaper until a still one replaces it */
+export async function setLiveWallpaper(path: string): Promise<void> {
+  return invoke("set_live_wallpaper", { path });
+}
+
+export async function stopLiveWallpaper(): Promise<void> {
+  return invoke("stop_live_wallpaper");
+}
+
+export async function getLiveWallpaper(): Promise<LiveWallpaper | null> {
+  return invoke("get_live_wallpaper");
+}
+
+export async function listKdeActivities(): Promise<KdeActivity[]> {
+  return invoke("list_kde_activities");
+}
+
+export async function assignWallpaperToActivity(activityId: string): Promise<void> {
+  return invoke("assign_wallpaper_to_activity", { activityId });
+}
+
 export async function startAutoChange(): Promise<void> {
   return invoke("start_auto_change");
 }
@@ -82,10 +564,132 @@ export async function stopAutoChange(): Promise<void> {
   return invoke("stop_auto_change");
 }
 
-export async function getDaemonStatus(): Promise<boolean> {
-  return invoke("get_daemon_status");
+export async function previousWallpaper(): Promise<void> {
+  return invoke("previous_wallpaper");
+}
+
+export async function getWallpaperHistory(): Promise<CurrentWallpaper[]> {
+  return invoke("get_wallpaper_history");
+}
+
+/** Queue a photo to be shown before random ones, at `position` or at the end */
+export async function enqueueImage(image: UnsplashImage, position?: number): Promise<UnsplashImage[]> {
+  return invoke("enqueue_image", { image, position });
+}
+
+export async function listQueue(): Promise<UnsplashImage[]> {
+  return invoke("list_queue");
+}
+
+/** Order the queue by photo ID, dropping queued photos that are left out */
+export async function reorderQueue(photoIds: string[]): Promise<UnsplashImage[]> {
+  return invoke("reorder_queue", { photoIds });
+}
+
+export async function setRotationPaused(paused: boolean): Promise<void> {
+  return invoke("set_rotation_paused", { paused });
+}
+
+export async function getRotationPaused(): Promise<boolean> {
+  return invoke("get_rotation_paused");
+}
+
+/** Keep the current wallpaper, until an ISO 8601 time if given */
+export async function pinWallpaper(until?: string): Promise<PinStatus> {
+  return invoke("pin_wallpaper", { until });
+}
+
+export async function unpinWallpaper(): Promise<void> {
+  return invoke("unpin_wallpaper");
+}
+
+export async function getWallpaperPin(): Promise<PinStatus> {
+  return invoke("get_wallpaper_pin");
+}
+
+export async function getNextChangeTime(): Promise<string | null> {
+  return invoke("get_next_change_time");
+}
+
+export async function listCachedWallpapers(): Promise<CachedWallpaper[]> {
+  return invoke("list_cached_wallpapers");
+}
+
+export async function getPinnedWallpapers(): Promise<string[]> {
+  return invoke("get_pinned_wallpapers");
+}
+
+export async function setWallpaperPinned(
+  localPath: string,
+  pinned: boolean
+): Promise<void> {
+  return invoke("set_wallpaper_pinned", { localPath, pinned });
+}
+
+export interface DaemonState {
+  running: boolean;
+  paused: boolean;
+  last_change_at: string | null;
+  last_error: { code: WallyErrorCode; reason: string } | null;
+  next_change_at: string | null;
+  source: string;
+  rate_limit_remaining: number | null;
+}
+
+export async function getDaemonState(): Promise<DaemonState> {
+  return invoke("get_daemon_state");
 }
 
 export async function openUrl(url: string): Promise<void> {
   return invoke("open_url", { url });
 }
+
+export async function getRecentLogs(level?: LogLevel, limit?: number): Promise<LogEntry[]> {
+  return invoke("get_recent_logs", { level, limit });
+}
+
+export async function openLogFolder(): Promise<void> {
+  return invoke("open_log_folder");
+}
+
+export async function revealCurrentWallpaper(): Promise<void> {
+  return invoke("reveal_current_wallpaper");
+}
+
+/** Copy the current photo's credit line to the clipboard, returning it */
+export async function copyAttribution(format: CreditFormat): Promise<string> {
+  return invoke("copy_attribution", { format });
+}
+
+export async function shareCurrentWallpaper(content?: ShareContent): Promise<void> {
+  return invoke("share_current_wallpaper", { content });
+}
+
+export async function runDiagnostics(): Promise<DiagnosticsReport> {
+  return invoke("run_diagnostics");
+}
+
+export async function getLastCrashReport(): Promise<CrashReport | null> {
+  return invoke("get_last_crash_report");
+}
+
+export async function getUsageSummary(): Promise<UsageSummary> {
+  return invoke("get_usage_summary");
+}
+
+export async function clearUsageMetrics(): Promise<void> {
+  return invoke("clear_usage_metrics");
+}
+
+export async function checkForUpdates(): Promise<AvailableUpdate | null> {
+  return invoke("check_for_updates");
+}
+
+/** Installs the update found by the last check and restarts the app */
+export async function installUpdate(): Promise<void> {
+  return invoke("install_update");
+}
+
+export async function testWallpaperBackend(): Promise<BackendTestResult> {
+  return invoke("test_wallpaper_backend");
+}
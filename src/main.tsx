@@ -10,6 +10,8 @@ import {
 import "./index.css";
 import { HomePage } from "./pages/Home";
 import { SettingsPage } from "./pages/Settings";
+import { PreviewPage } from "./pages/Preview";
+import { LivePage } from "./pages/Live";
 
 const rootRoute = createRootRoute({
   component: () => <Outlet />,
@@ -27,7 +29,19 @@ const settingsRoute = createRoute({
   component: SettingsPage,
 });
 
-const routeTree = rootRoute.addChildren([indexRoute, settingsRoute]);
+const previewRoute = createRoute({
+  getParentRoute: () => rootRoute,
+  path: "/preview",
+  component: PreviewPage,
+});
+
+const liveRoute = createRoute({
+  getParentRoute: () => rootRoute,
+  path: "/live",
+  component: LivePage,
+});
+
+const routeTree = rootRoute.addChildren([indexRoute, settingsRoute, previewRoute, liveRoute]);
 
 const router = createRouter({ routeTree });
 
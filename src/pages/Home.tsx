@@ -26,8 +26,9 @@ import {
   saveCurrentWallpaper,
   triggerDownload,
   getSettings,
-  getDaemonStatus,
+  getDaemonState,
   openUrl,
+  errorMessage,
   type UnsplashImage,
   type WallpaperSettings,
 } from "@/lib/wallpaper";
@@ -65,17 +66,17 @@ export function HomePage() {
 
   async function loadInitialData() {
     try {
-      const [wallpaper, settingsData, daemonStatus] = await Promise.all([
+      const [wallpaper, settingsData, daemonState] = await Promise.all([
         getCurrentWallpaper(),
         getSettings(),
-        getDaemonStatus(),
+        getDaemonState(),
       ]);
       if (wallpaper.image) {
         setCurrentImage(wallpaper.image);
         setLocalPath(wallpaper.local_path);
       }
       setSettings(settingsData);
-      setDaemonRunning(daemonStatus);
+      setDaemonRunning(daemonState.running);
     } catch (err) {
       console.error("Failed to load initial data:", err);
     }
@@ -92,7 +93,7 @@ export function HomePage() {
       const image = await fetchRandomImage();
       setPreviewImage(image);
     } catch (err) {
-      setError(err instanceof Error ? err.message : "Failed to fetch image");
+      setError(errorMessage(err, "Failed to fetch image"));
     } finally {
       setIsLoading(false);
     }
@@ -103,14 +104,14 @@ export function HomePage() {
     setIsSettingWallpaper(true);
     setError(null);
     try {
-      const path = await setWallpaper(displayImage.urls.full, displayImage.id);
+      const path = await setWallpaper(displayImage.urls.full, displayImage.id, displayImage);
       await saveCurrentWallpaper(displayImage, path);
       await triggerDownload(displayImage.links.download_location);
       setCurrentImage(displayImage);
       setPreviewImage(null);
       setLocalPath(path);
     } catch (err) {
-      setError(err instanceof Error ? err.message : "Failed to set wallpaper");
+      setError(errorMessage(err, "Failed to set wallpaper"));
     } finally {
       setIsSettingWallpaper(false);
     }
@@ -121,10 +122,14 @@ export function HomePage() {
     setIsDownloading(true);
     setError(null);
     try {
-      await downloadImage(displayImage.urls.full, `unsplash-${displayImage.id}.jpg`);
+      await downloadImage(
+        displayImage.urls.full,
+        `unsplash-${displayImage.id}.jpg`,
+        displayImage
+      );
       await triggerDownload(displayImage.links.download_location);
     } catch (err) {
-      setError(err instanceof Error ? err.message : "Failed to download image");
+      setError(errorMessage(err, "Failed to download image"));
     } finally {
       setIsDownloading(false);
     }
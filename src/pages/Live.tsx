@@ -0,0 +1,25 @@
+import { useState, useEffect } from "react";
+import { convertFileSrc } from "@tauri-apps/api/core";
+import { getLiveWallpaper, type LiveWallpaper } from "@/lib/wallpaper";
+
+/** Contents of the window that plays the live wallpaper behind the desktop icons */
+export function LivePage() {
+  const [live, setLive] = useState<LiveWallpaper | null>(null);
+
+  useEffect(() => {
+    getLiveWallpaper().then(setLive);
+  }, []);
+
+  if (!live) return <div className="fixed inset-0 bg-black" />;
+
+  const src = convertFileSrc(live.path);
+  return (
+    <div className="fixed inset-0 bg-black">
+      {live.video ? (
+        <video src={src} autoPlay loop muted playsInline className="h-full w-full object-cover" />
+      ) : (
+        <img src={src} alt="" className="h-full w-full object-cover" />
+      )}
+    </div>
+  );
+}
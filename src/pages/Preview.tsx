@@ -0,0 +1,41 @@
+import { useState, useEffect } from "react";
+import {
+  getFullscreenPreview,
+  closeFullscreenPreview,
+  type FullscreenPreview,
+} from "@/lib/wallpaper";
+
+/** Contents of the full-screen preview window, closed by a click or Escape */
+export function PreviewPage() {
+  const [preview, setPreview] = useState<FullscreenPreview | null>(null);
+
+  useEffect(() => {
+    getFullscreenPreview().then(setPreview);
+
+    const handleKeyDown = (e: KeyboardEvent) => {
+      if (e.key === "Escape") closeFullscreenPreview();
+    };
+    window.addEventListener("keydown", handleKeyDown);
+    return () => window.removeEventListener("keydown", handleKeyDown);
+  }, []);
+
+  return (
+    <div
+      onClick={() => closeFullscreenPreview()}
+      className="fixed inset-0 bg-black cursor-default select-none"
+    >
+      {preview && (
+        <>
+          <img
+            src={preview.url}
+            alt={preview.image.alt_description ?? ""}
+            className="h-full w-full object-cover"
+          />
+          <div className="absolute bottom-4 right-4 rounded-md bg-black/50 px-3 py-1.5 text-xs text-white/80">
+            Photo by {preview.image.user.name} on Unsplash · Esc to close
+          </div>
+        </>
+      )}
+    </div>
+  );
+}
@@ -1,5 +1,6 @@
 import { useState, useEffect } from "react";
 import { useNavigate } from "@tanstack/react-router";
+import { listen } from "@tauri-apps/api/event";
 import { ArrowLeft, Save, Loader2, ExternalLink, Check } from "lucide-react";
 import { TitleBar } from "@/components/TitleBar";
 import { Logo } from "@/components/Logo";
@@ -53,7 +54,7 @@ const COLLECTION_PRESETS = [
 
 export function SettingsPage() {
   const navigate = useNavigate();
-  const [, setSettings] = useState<WallpaperSettings | null>(null);
+  const [settings, setSettings] = useState<WallpaperSettings | null>(null);
   const [platform, setPlatform] = useState<string>("");
   const [isSaving, setIsSaving] = useState(false);
   const [hasChanges, setHasChanges] = useState(false);
@@ -68,6 +69,18 @@ export function SettingsPage() {
     loadSettings();
   }, []);
 
+  // Pick up edits made to settings.json outside the app, unless the form has unsaved changes
+  useEffect(() => {
+    if (hasChanges) return;
+    const unlisten = listen("settings-changed", () => {
+      loadSettings();
+    });
+
+    return () => {
+      unlisten.then((fn) => fn());
+    };
+  }, [hasChanges]);
+
   async function loadSettings() {
     try {
       const [settingsData, platformData] = await Promise.all([
@@ -75,7 +88,7 @@ export function SettingsPage() {
         getPlatform(),
       ]);
       setSettings(settingsData);
-      setPlatform(platformData);
+      setPlatform(platformData.backend);
       setApiKey(settingsData.api_key);
 
       // Check if the collection ID matches a preset
@@ -132,6 +145,7 @@ export function SettingsPage() {
     const intervalUnit = unit as IntervalUnit;
 
     const newSettings: WallpaperSettings = {
+      ...(settings as WallpaperSettings),
       api_key: apiKey,
       collection_id: getEffectiveCollectionId(),
       interval_value: intervalValue,